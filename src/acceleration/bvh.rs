@@ -46,28 +46,44 @@ impl Branch for Node {
   }
 }
 
-pub struct BVH<'a> {
-  list: Vec<Object<'a>>,
+/**
+ * AABBの列に対するBVHの木構造
+ *
+ * 葉は元の列のインデックスを持つ
+ */
+pub struct Tree {
   root: Box<dyn Branch + Sync + Send>,
 }
 
-impl<'a> BVH<'a> {
-  pub fn new(objects: Vec<Object<'a>>) -> Self {
-    let mut leaf = objects
-      .iter()
+impl Tree {
+  pub fn new(aabbs: Vec<AABB>) -> Self {
+    debug_assert!(!aabbs.is_empty());
+    let mut leaf = aabbs
+      .into_iter()
       .enumerate()
-      .map(|(i, v)| Leaf {
-        aabb: v.geometry.aabb().clone(),
+      .map(|(i, aabb)| Leaf {
+        aabb: aabb,
         index: i,
       })
       .collect::<Vec<_>>();
-    let root = Self::build(&mut leaf);
-    BVH {
-      list: objects,
-      root: root,
+    Tree {
+      root: Self::build(&mut leaf),
     }
   }
 
+  pub fn aabb(&self) -> &AABB {
+    self.root.aabb()
+  }
+
+  /**
+   * レイと交差する可能性のある葉のインデックス
+   */
+  pub fn candidates(&self, ray: &Ray) -> Vec<usize> {
+    let mut candidate = Vec::new();
+    self.root.may_intersect(ray, &mut candidate);
+    candidate
+  }
+
   fn build(list: &mut [Leaf]) -> Box<dyn Branch + Sync + Send> {
     // TODO
     let t_aabb = 1.0;
@@ -139,11 +155,26 @@ impl<'a> BVH<'a> {
   }
 }
 
+pub struct BVH<'a> {
+  list: Vec<Object<'a>>,
+  tree: Tree,
+}
+
+impl<'a> BVH<'a> {
+  pub fn new(objects: Vec<Object<'a>>) -> Self {
+    let tree = Tree::new(objects.iter().map(|v| v.aabb().clone()).collect());
+    BVH {
+      list: objects,
+      tree: tree,
+    }
+  }
+}
+
 impl<'a> Interact for BVH<'a> {
   fn interact(&self, ray: Ray) -> Option<Interaction> {
-    let mut candidate = Vec::new();
-    self.root.may_intersect(&ray, &mut candidate);
-    candidate
+    self
+      .tree
+      .candidates(&ray)
       .iter()
       .flat_map(|&i| self.list[i].interact(ray))
       .min()
@@ -3,6 +3,7 @@ mod linear;
 
 pub use self::bvh::*;
 pub use self::linear::*;
+use environment::Environment;
 use object::Interact;
 use object::{LightSampler, Object};
 
@@ -11,14 +12,20 @@ pub trait Acceleration: Interact {
 }
 
 pub trait AccelerationUtility: Acceleration {
-  fn light_sampler(&self) -> LightSampler;
+  fn light_sampler<'a>(
+    &'a self,
+    environment: &'a (dyn Environment + Send + Sync),
+  ) -> LightSampler<'a>;
 }
 
 impl<T> AccelerationUtility for T
 where
   T: Acceleration,
 {
-  fn light_sampler(&self) -> LightSampler {
-    LightSampler::new(self.objects())
+  fn light_sampler<'a>(
+    &'a self,
+    environment: &'a (dyn Environment + Send + Sync),
+  ) -> LightSampler<'a> {
+    LightSampler::new(self.objects(), &[], environment)
   }
 }
@@ -1,10 +1,30 @@
+use math::Vector3;
 use object::Transform;
 use ray::Ray;
 use sample::pdf;
 use sample::Sample;
 
+/**
+ * 点からカメラへの接続
+ */
+pub struct Importance {
+  // フィルム上の座標 ((0, 0) が左下)
+  pub uv: (f32, f32),
+  // カメラ上の点
+  pub position: Vector3,
+  // 重要度 We にカメラ側のcos項を掛けたもの
+  pub importance: f32,
+  // カメラからその方向のレイがサンプリングされる確率密度
+  pub pdf: pdf::SolidAngle,
+}
+
 pub trait Camera: Transform {
   type PDF: pdf::Measure;
 
   fn sample(&self, u: f32, v: f32) -> Sample<Ray, Self::PDF>;
+
+  /**
+   * 点xをカメラに接続する (フィルムに写らない場合はNone)
+   */
+  fn importance(&self, x: Vector3) -> Option<Importance>;
 }
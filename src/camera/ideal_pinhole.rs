@@ -1,4 +1,4 @@
-use camera::Camera;
+use camera::{Camera, Importance};
 use math::*;
 use object::Transform;
 use ray::Ray;
@@ -11,6 +11,7 @@ pub struct IdealPinhole {
   aperture: Vector3,
   aperture_to_film_distance: f32,
   matrix: Matrix4,
+  inverse: Matrix4,
 }
 
 impl IdealPinhole {
@@ -30,11 +31,13 @@ impl IdealPinhole {
     // 視野角から開口部から撮像素子までの距離を計算
     // 撮像素子の大きさは1x(1/aspect)
     let aperture_to_film_distance = 0.5 / (xfov / 2.0).tan();
+    let inverse = matrix.inverse();
     IdealPinhole {
       aspect: aspect,
       aperture: aperture,
       aperture_to_film_distance: aperture_to_film_distance,
       matrix: matrix,
+      inverse: inverse,
     }
   }
 }
@@ -73,4 +76,55 @@ impl Camera for IdealPinhole {
       pdf: pdf::SolidAngle(distribution::DELTA_FUNCTION),
     }
   }
+
+  fn importance(&self, x: Vector3) -> Option<Importance> {
+    // カメラ座標系での開口部から点への方向
+    let direction = self.inverse.transform_direction(x - self.aperture);
+    let distance = direction.norm();
+    let cos = -direction.z / distance;
+    if cos <= 0.0 {
+      return None;
+    }
+    // 方向を延長した撮像素子上の点 (sample の逆変換)
+    let point = -direction * (self.aperture_to_film_distance / -direction.z);
+    let u = 0.5 - point.x;
+    let v = 0.5 - point.y * self.aspect;
+    if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+      return None;
+    }
+    // 開口部から距離1の位置での撮像素子の面積
+    let area = 1.0 / self.aspect / self.aperture_to_film_distance.powi(2);
+    // 撮像素子上で一様にサンプリングしたときの方向の確率密度 1 / (A cos^3)
+    let pdf = 1.0 / (area * cos.powi(3));
+    Some(Importance {
+      uv: (u, v),
+      position: self.aperture,
+      // We cos = 1 / (A cos^3)
+      importance: pdf,
+      pdf: pdf::SolidAngle(pdf),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn importance_uv_test() {
+    // サンプリングしたレイの先の点はサンプリングしたフィルム上の座標に写る
+    let matrix = Matrix4::look_at(
+      Vector3::new(1.0, 2.0, 3.0),
+      Vector3::new(0.0, 0.5, -1.0),
+      Vector3::new(0.0, 1.0, 0.0),
+    );
+    let camera = IdealPinhole::new(0.7, 1.5, matrix);
+    for &(u, v) in &[(0.5, 0.5), (0.1, 0.8), (0.9, 0.2)] {
+      let ray = camera.sample(u, v).value;
+      let importance = camera.importance(ray.origin + ray.direction * 4.0).unwrap();
+      let (u2, v2) = importance.uv;
+      assert!(u.approx_eq(u2), "{} {}", u, u2);
+      assert!(v.approx_eq(v2), "{} {}", v, v2);
+    }
+  }
 }
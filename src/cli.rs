@@ -0,0 +1,318 @@
+use scene::{SamplerKind, TonemapKind, TransportKind};
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: sunnypiece [OPTIONS] [SCENE]
+
+Arguments:
+  [SCENE]                      Scene description file [default: scenes/cornell_box.scene]
+
+Options:
+  -o, --output <PATH>          Output image path [default: images/image_<time>_<spp>.<ext>]
+  -r, --resolution <WxH>       Override the film resolution (e.g. 1280x720)
+  -s, --spp <N>                Override the samples per pixel
+                               (iterations for progressive_photon_mapping)
+      --seed <N>               Seed for the sampler [default: random]
+  -j, --threads <N>            Number of worker threads [default: all cores]
+  -i, --integrator <NAME>      par_pixel, par_debug, debug or metropolis [default: par_pixel]
+                               (progressive_photon_mapping always renders per pixel)
+      --sampler <NAME>         independent, stratified, halton or sobol [default: from the scene]
+  -t, --transport <NAME>       naive, explicit_light, only_light, volumetric_path,
+                               bidirectional, light_tracing, photon_mapping,
+                               progressive_photon_mapping, normal, id or intersection_test
+                               [default: from the scene]
+      --min-depth <N>          Depth at which Russian roulette starts [default: from the scene]
+      --max-depth <N>          Maximum path depth [default: from the scene]
+  -f, --format <NAME>          png or ppm [default: from the output extension, otherwise png]
+      --tonemap <NAME>         linear, gamma, gamma=<G> or debug [default: from the scene]
+  -h, --help                   Print this help";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntegratorKind {
+  ParPixel,
+  ParDebug,
+  Debug,
+  Metropolis,
+}
+
+impl IntegratorKind {
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "par_pixel" => Some(IntegratorKind::ParPixel),
+      "par_debug" => Some(IntegratorKind::ParDebug),
+      "debug" => Some(IntegratorKind::Debug),
+      "metropolis" => Some(IntegratorKind::Metropolis),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormatKind {
+  PNG,
+  PPM,
+}
+
+impl FormatKind {
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name.to_ascii_lowercase().as_str() {
+      "png" => Some(FormatKind::PNG),
+      "ppm" => Some(FormatKind::PPM),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct Options {
+  pub help: bool,
+  pub scene: PathBuf,
+  pub output: Option<PathBuf>,
+  pub resolution: Option<(usize, usize)>,
+  pub spp: Option<usize>,
+  pub seed: Option<u64>,
+  pub threads: Option<usize>,
+  pub integrator: Option<IntegratorKind>,
+  pub sampler: Option<SamplerKind>,
+  pub transport: Option<TransportKind>,
+  pub min_depth: Option<usize>,
+  pub max_depth: Option<usize>,
+  pub format: FormatKind,
+  pub tonemap: Option<TonemapKind>,
+}
+
+impl Options {
+  /**
+   * コマンドライン引数 (プログラム名を除く) を解釈する
+   */
+  pub fn parse<I>(args: I) -> Result<Options, String>
+  where
+    I: IntoIterator<Item = String>,
+  {
+    let mut args = args.into_iter();
+    let mut help = false;
+    let mut scene = None;
+    let mut output = None;
+    let mut resolution = None;
+    let mut spp = None;
+    let mut seed = None;
+    let mut threads = None;
+    let mut integrator = None;
+    let mut sampler = None;
+    let mut transport = None;
+    let mut min_depth = None;
+    let mut max_depth = None;
+    let mut format = None;
+    let mut tonemap = None;
+    while let Some(arg) = args.next() {
+      // `--name=value` の形式も受け付ける
+      let (name, inline_value) = match arg.find('=') {
+        Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+        _ => (arg.clone(), None),
+      };
+      let mut value = |what: &str| -> Result<String, String> {
+        inline_value
+          .clone()
+          .or_else(|| args.next())
+          .ok_or_else(|| format!("`{}` expects {}", name, what))
+      };
+      match name.as_str() {
+        "-h" | "--help" => help = true,
+        "-o" | "--output" => output = Some(PathBuf::from(value("a path")?)),
+        "-r" | "--resolution" => resolution = Some(parse_resolution(&value("<width>x<height>")?)?),
+        "-s" | "--spp" => spp = Some(parse_positive(&name, &value("a sample count")?)?),
+        "--seed" => {
+          let v = value("a seed")?;
+          seed = Some(
+            v.parse::<u64>()
+              .map_err(|_| format!("`--seed` expects an unsigned integer but found `{}`", v))?,
+          )
+        }
+        "-j" | "--threads" => threads = Some(parse_positive(&name, &value("a thread count")?)?),
+        "-i" | "--integrator" => {
+          let v = value("an integrator name")?;
+          integrator = Some(
+            IntegratorKind::from_name(&v).ok_or_else(|| format!("unknown integrator `{}`", v))?,
+          )
+        }
+        "--sampler" => {
+          let v = value("a sampler name")?;
+          sampler =
+            Some(SamplerKind::from_name(&v).ok_or_else(|| format!("unknown sampler `{}`", v))?)
+        }
+        "-t" | "--transport" => {
+          let v = value("a light transport name")?;
+          transport = Some(
+            TransportKind::from_name(&v)
+              .ok_or_else(|| format!("unknown light transport `{}`", v))?,
+          )
+        }
+        "--min-depth" => min_depth = Some(parse_unsigned(&name, &value("a path depth")?)?),
+        "--max-depth" => max_depth = Some(parse_positive(&name, &value("a path depth")?)?),
+        "-f" | "--format" => {
+          let v = value("an image format")?;
+          format = Some(FormatKind::from_name(&v).ok_or_else(|| format!("unknown format `{}`", v))?)
+        }
+        "--tonemap" => tonemap = Some(parse_tonemap(&value("a tonemap name")?)?),
+        _ if name.starts_with('-') && name.len() > 1 => {
+          return Err(format!("unknown option `{}`", name))
+        }
+        _ => {
+          if scene.is_some() {
+            return Err(format!("unexpected argument `{}`", arg));
+          }
+          scene = Some(PathBuf::from(arg))
+        }
+      }
+    }
+
+    // 保存形式は明示されていなければ出力パスの拡張子から決める
+    let format = match (format, &output) {
+      (Some(format), _) => format,
+      (None, Some(path)) => match path.extension() {
+        None => FormatKind::PNG,
+        Some(ext) => FormatKind::from_name(&ext.to_string_lossy()).ok_or_else(|| {
+          format!(
+            "cannot infer the image format from `{}` (use `--format`)",
+            path.display()
+          )
+        })?,
+      },
+      (None, None) => FormatKind::PNG,
+    };
+
+    Ok(Options {
+      help: help,
+      scene: scene.unwrap_or(PathBuf::from("scenes/cornell_box.scene")),
+      output: output,
+      resolution: resolution,
+      spp: spp,
+      seed: seed,
+      threads: threads,
+      integrator: integrator,
+      sampler: sampler,
+      transport: transport,
+      min_depth: min_depth,
+      max_depth: max_depth,
+      format: format,
+      tonemap: tonemap,
+    })
+  }
+}
+
+fn parse_positive(name: &str, value: &str) -> Result<usize, String> {
+  match value.parse::<usize>() {
+    Ok(v) if v > 0 => Ok(v),
+    _ => Err(format!(
+      "`{}` expects a positive integer but found `{}`",
+      name, value
+    )),
+  }
+}
+
+fn parse_unsigned(name: &str, value: &str) -> Result<usize, String> {
+  value.parse::<usize>().map_err(|_| {
+    format!(
+      "`{}` expects a non-negative integer but found `{}`",
+      name, value
+    )
+  })
+}
+
+fn parse_resolution(value: &str) -> Result<(usize, usize), String> {
+  let error = || {
+    format!(
+      "`--resolution` expects <width>x<height> (e.g. 800x600) but found `{}`",
+      value
+    )
+  };
+  let mut split = value.splitn(2, ['x', 'X']);
+  let width = split.next().and_then(|v| v.parse::<usize>().ok());
+  let height = split.next().and_then(|v| v.parse::<usize>().ok());
+  match (width, height) {
+    (Some(w), Some(h)) if w > 0 && h > 0 => Ok((w, h)),
+    _ => Err(error()),
+  }
+}
+
+fn parse_tonemap(value: &str) -> Result<TonemapKind, String> {
+  let mut split = value.splitn(2, '=');
+  let name = split.next().unwrap_or("");
+  let gamma = match split.next() {
+    None => None,
+    Some(v) => Some(
+      v.parse::<f32>()
+        .map_err(|_| format!("`--tonemap` expects a number after `=` but found `{}`", v))?,
+    ),
+  };
+  TonemapKind::from_name(name, gamma).ok_or_else(|| format!("unknown tonemap `{}`", value))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(args: &[&str]) -> Result<Options, String> {
+    Options::parse(args.iter().map(|v| v.to_string()))
+  }
+
+  #[test]
+  fn default_test() {
+    let options = parse(&[]).unwrap();
+    assert!(!options.help);
+    assert_eq!(options.integrator, None);
+    assert_eq!(options.format, FormatKind::PNG);
+    assert_eq!(options.transport, None);
+  }
+
+  #[test]
+  fn options_test() {
+    let options = parse(&[
+      "scene.txt",
+      "-r",
+      "320x240",
+      "--spp=16",
+      "--seed",
+      "42",
+      "-o",
+      "out.ppm",
+      "--tonemap",
+      "gamma=2.4",
+      "-t",
+      "naive",
+      "--sampler",
+      "sobol",
+    ])
+    .unwrap();
+    assert_eq!(options.scene, PathBuf::from("scene.txt"));
+    assert_eq!(options.resolution, Some((320, 240)));
+    assert_eq!(options.spp, Some(16));
+    assert_eq!(options.seed, Some(42));
+    assert_eq!(options.integrator, None);
+    assert_eq!(options.format, FormatKind::PPM);
+    assert_eq!(options.tonemap, Some(TonemapKind::Gamma(2.4)));
+    assert_eq!(options.transport, Some(TransportKind::Naive));
+    assert_eq!(options.sampler, Some(SamplerKind::Sobol));
+  }
+
+  #[test]
+  fn validation_test() {
+    assert!(parse(&["--spp", "0"]).is_err());
+    assert!(parse(&["--spp"]).is_err());
+    assert!(parse(&["-r", "800"]).is_err());
+    assert!(parse(&["--seed", "-1"]).is_err());
+    assert!(parse(&["--integrator", "par_pixel", "--seed", "1"]).is_ok());
+    // 既定値と同じ積分器でも明示的な指定として区別する
+    assert_eq!(
+      parse(&["--integrator", "par_pixel"]).unwrap().integrator,
+      Some(IntegratorKind::ParPixel)
+    );
+    assert!(parse(&["-o", "out.jpg"]).is_err());
+    assert!(parse(&["--max-depth", "0"]).is_err());
+    assert!(parse(&["--min-depth", "-1"]).is_err());
+    assert_eq!(parse(&["--min-depth", "0"]).unwrap().min_depth, Some(0));
+    assert!(parse(&["--sampler", "bogus"]).is_err());
+    assert!(parse(&["--bogus"]).is_err());
+    assert!(parse(&["a.scene", "b.scene"]).is_err());
+  }
+}
@@ -0,0 +1,40 @@
+use super::Environment;
+use math::*;
+use sample::{pdf, Sample};
+use sampler::Sampling;
+
+/**
+ * 全方向から一様な放射輝度の環境光
+ */
+pub struct Constant {
+  pub radiance: Vector3,
+}
+
+impl Constant {
+  pub fn black() -> Self {
+    Constant {
+      radiance: Vector3::zero(),
+    }
+  }
+}
+
+impl Environment for Constant {
+  fn radiance(&self, _direction: Vector3) -> Vector3 {
+    self.radiance
+  }
+
+  fn sample(&self) -> Sample<Vector3, pdf::SolidAngle> {
+    Sample {
+      value: Sampling::sphere_uniform(),
+      pdf: self.pdf(Vector3::zero()),
+    }
+  }
+
+  fn pdf(&self, _direction: Vector3) -> pdf::SolidAngle {
+    pdf::SolidAngle(1.0 / (4.0 * PI))
+  }
+
+  fn mean_radiance(&self) -> Vector3 {
+    self.radiance
+  }
+}
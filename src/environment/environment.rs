@@ -0,0 +1,18 @@
+use math::Vector3;
+use sample::{pdf, Sample};
+
+/**
+ * シーンの外側から入射する環境光
+ *
+ * 方向ベクトルはシーンの中心から外向き
+ */
+pub trait Environment {
+  // 方向directionから入射する放射輝度
+  fn radiance(&self, direction: Vector3) -> Vector3;
+  // 放射輝度に応じた方向のサンプリング
+  fn sample(&self) -> Sample<Vector3, pdf::SolidAngle>;
+  // 方向directionがサンプリングされる確率密度
+  fn pdf(&self, direction: Vector3) -> pdf::SolidAngle;
+  // 全方向の平均の放射輝度 (光源の選択確率に使う)
+  fn mean_radiance(&self) -> Vector3;
+}
@@ -0,0 +1,245 @@
+use super::Environment;
+use image::codecs::hdr::HdrDecoder;
+use math::*;
+use sample::distribution::Distribution1D;
+use sample::{pdf, Sample};
+use sampler::Sampling;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/**
+ * 正距円筒図法 (equirectangular) のHDR画像による環境光
+ *
+ * 画像の上端が +y (天頂)、画像の中心が -z の方向
+ * 画素ごとの放射輝度と立体角に比例して方向を重点的にサンプリングする
+ */
+pub struct Equirectangular {
+  width: usize,
+  height: usize,
+  // Row-major order (上の行から)
+  data: Vec<Vector3>,
+  // y軸まわりの回転 [rad]
+  rotation: f32,
+  // 行の周辺分布
+  marginal: Distribution1D,
+  // 行ごとの列の条件付き分布
+  conditional: Vec<Distribution1D>,
+  mean_radiance: Vector3,
+}
+
+impl Equirectangular {
+  pub fn new(width: usize, height: usize, data: Vec<Vector3>, rotation: f32) -> Self {
+    debug_assert!(width > 0 && height > 0);
+    debug_assert_eq!(data.len(), width * height);
+    // 各画素の重みは 放射輝度 * sinθ (画素の立体角に比例)
+    let conditional = (0..height)
+      .map(|j| {
+        let sin_theta = ((j as f32 + 0.5) / height as f32 * PI).sin();
+        let weights = data[j * width..(j + 1) * width]
+          .iter()
+          .map(|v| v.max().max(0.0) * sin_theta)
+          .collect::<Vec<_>>();
+        Distribution1D::new(&weights)
+      })
+      .collect::<Vec<_>>();
+    let marginal = Distribution1D::new(&conditional.iter().map(|v| v.sum()).collect::<Vec<_>>());
+    // 立体角で重み付けした平均
+    let mut sum = Vector3::zero();
+    let mut solid_angle = 0.0;
+    for j in 0..height {
+      let sin_theta = ((j as f32 + 0.5) / height as f32 * PI).sin();
+      for i in 0..width {
+        sum += data[j * width + i] * sin_theta;
+        solid_angle += sin_theta;
+      }
+    }
+    Equirectangular {
+      width: width,
+      height: height,
+      data: data,
+      rotation: rotation,
+      marginal: marginal,
+      conditional: conditional,
+      mean_radiance: sum / solid_angle,
+    }
+  }
+
+  /**
+   * Radiance HDR (.hdr) または OpenEXR (.exr) 形式の画像を読み込む
+   *
+   * 形式は拡張子で判定する (OpenEXRは最初のRGBレイヤーを使う)
+   */
+  pub fn load(path: &Path, scale: f32, rotation: f32) -> Result<Self, String> {
+    let extension = path
+      .extension()
+      .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+      .unwrap_or_default();
+    let (width, height, data) = match extension.as_str() {
+      "hdr" => Self::read_hdr(path)?,
+      "exr" => Self::read_exr(path)?,
+      _ => {
+        return Err(
+          "environment maps must be Radiance HDR (.hdr) or OpenEXR (.exr) images".to_string(),
+        )
+      }
+    };
+    let data = data.into_iter().map(|v| v * scale).collect();
+    Ok(Equirectangular::new(width, height, data, rotation))
+  }
+
+  fn read_hdr(path: &Path) -> Result<(usize, usize, Vec<Vector3>), String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let decoder = HdrDecoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+    let metadata = decoder.metadata();
+    let data = decoder
+      .read_image_hdr()
+      .map_err(|e| e.to_string())?
+      .iter()
+      .map(|p| Vector3::new(p[0], p[1], p[2]))
+      .collect::<Vec<_>>();
+    Ok((metadata.width as usize, metadata.height as usize, data))
+  }
+
+  fn read_exr(path: &Path) -> Result<(usize, usize, Vec<Vector3>), String> {
+    // アルファチャンネルは使わない
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+      path,
+      |size, _| (size.width(), vec![Vector3::zero(); size.area()]),
+      |pixels: &mut (usize, Vec<Vector3>), position, (r, g, b, _): (f32, f32, f32, f32)| {
+        let width = pixels.0;
+        pixels.1[position.y() * width + position.x()] = Vector3::new(r, g, b);
+      },
+    )
+    .map_err(|e| e.to_string())?;
+    let size = image.layer_data.size;
+    let (_, data) = image.layer_data.channel_data.pixels;
+    Ok((size.width(), size.height(), data))
+  }
+
+  /**
+   * 方向 -> 画像上の座標 [0, 1)^2
+   */
+  fn uv(&self, direction: Vector3) -> (f32, f32) {
+    let phi = direction.x.atan2(-direction.z) - self.rotation;
+    let u = phi / (2.0 * PI) + 0.5;
+    let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+    (u - u.floor(), v)
+  }
+
+  /**
+   * 画像上の座標 -> 方向
+   */
+  fn direction(&self, u: f32, v: f32) -> Vector3 {
+    let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+    let theta = v * PI;
+    let sin_theta = theta.sin();
+    Vector3::new(sin_theta * phi.sin(), theta.cos(), -sin_theta * phi.cos())
+  }
+
+  fn pixel(&self, u: f32, v: f32) -> (usize, usize) {
+    let i = ((u * self.width as f32) as usize).min(self.width - 1);
+    let j = ((v * self.height as f32) as usize).min(self.height - 1);
+    (i, j)
+  }
+
+  /**
+   * 画像上の面積測度の確率密度 -> 立体角測度の確率密度
+   *
+   * dω = 2π^2 sinθ du dv
+   */
+  fn pdf_uv(&self, i: usize, j: usize, v: f32) -> pdf::SolidAngle {
+    let sin_theta = (v * PI).sin();
+    if sin_theta <= 0.0 {
+      return pdf::SolidAngle(0.0);
+    }
+    let p = self.marginal.probability(j) * self.conditional[j].probability(i);
+    let pdf_uv = p * (self.width * self.height) as f32;
+    pdf::SolidAngle(pdf_uv / (2.0 * PI * PI * sin_theta))
+  }
+}
+
+impl Environment for Equirectangular {
+  fn radiance(&self, direction: Vector3) -> Vector3 {
+    let (u, v) = self.uv(direction);
+    let (i, j) = self.pixel(u, v);
+    self.data[j * self.width + i]
+  }
+
+  fn sample(&self) -> Sample<Vector3, pdf::SolidAngle> {
+    // 行, 列の順に選び、画素内では一様にサンプリングする
+    let (r1, r2) = Sampling::get_2d();
+    let j = self.marginal.sample(r1);
+    let i = self.conditional[j].sample(r2);
+    let (su, sv) = Sampling::get_2d();
+    let u = (i as f32 + su) / self.width as f32;
+    let v = (j as f32 + sv) / self.height as f32;
+    Sample {
+      value: self.direction(u, v),
+      pdf: self.pdf_uv(i, j, v),
+    }
+  }
+
+  fn pdf(&self, direction: Vector3) -> pdf::SolidAngle {
+    let (u, v) = self.uv(direction);
+    let (i, j) = self.pixel(u, v);
+    self.pdf_uv(i, j, v)
+  }
+
+  fn mean_radiance(&self) -> Vector3 {
+    self.mean_radiance
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn environment() -> Equirectangular {
+    let (width, height) = (8, 4);
+    let data = (0..width * height)
+      .map(|k| Vector3::fill(1.0 + (k % 5) as f32))
+      .collect();
+    Equirectangular::new(width, height, data, 0.3)
+  }
+
+  #[test]
+  fn direction_uv_test() {
+    let e = environment();
+    for &(u, v) in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.7)] {
+      let (u2, v2) = e.uv(e.direction(u, v));
+      assert!(u.approx_eq(u2), "{} {}", u, u2);
+      assert!(v.approx_eq(v2), "{} {}", v, v2);
+    }
+  }
+
+  #[test]
+  fn pdf_normalized_test() {
+    // 立体角について積分すると1になる
+    let e = environment();
+    let n = 256;
+    let mut integral = 0.0;
+    for j in 0..n {
+      for i in 0..n {
+        let u = (i as f32 + 0.5) / n as f32;
+        let v = (j as f32 + 0.5) / n as f32;
+        let d_omega = 2.0 * PI * PI * (v * PI).sin() / (n * n) as f32;
+        integral += e.pdf(e.direction(u, v)).0 * d_omega;
+      }
+    }
+    assert!((integral - 1.0).abs() < 1e-3, "{}", integral);
+  }
+
+  #[test]
+  fn load_exr_test() {
+    // 画像の上の行から読み込まれ、scale が掛けられる
+    let path = std::env::temp_dir().join("sunnypiece_load_exr_test.exr");
+    exr::prelude::write_rgba_file(&path, 4, 2, |x, y| (x as f32, y as f32, 0.5, 1.0)).unwrap();
+    let e = Equirectangular::load(&path, 2.0, 0.0).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!((e.width, e.height), (4, 2));
+    // (x, y) = (3, 1)
+    assert_eq!(e.data[7], Vector3::new(6.0, 2.0, 1.0));
+    assert!(Equirectangular::load(Path::new("sky.png"), 1.0, 0.0).is_err());
+  }
+}
@@ -0,0 +1,9 @@
+mod constant;
+mod environment;
+mod equirectangular;
+mod sky;
+
+pub use self::constant::*;
+pub use self::environment::*;
+pub use self::equirectangular::*;
+pub use self::sky::*;
@@ -0,0 +1,63 @@
+use super::Environment;
+use math::*;
+use sample::{pdf, Sample};
+use sampler::Sampling;
+
+/**
+ * 天頂から地平線へのグラデーションと地面からなる手続き的な空
+ *
+ * 上方向は +y
+ */
+pub struct Sky {
+  pub zenith: Vector3,
+  pub horizon: Vector3,
+  pub ground: Vector3,
+}
+
+impl Sky {
+  pub fn new(zenith: Vector3, horizon: Vector3, ground: Vector3) -> Self {
+    Sky {
+      zenith: zenith,
+      horizon: horizon,
+      ground: ground,
+    }
+  }
+}
+
+impl Default for Sky {
+  fn default() -> Self {
+    Sky::new(
+      Vector3::new(0.3, 0.5, 1.0),
+      Vector3::new(1.0, 1.0, 1.0),
+      Vector3::new(0.3, 0.3, 0.3),
+    )
+  }
+}
+
+impl Environment for Sky {
+  fn radiance(&self, direction: Vector3) -> Vector3 {
+    if direction.y < 0.0 {
+      return self.ground;
+    }
+    // 地平線付近で急に変化させるために sqrt(y) で補間する
+    let t = direction.y.min(1.0).sqrt();
+    self.horizon * (1.0 - t) + self.zenith * t
+  }
+
+  fn sample(&self) -> Sample<Vector3, pdf::SolidAngle> {
+    Sample {
+      value: Sampling::sphere_uniform(),
+      pdf: self.pdf(Vector3::zero()),
+    }
+  }
+
+  fn pdf(&self, _direction: Vector3) -> pdf::SolidAngle {
+    pdf::SolidAngle(1.0 / (4.0 * PI))
+  }
+
+  fn mean_radiance(&self) -> Vector3 {
+    // 球面上の一様分布ではyが[-1, 1]で一様なので ∫_0^1 sqrt(y) dy = 2/3
+    let sky = self.horizon / 3.0 + self.zenith * (2.0 / 3.0);
+    (sky + self.ground) / 2.0
+  }
+}
@@ -1,9 +1,8 @@
 use super::tonemap::Tonemap;
 use math::Vector3;
-use rand::Rng;
+use sampler::Sampling;
 use std::path::Path;
 use util::*;
-use RNG;
 
 pub struct Film<T> {
   /**
@@ -41,13 +40,10 @@ impl<T: Clone> Film<T> {
       // flip y
       let y = h - index / w - 1;
 
-      RNG.with(|rng| {
-        // super sampling
-        let su = rng.borrow_mut().gen::<f32>();
-        let sv = rng.borrow_mut().gen::<f32>();
+      // super sampling
+      let (su, sv) = Sampling::get_2d();
 
-        ((x as f32 + su) / w as f32, (y as f32 + sv) / h as f32)
-      })
+      ((x as f32 + su) / w as f32, (y as f32 + sv) / h as f32)
     }
   }
 
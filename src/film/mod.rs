@@ -1,8 +1,10 @@
 mod film;
 mod png;
 mod ppm;
+mod splat;
 pub mod tonemap;
 
 pub use self::film::*;
 pub use self::png::*;
 pub use self::ppm::*;
+pub use self::splat::*;
@@ -0,0 +1,49 @@
+use super::Film;
+use math::*;
+use std::sync::Mutex;
+
+/**
+ * 画素を選ばずに寄与を足し込むフィルム
+ *
+ * 光源側から追跡したパスのように、寄与する画素がサンプルごとに変わる場合に使う
+ * 複数のスレッドから同時に足し込める
+ */
+pub struct Splat {
+  width: usize,
+  height: usize,
+  data: Vec<Mutex<Vector3>>,
+}
+
+impl Splat {
+  pub fn new(width: usize, height: usize) -> Self {
+    Splat {
+      width: width,
+      height: height,
+      data: (0..width * height)
+        .map(|_| Mutex::new(Vector3::zero()))
+        .collect(),
+    }
+  }
+
+  /**
+   * フィルム上の座標uv ((0, 0) が左下) の画素に寄与を足す
+   */
+  pub fn add(&self, uv: (f32, f32), value: Vector3) {
+    let (u, v) = uv;
+    let x = ((u * self.width as f32) as usize).min(self.width - 1);
+    // flip y
+    let y = self.height - ((v * self.height as f32) as usize).min(self.height - 1) - 1;
+    let mut pixel = self.data[y * self.width + x].lock().unwrap();
+    *pixel += value;
+  }
+
+  /**
+   * 足し込んだ寄与をscale倍してフィルムに加える
+   */
+  pub fn apply(&self, film: &mut Film<Vector3>, scale: f32) {
+    debug_assert!(film.width == self.width && film.height == self.height);
+    for (pixel, splat) in film.data.iter_mut().zip(self.data.iter()) {
+      *pixel += *splat.lock().unwrap() * scale;
+    }
+  }
+}
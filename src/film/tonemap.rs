@@ -10,6 +10,12 @@ pub struct Gamma {
   gamma: f32,
 }
 
+impl Gamma {
+  pub fn new(gamma: f32) -> Self {
+    Gamma { gamma }
+  }
+}
+
 impl Default for Gamma {
   fn default() -> Self {
     Gamma { gamma: 2.2 }
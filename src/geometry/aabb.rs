@@ -60,6 +60,27 @@ impl AABB {
     }
   }
 
+  /**
+   * 変換後の8頂点を囲むAABB
+   */
+  pub fn transform(&self, m: &Matrix4) -> AABB {
+    let mut aabb = AABB::empty();
+    for i in 0..8 {
+      let corner = Vector3::new(
+        if i & 1 == 0 { self.min.x } else { self.max.x },
+        if i & 2 == 0 { self.min.y } else { self.max.y },
+        if i & 4 == 0 { self.min.z } else { self.max.z },
+      );
+      let p = m * corner;
+      aabb = aabb.merge_with(&AABB {
+        min: p,
+        max: p,
+        center: p,
+      });
+    }
+    aabb
+  }
+
   pub fn empty() -> AABB {
     AABB {
       min: Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
@@ -1,16 +1,20 @@
 use super::intersection::Intersection;
 use super::AABB;
-use math::Vector3;
+use math::{Matrix4, Vector3};
 use ray::Ray;
 use sample::{pdf, Sample};
 
 pub trait Geometry {
   fn intersect(&self, ray: &Ray) -> Option<Intersection>;
   fn area(&self) -> f32;
+  // 変換matrixを掛けた後の表面積
+  fn transformed_area(&self, matrix: &Matrix4) -> f32;
   fn sample(&self) -> Sample<Vector3, pdf::Area>;
   fn pdf(&self) -> pdf::Area;
   fn aabb(&self) -> &AABB;
+  // 点xでの面の幾何的な法線 (面積の変換や光源の放射に使う)
   fn normal(&self, x: Vector3) -> Vector3;
+  fn uv(&self, x: Vector3) -> (f32, f32);
   fn id(&self) -> usize;
   fn bounding_sphere(&self) -> (Vector3, f32);
 }
@@ -2,6 +2,15 @@ use math::Vector3;
 
 pub struct Intersection {
   pub position: Vector3,
+  // 面の幾何的な法線
   pub normal: Vector3,
+  // 頂点法線を補間したシェーディング法線 (normal と同じ側)
+  pub shading_normal: Vector3,
   pub distance: f32,
+  // テクスチャ座標
+  pub uv: (f32, f32),
+  // 三角形の頂点 p0, p1, p2 の重み (球では使わない)
+  pub barycentric: Vector3,
+  // テクスチャ座標の u が増える向きの接線 (正規化されていない)
+  pub tangent: Vector3,
 }
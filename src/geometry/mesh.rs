@@ -0,0 +1,138 @@
+use super::{Geometry, Intersection, Triangle, AABB};
+use acceleration::Tree;
+use math::{Matrix4, Vector3};
+use ray::Ray;
+use sample::distribution::Distribution1D;
+use sample::{pdf, Sample};
+use sampler::Sampling;
+
+/**
+ * 三角形の集合とそのBVH
+ *
+ * 複数のオブジェクトから共有される (インスタンシング)
+ * 三角形のidはメッシュ内で1から連番になる
+ */
+pub struct Mesh {
+  triangles: Vec<Triangle>,
+  tree: Tree,
+  area: f32,
+  // 面積に比例した三角形の選択
+  distribution: Distribution1D,
+}
+
+impl Mesh {
+  pub fn new(triangles: Vec<Triangle>) -> Self {
+    debug_assert!(
+      triangles.iter().enumerate().all(|(i, t)| t.id() == i + 1),
+      "triangle ids should be local to the mesh"
+    );
+    let tree = Tree::new(triangles.iter().map(|v| v.aabb().clone()).collect());
+    let area = triangles.iter().map(|v| v.area()).sum();
+    let distribution = Distribution1D::new(&triangles.iter().map(|v| v.area()).collect::<Vec<_>>());
+    Mesh {
+      triangles: triangles,
+      tree: tree,
+      area: area,
+      distribution: distribution,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.triangles.len()
+  }
+
+  pub fn aabb(&self) -> &AABB {
+    self.tree.aabb()
+  }
+
+  pub fn area(&self) -> f32 {
+    self.area
+  }
+
+  /**
+   * 変換matrixを掛けた後の表面積
+   */
+  pub fn transformed_area(&self, matrix: &Matrix4) -> f32 {
+    self
+      .triangles
+      .iter()
+      .map(|v| v.transformed_area(matrix))
+      .sum()
+  }
+
+  /**
+   * 最も近い交差点とその三角形
+   */
+  pub fn intersect(&self, ray: &Ray) -> Option<(&Triangle, Intersection)> {
+    self
+      .tree
+      .candidates(ray)
+      .iter()
+      .flat_map(|&i| {
+        let triangle = &self.triangles[i];
+        triangle.intersect(ray).map(|v| (triangle, v))
+      })
+      // 縮退した三角形では距離がNaNになりうる
+      .filter(|a| a.1.distance.is_finite())
+      .min_by(|a, b| a.1.distance.total_cmp(&b.1.distance))
+  }
+
+  /**
+   * 面積に比例して三角形を選び、その上の点を一様にサンプリングする
+   */
+  pub fn sample(&self) -> (&Triangle, Sample<Vector3, pdf::Area>) {
+    let i = self.distribution.sample(Sampling::get_1d());
+    let triangle = &self.triangles[i];
+    let sample = triangle.sample();
+    (
+      triangle,
+      Sample {
+        value: sample.value,
+        pdf: self.pdf(),
+      },
+    )
+  }
+
+  pub fn pdf(&self) -> pdf::Area {
+    pdf::Area(1.0 / self.area)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use geometry::UUID;
+  use math::*;
+
+  fn quad(z: f32, uuid: &mut UUID) -> Vec<Triangle> {
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let p = [
+      Vector3::new(-1.0, -1.0, z),
+      Vector3::new(1.0, -1.0, z),
+      Vector3::new(1.0, 1.0, z),
+      Vector3::new(-1.0, 1.0, z),
+    ];
+    vec![
+      Triangle::new(p[0], p[1], p[2], n, n, n, uuid),
+      Triangle::new(p[0], p[2], p[3], n, n, n, uuid),
+    ]
+  }
+
+  #[test]
+  fn intersect_nearest_test() {
+    let mut uuid = UUID::new();
+    let mut triangles = quad(-2.0, &mut uuid);
+    triangles.append(&mut quad(-1.0, &mut uuid));
+    let mesh = Mesh::new(triangles);
+    assert_eq!(mesh.len(), 4);
+    assert!(mesh.area().approx_eq(8.0));
+    let ray = Ray {
+      origin: Vector3::new(0.1, 0.2, 0.0),
+      direction: Vector3::new(0.0, 0.0, -1.0),
+      from: None,
+    };
+    let (triangle, intersection) = mesh.intersect(&ray).unwrap();
+    assert!(triangle.id() > 2);
+    assert!(intersection.distance.approx_eq(1.0));
+  }
+}
@@ -1,6 +1,7 @@
 mod aabb;
 mod geometry;
 mod intersection;
+mod mesh;
 mod sphere;
 mod triangle;
 mod util;
@@ -8,6 +9,7 @@ mod util;
 pub use self::aabb::*;
 pub use self::geometry::*;
 pub use self::intersection::*;
+pub use self::mesh::*;
 pub use self::sphere::*;
 pub use self::triangle::*;
 pub use self::util::*;
@@ -5,7 +5,7 @@ use super::UUID;
 use math::*;
 use ray::Ray;
 use sample::{pdf, Sample};
-use sampler::Sampler;
+use sampler::Sampling;
 
 pub struct Sphere {
   position: Vector3,
@@ -26,6 +26,32 @@ impl Sphere {
     }
   }
 
+  /**
+   * 法線 -> テクスチャ座標
+   *
+   * u は -z から -x の向きに回る経度 (外から見て左から右)、
+   * v は南極 (-y) で0、北極 (+y) で1になる緯度
+   */
+  fn uv(normal: Vector3) -> (f32, f32) {
+    let u = (-normal.x).atan2(-normal.z) / (2.0 * PI) + 0.5;
+    let v = (-normal.y).clamp(-1.0, 1.0).acos() / PI;
+    (u, v)
+  }
+
+  /**
+   * 法線 -> 経度 u が増える向きの接線
+   *
+   * 極では経度が定まらないので -x
+   */
+  fn tangent(normal: Vector3) -> Vector3 {
+    let tangent = Vector3::new(normal.z, 0.0, -normal.x);
+    if tangent.sqr_norm() > 0.0 {
+      tangent
+    } else {
+      Vector3::new(-1.0, 0.0, 0.0)
+    }
+  }
+
   fn aabb(position: Vector3, radius: f32) -> AABB {
     let r = Vector3::fill(radius);
     AABB {
@@ -77,7 +103,11 @@ impl Geometry for Sphere {
     Some(Intersection {
       position: position_refined,
       normal: normal,
+      shading_normal: normal,
       distance: distance,
+      uv: Self::uv(normal),
+      barycentric: Vector3::zero(),
+      tangent: Self::tangent(normal),
     })
   }
 
@@ -85,9 +115,27 @@ impl Geometry for Sphere {
     self.area
   }
 
+  fn transformed_area(&self, matrix: &Matrix4) -> f32 {
+    // 楕円体の表面積は初等関数で書けないので、
+    // 面積の拡大率 |det(M)| |M^-T n| を cosθ と φ で等分した球面上で数値積分する
+    let normal_matrix = matrix.normal_matrix();
+    let (nt, np) = (64, 128);
+    let mut sum = 0.0;
+    for i in 0..nt {
+      let cos = 1.0 - 2.0 * (i as f32 + 0.5) / nt as f32;
+      let sin = (1.0 - cos * cos).sqrt();
+      for j in 0..np {
+        let phi = 2.0 * PI * (j as f32 + 0.5) / np as f32;
+        let n = Vector3::new(sin * phi.cos(), sin * phi.sin(), cos);
+        sum += normal_matrix.transform_direction(n).norm();
+      }
+    }
+    self.area * matrix.determinant().abs() * sum / (nt * np) as f32
+  }
+
   fn sample(&self) -> Sample<Vector3, pdf::Area> {
     Sample {
-      value: self.position + self.radius * Sampler::sphere_uniform(),
+      value: self.position + self.radius * Sampling::sphere_uniform(),
       pdf: pdf::Area(1.0 / self.area),
     }
   }
@@ -104,6 +152,10 @@ impl Geometry for Sphere {
     (x - self.position).normalize()
   }
 
+  fn uv(&self, x: Vector3) -> (f32, f32) {
+    Self::uv(self.normal(x))
+  }
+
   fn id(&self) -> usize {
     self.id
   }
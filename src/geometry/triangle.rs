@@ -4,6 +4,7 @@ use geometry::{Geometry, Intersection};
 use math::*;
 use ray::Ray;
 use sample::{pdf, Sample};
+use sampler::Sampling;
 
 pub struct Triangle {
   p0: Vector3,
@@ -12,6 +13,14 @@ pub struct Triangle {
   n0: Vector3,
   n1: Vector3,
   n2: Vector3,
+  // 頂点のテクスチャ座標
+  uv0: (f32, f32),
+  uv1: (f32, f32),
+  uv2: (f32, f32),
+  // 頂点の接線 (テクスチャ座標の u が増える向き)
+  t0: Vector3,
+  t1: Vector3,
+  t2: Vector3,
   normal: Vector3,
   area: f32,
   aabb: AABB,
@@ -29,6 +38,8 @@ impl Triangle {
     n2: Vector3,
     uuid: &mut UUID,
   ) -> Self {
+    let (uv0, uv1, uv2) = ((0.0, 0.0), (1.0, 0.0), (1.0, 1.0));
+    let tangent = Self::tangent(p0, p1, p2, uv0, uv1, uv2);
     Triangle {
       p0,
       p1,
@@ -36,6 +47,12 @@ impl Triangle {
       n0,
       n1,
       n2,
+      uv0: uv0,
+      uv1: uv1,
+      uv2: uv2,
+      t0: tangent,
+      t1: tangent,
+      t2: tangent,
       normal: Self::normal(p0, p1, p2),
       area: (p1 - p0).cross(p2 - p0).norm() * 0.5,
       aabb: Self::aabb(p0, p1, p2),
@@ -44,10 +61,80 @@ impl Triangle {
     }
   }
 
+  /**
+   * 頂点のテクスチャ座標を指定する
+   *
+   * 指定しない場合は (0, 0), (1, 0), (1, 1)
+   * 頂点の接線は面の接線になる
+   */
+  pub fn with_uv(mut self, uv0: (f32, f32), uv1: (f32, f32), uv2: (f32, f32)) -> Self {
+    self.uv0 = uv0;
+    self.uv1 = uv1;
+    self.uv2 = uv2;
+    let tangent = Self::tangent(self.p0, self.p1, self.p2, uv0, uv1, uv2);
+    self.t0 = tangent;
+    self.t1 = tangent;
+    self.t2 = tangent;
+    self
+  }
+
+  /**
+   * 頂点の接線を指定する (メッシュの頂点ごとに平均した接線など)
+   */
+  pub fn with_tangent(mut self, t0: Vector3, t1: Vector3, t2: Vector3) -> Self {
+    self.t0 = t0;
+    self.t1 = t1;
+    self.t2 = t2;
+    self
+  }
+
+  /**
+   * テクスチャ座標から求めた面の接線 dp/du (正規化済み)
+   *
+   * テクスチャ座標が縮退している場合は辺 p0-p1 の向き
+   */
+  pub fn tangent(
+    p0: Vector3,
+    p1: Vector3,
+    p2: Vector3,
+    uv0: (f32, f32),
+    uv1: (f32, f32),
+    uv2: (f32, f32),
+  ) -> Vector3 {
+    let (dp1, dp2) = (p1 - p0, p2 - p0);
+    let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+    let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+    let det = du1 * dv2 - du2 * dv1;
+    let tangent = (dp1 * dv2 - dp2 * dv1) * det.signum();
+    if det.abs() < 1e-12 || tangent.sqr_norm() == 0.0 {
+      dp1.normalize()
+    } else {
+      tangent.normalize()
+    }
+  }
+
   pub fn normal(p0: Vector3, p1: Vector3, p2: Vector3) -> Vector3 {
     (p1 - p0).cross(p2 - p0).normalize()
   }
 
+  /**
+   * 頂点 p0, p1, p2 の重みが w, u, v の点で頂点法線を補間したシェーディング法線
+   *
+   * 面の法線と同じ側に向きを揃え、補間した法線が縮退する場合は面の法線
+   */
+  fn shading_normal(&self, w: f32, u: f32, v: f32) -> Vector3 {
+    let n = self.n0 * w + self.n1 * u + self.n2 * v;
+    if n.sqr_norm() == 0.0 {
+      return self.normal;
+    }
+    let n = n.normalize();
+    if n.dot(self.normal) < 0.0 {
+      -n
+    } else {
+      n
+    }
+  }
+
   fn aabb(p0: Vector3, p1: Vector3, p2: Vector3) -> AABB {
     let min = Vector3::new(
       p0.x.min(p1.x).min(p2.x),
@@ -138,10 +225,18 @@ impl Geometry for Triangle {
     }
     // Derive position from barycentric coordinates
     let p = self.p0 + e1 * u + e2 * v;
+    let w = 1.0 - u - v;
     Some(Intersection {
       distance: t,
       normal: self.normal,
+      shading_normal: self.shading_normal(w, u, v),
       position: p,
+      uv: (
+        self.uv0.0 * w + self.uv1.0 * u + self.uv2.0 * v,
+        self.uv0.1 * w + self.uv1.1 * u + self.uv2.1 * v,
+      ),
+      barycentric: Vector3::new(w, u, v),
+      tangent: self.t0 * w + self.t1 * u + self.t2 * v,
     })
   }
 
@@ -149,9 +244,13 @@ impl Geometry for Triangle {
     self.area
   }
 
+  fn transformed_area(&self, matrix: &Matrix4) -> f32 {
+    let (p0, p1, p2) = (matrix * self.p0, matrix * self.p1, matrix * self.p2);
+    (p1 - p0).cross(p2 - p0).norm() * 0.5
+  }
+
   fn sample(&self) -> Sample<Vector3, pdf::Area> {
-    let u = rand::random::<f32>();
-    let v = rand::random::<f32>();
+    let (u, v) = Sampling::get_2d();
     let min = u.min(v);
     let max = u.max(v);
     Sample {
@@ -168,10 +267,16 @@ impl Geometry for Triangle {
     &self.aabb
   }
 
-  fn normal(&self, x: Vector3) -> Vector3 {
+  fn normal(&self, _x: Vector3) -> Vector3 {
+    self.normal
+  }
+
+  fn uv(&self, x: Vector3) -> (f32, f32) {
     let g = x.barycentric_coordinate(self.p0, self.p1, self.p2);
-    (g.x * self.n0 + g.y * self.n1 + g.z * self.n2).normalize()
-    // self.normal
+    (
+      g.x * self.uv0.0 + g.y * self.uv1.0 + g.z * self.uv2.0,
+      g.x * self.uv0.1 + g.y * self.uv1.1 + g.z * self.uv2.1,
+    )
   }
 
   fn id(&self) -> usize {
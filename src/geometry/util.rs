@@ -11,4 +11,15 @@ impl UUID {
     self.i += 1;
     self.i
   }
+
+  /**
+   * n個分のidをまとめて確保し、その直前のidを返す
+   *
+   * 確保されたidは (返り値, 返り値 + n] の範囲
+   */
+  pub fn reserve(&mut self, n: usize) -> usize {
+    let offset = self.i;
+    self.i += n;
+    offset
+  }
 }
@@ -0,0 +1,132 @@
+use super::integrator::Integrator;
+use super::util::ProgressIndicator;
+use film::{Film, Splat};
+use math::*;
+use rayon::prelude::*;
+use sample::distribution::Distribution1D;
+use sampler::{PrimarySample, Sampling};
+use std::ops::{Add, Div};
+use std::sync::Mutex;
+
+/**
+ * 主標本空間でのメトロポリス光輸送 (PSSMLT)
+ *
+ * Kelemen et al., "A Simple and Robust Mutation Strategy for the Metropolis Light Transport Algorithm" (2002)
+ * 画素の位置も含めて、放射輝度の推定に使う乱数の列全体をマルコフ連鎖で変異させる
+ * 輝度に比例した密度でサンプルが集まるので、明るい経路が見つかりにくいシーンに強い
+ *
+ * 最初に独立なサンプルで画像全体の平均輝度 (正規化の定数) を求め (bootstrap)、
+ * その中から輝度に比例して選んだ点を各連鎖の初期値にする
+ * 寄与は期待値で重み付けして、提案と現在の両方の画素に足し込む
+ * 変異の総数は画素数×sppになる
+ */
+pub struct Metropolis<'a> {
+  pub film: &'a mut Film<Vector3>,
+  pub spp: usize,
+  seed: u64,
+  bootstrap: usize,
+  chains: usize,
+  sigma: f32,
+  large_step_probability: f32,
+}
+
+impl<'a> Metropolis<'a> {
+  pub fn new<'b>(film: &'b mut Film<Vector3>, spp: usize, seed: u64) -> Metropolis<'b> {
+    Metropolis {
+      film: film,
+      spp: spp,
+      seed: seed,
+      bootstrap: 100000,
+      chains: 1000,
+      sigma: 0.01,
+      large_step_probability: 0.3,
+    }
+  }
+
+  fn primary_sample(&self, stream: usize) -> PrimarySample {
+    PrimarySample::new(self.seed, stream, self.sigma, self.large_step_probability)
+  }
+}
+
+/**
+ * 連鎖の状態の評価に使う輝度
+ */
+fn luminance(l: Vector3) -> f32 {
+  (0.2126 * l.x + 0.7152 * l.y + 0.0722 * l.z).max(0.0)
+}
+
+impl<'a> Integrator<Vector3> for Metropolis<'a> {
+  fn each<F>(&mut self, f: F)
+  where
+    Vector3: Clone + Send + Sync + Add<Vector3, Output = Vector3> + Div<f32, Output = Vector3>,
+    F: Send + Sync + Fn(f32, f32) -> Vector3,
+  {
+    // 最初の2次元で画素上の位置を決める
+    let contribution = |sampler: PrimarySample| {
+      Sampling::scoped(sampler, || {
+        let uv = Sampling::get_2d();
+        (uv, f(uv.0, uv.1))
+      })
+    };
+
+    // 正規化の定数
+    let bootstrap = (0..self.bootstrap)
+      .into_par_iter()
+      .map(|i| luminance((contribution(self.primary_sample(i)).1).1))
+      .collect::<Vec<_>>();
+    let distribution = Distribution1D::new(&bootstrap);
+    let b = distribution.sum() / self.bootstrap as f32;
+    for pixel in self.film.data.iter_mut() {
+      *pixel = Vector3::zero();
+    }
+    if b <= 0.0 {
+      return;
+    }
+
+    let splat = Splat::new(self.film.width, self.film.height);
+    let total = self.film.width * self.film.height * self.spp;
+    let chains = self.chains.min(total);
+    let progress = Mutex::new(ProgressIndicator::new(chains));
+    (0..chains).into_par_iter().for_each(|chain| {
+      {
+        progress.lock().unwrap().next();
+      }
+      // 連鎖の初期値はbootstrapのサンプルから輝度に比例して選び、同じ乱数の列で再現する
+      let mut rng = self.primary_sample(self.bootstrap + chain);
+      let index = distribution.sample(rng.uniform());
+      let (mut current, (mut current_uv, mut current_l)) = contribution(self.primary_sample(index));
+      // 同じbootstrapのサンプルから始まる連鎖が同じ変異を辿らないように、変異の乱数は連鎖ごとに分ける
+      current.reseed(self.seed, self.bootstrap + chains + chain);
+      let mutations = total / chains + if chain < total % chains { 1 } else { 0 };
+      for _ in 0..mutations {
+        current.start_iteration();
+        let (proposal, (uv, l)) = contribution(current);
+        current = proposal;
+        let current_i = luminance(current_l);
+        let proposal_i = luminance(l);
+        let accept = if current_i > 0.0 {
+          (proposal_i / current_i).min(1.0)
+        } else {
+          1.0
+        };
+        if proposal_i > 0.0 {
+          splat.add(uv, l * (accept / proposal_i));
+        }
+        if current_i > 0.0 {
+          splat.add(current_uv, current_l * ((1.0 - accept) / current_i));
+        }
+        if rng.uniform() < accept {
+          current.accept();
+          current_uv = uv;
+          current_l = l;
+        } else {
+          current.reject();
+        }
+      }
+    });
+    progress.lock().unwrap().end();
+
+    // 1画素あたりspp回の変異で、画像全体の平均輝度がbになるように戻す
+    splat.apply(self.film, b / self.spp as f32);
+  }
+}
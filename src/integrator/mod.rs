@@ -1,10 +1,13 @@
 mod debug;
 mod integrator;
+mod metropolis;
 mod par_debug;
 mod par_pixel;
 mod util;
 
 pub use self::debug::*;
 pub use self::integrator::*;
+pub use self::metropolis::*;
 pub use self::par_debug::*;
 pub use self::par_pixel::*;
+pub use self::util::ProgressIndicator;
@@ -1,24 +1,27 @@
 use super::integrator::Integrator;
 use super::util::ProgressIndicator;
 use film::Film;
-use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
+use sampler::{Sampler, Sampling};
 use std::ops::{Add, Div};
 use std::sync::Mutex;
-use RNG;
 
 pub struct ParDebug<'a, Pixel> {
   pub film: &'a mut Film<Pixel>,
   pub spp: usize,
-  seed: u64,
+  sampler: &'a (dyn Sampler + Sync),
 }
 
 impl<'a, Pixel> ParDebug<'a, Pixel> {
-  pub fn new<'b>(film: &'b mut Film<Pixel>, spp: usize, seed: u64) -> ParDebug<'b, Pixel> {
+  pub fn new<'b>(
+    film: &'b mut Film<Pixel>,
+    spp: usize,
+    sampler: &'b (dyn Sampler + Sync),
+  ) -> ParDebug<'b, Pixel> {
     ParDebug {
       film: film,
       spp: spp,
-      seed: seed,
+      sampler: sampler,
     }
   }
 }
@@ -31,19 +34,14 @@ impl<'a, Pixel> Integrator<Pixel> for ParDebug<'a, Pixel> {
   {
     let chunk_size = self.film.width;
     let spp = self.spp;
+    let sampler = self.sampler;
     let uv = self.film.uv();
     let total = self.film.height * self.film.width / chunk_size;
     let progress = Mutex::new(ProgressIndicator::new(total));
 
-    println!("Using seed: {}", self.seed);
     println!("Resolution: {} x {}", self.film.width, self.film.height);
     println!("SPP: {}", self.spp);
 
-    let mut local_rng = RNG::seed_from_u64(self.seed);
-    let thread_seed = (0..total)
-      .map(|_| local_rng.gen::<u64>())
-      .collect::<Vec<_>>();
-
     self
       .film
       .data
@@ -54,12 +52,11 @@ impl<'a, Pixel> Integrator<Pixel> for ParDebug<'a, Pixel> {
           progress.lock().unwrap().next();
         }
 
-        // initialize thread local rng
-        RNG.with(|rng| *rng.borrow_mut() = RNG::seed_from_u64(thread_seed[index]));
-
         // heavy task
         slice.iter_mut().enumerate().for_each(|(i, pixel)| {
-          *pixel = (0..spp).fold(pixel.clone(), |sum, _| {
+          Sampling::start_pixel(sampler, index * chunk_size + i);
+          *pixel = (0..spp).fold(pixel.clone(), |sum, s| {
+            Sampling::start_sample(s);
             let (u, v) = uv(index * chunk_size + i);
             sum + f(u, v)
           }) / spp as f32
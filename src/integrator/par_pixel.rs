@@ -2,19 +2,26 @@ use super::integrator::Integrator;
 use super::util::ProgressIndicator;
 use film::Film;
 use rayon::prelude::*;
+use sampler::{Sampler, Sampling};
 use std::ops::{Add, Div};
 use std::sync::Mutex;
 
 pub struct ParPixel<'a, Pixel> {
   pub film: &'a mut Film<Pixel>,
   pub spp: usize,
+  sampler: &'a (dyn Sampler + Sync),
 }
 
 impl<'a, Pixel> ParPixel<'a, Pixel> {
-  pub fn new<'b>(film: &'b mut Film<Pixel>, spp: usize) -> ParPixel<'b, Pixel> {
+  pub fn new<'b>(
+    film: &'b mut Film<Pixel>,
+    spp: usize,
+    sampler: &'b (dyn Sampler + Sync),
+  ) -> ParPixel<'b, Pixel> {
     ParPixel {
       film: film,
       spp: spp,
+      sampler: sampler,
     }
   }
 }
@@ -26,6 +33,7 @@ impl<'a, Pixel> Integrator<Pixel> for ParPixel<'a, Pixel> {
     F: Send + Sync + Fn(f32, f32) -> Pixel,
   {
     let spp = self.spp;
+    let sampler = self.sampler;
     let uv = self.film.uv();
     let total = self.film.height * self.film.width;
     let progress = Mutex::new(ProgressIndicator::new(total));
@@ -40,7 +48,9 @@ impl<'a, Pixel> Integrator<Pixel> for ParPixel<'a, Pixel> {
           progress.lock().unwrap().next();
         }
         // heavy task
-        *pixel = (0..spp).fold(pixel.clone(), |sum, _| {
+        Sampling::start_pixel(sampler, index);
+        *pixel = (0..spp).fold(pixel.clone(), |sum, i| {
+          Sampling::start_sample(i);
           let (u, v) = uv(index);
           sum + f(u, v)
         }) / spp as f32
@@ -0,0 +1,712 @@
+use super::radiance::Radiance;
+use super::RussianRoulette;
+use acceleration::{Acceleration, AccelerationUtility};
+use camera::Camera;
+use environment::Environment;
+use film::Splat;
+use math::*;
+use object::{AreaLight, Connection, Interaction, LightSampler};
+use ray::Ray;
+use sample::mis::MIS;
+
+/**
+ * 部分パスの頂点の種類
+ */
+enum Kind<'a> {
+  // カメラ上の点
+  Camera(Vector3),
+  // 光源上の点 (法線は幾何的な法線の表側)
+  Light {
+    position: Vector3,
+    normal: Vector3,
+    light: &'a AreaLight,
+  },
+  // 物体表面での散乱
+  Surface(Interaction<'a>),
+}
+
+/**
+ * 部分パスの頂点
+ */
+struct Vertex<'a> {
+  kind: Kind<'a>,
+  // 部分パスの始点からこの頂点までの寄与
+  throughput: Vector3,
+  // 部分パスの向きにこの頂点が生成される確率密度 (面積測度)
+  pdf_fwd: f32,
+  // 逆向きの部分パスでこの頂点が生成される確率密度 (面積測度)
+  pdf_rev: f32,
+  is_delta: bool,
+}
+
+impl<'a> Vertex<'a> {
+  fn position(&self) -> Vector3 {
+    match self.kind {
+      Kind::Camera(position) => position,
+      Kind::Light { position, .. } => position,
+      Kind::Surface(ref interaction) => interaction.intersection.position,
+    }
+  }
+
+  /**
+   * 面の法線 (カメラは面を持たない)
+   */
+  fn normal(&self) -> Option<Vector3> {
+    match self.kind {
+      Kind::Camera(_) => None,
+      Kind::Light { normal, .. } => Some(normal),
+      Kind::Surface(ref interaction) => Some(interaction.intersection.normal),
+    }
+  }
+
+  fn interaction(&self) -> &Interaction<'a> {
+    match self.kind {
+      Kind::Surface(ref interaction) => interaction,
+      _ => unreachable!("not a surface vertex"),
+    }
+  }
+
+  /**
+   * この頂点からnextへの方向の立体角測度の確率密度 -> nextでの面積測度の確率密度
+   */
+  fn to_area(&self, pdf: f32, next: &Vertex) -> f32 {
+    let path = next.position() - self.position();
+    let sqr_distance = path.sqr_norm();
+    match next.normal() {
+      Some(n) => pdf * n.dot(path).abs() / (sqr_distance * sqr_distance.sqrt()),
+      None => pdf / sqr_distance,
+    }
+  }
+}
+
+/**
+ * 双方向パストレーシング
+ *
+ * カメラと物体の光源の双方から部分パスを生成し、全ての接続の仕方をMISで重み付けして足し合わせる
+ * カメラに直接接続した光源サブパスの寄与 (light tracing) は splat に足し込む
+ *
+ * 環境光の寄与は光源サブパスを使わず、ExplicitLight と同じく
+ * カメラサブパスの各頂点での環境光のサンプリングとMISで求める
+ */
+pub struct Bidirectional<'a, S, C>
+where
+  S: Acceleration,
+  C: Camera,
+{
+  structure: &'a S,
+  camera: &'a C,
+  environment: &'a (dyn Environment + Send + Sync),
+  russian_roulette: RussianRoulette,
+  light_sampler: LightSampler<'a>,
+  splat: &'a Splat,
+  // 環境光を明示的にサンプリングするかどうか
+  sample_environment: bool,
+}
+
+impl<'a, S, C> Bidirectional<'a, S, C>
+where
+  S: Acceleration + 'a,
+  C: Camera,
+{
+  pub fn new(
+    structure: &'a S,
+    camera: &'a C,
+    environment: &'a (dyn Environment + Send + Sync),
+    russian_roulette: RussianRoulette,
+    splat: &'a Splat,
+  ) -> Self {
+    Bidirectional {
+      structure: structure,
+      camera: camera,
+      environment: environment,
+      russian_roulette: russian_roulette,
+      light_sampler: structure.light_sampler(environment),
+      splat: splat,
+      sample_environment: environment.mean_radiance().max() > 0.0,
+    }
+  }
+
+  /**
+   * カメラサブパスと、何にも衝突せずに外に出たパスの環境光の寄与
+   */
+  fn camera_subpath(&self, ray: Ray) -> (Vec<Vertex<'a>>, Vector3) {
+    let mut vertices = vec![Vertex {
+      kind: Kind::Camera(ray.origin),
+      throughput: Vector3::fill(1.0),
+      pdf_fwd: 1.0,
+      pdf_rev: 0.0,
+      is_delta: false,
+    }];
+    match self.structure.interact(ray) {
+      None => return (vertices, self.environment.radiance(ray.direction)),
+      Some(interaction) => {
+        let pdf = self
+          .camera
+          .importance(interaction.intersection.position)
+          .map(|v| v.pdf.0)
+          .unwrap_or(0.0);
+        let is_delta = interaction.is_delta();
+        let mut vertex = Vertex {
+          kind: Kind::Surface(interaction),
+          throughput: Vector3::fill(1.0),
+          pdf_fwd: 0.0,
+          pdf_rev: 0.0,
+          is_delta: is_delta,
+        };
+        vertex.pdf_fwd = vertices[0].to_area(pdf, &vertex);
+        vertices.push(vertex);
+      }
+    }
+    let environment_contrib = self.random_walk(&mut vertices, true);
+    (vertices, environment_contrib)
+  }
+
+  /**
+   * 物体の光源から始まる光源サブパス
+   */
+  fn light_subpath(&self) -> Vec<Vertex<'a>> {
+    let mut vertices = Vec::new();
+    let emitter = match self.light_sampler.sample_emitter() {
+      None => return vertices,
+      Some(emitter) => emitter,
+    };
+    let pdf_position = emitter.pdf.0;
+    // 放射輝度は放射方向によるので、光源の頂点には位置の確率密度だけを持たせる
+    vertices.push(Vertex {
+      kind: Kind::Light {
+        position: emitter.position,
+        normal: emitter.normal,
+        light: emitter.light,
+      },
+      throughput: Vector3::fill(1.0 / pdf_position),
+      pdf_fwd: pdf_position,
+      pdf_rev: 0.0,
+      is_delta: false,
+    });
+    // 放射方向を配光とcos項に (おおよそ) 比例してサンプリング
+    let direction = emitter.light.sample_direction(emitter.normal);
+    let wo = direction.value;
+    let cos = wo.dot(emitter.normal).abs();
+    let pdf_direction = direction.pdf.0;
+    if cos <= 0.0 || pdf_direction <= 0.0 {
+      return vertices;
+    }
+    let emittance = emitter.light.radiance(emitter.uv, emitter.normal, wo);
+    let ray = Ray {
+      from: None,
+      origin: emitter.position + wo * EPS,
+      direction: wo,
+    };
+    match self.structure.interact(ray) {
+      None => return vertices,
+      Some(interaction) => {
+        let is_delta = interaction.is_delta();
+        let mut vertex = Vertex {
+          kind: Kind::Surface(interaction),
+          throughput: emittance * (cos / (pdf_position * pdf_direction)),
+          pdf_fwd: 0.0,
+          pdf_rev: 0.0,
+          is_delta: is_delta,
+        };
+        vertex.pdf_fwd = vertices[0].to_area(pdf_direction, &vertex);
+        vertices.push(vertex);
+      }
+    }
+    self.random_walk(&mut vertices, false);
+    vertices
+  }
+
+  /**
+   * マテリアルに基づいて部分パスを延ばす
+   *
+   * カメラサブパスでは視線の向き、光源サブパスでは光の進む向きにBSDFを評価する
+   * カメラサブパスが何にも衝突せずに外に出た場合は環境光の寄与を返す
+   */
+  fn random_walk(&self, vertices: &mut Vec<Vertex<'a>>, is_camera: bool) -> Vector3 {
+    let mut environment_contrib = Vector3::zero();
+    // 光源サブパスの寄与は放射輝度を確率密度で割った大きな値から始まるので、
+    // ロシアンルーレットは最初の衝突点での寄与からの比で行う
+    let initial = vertices[1].throughput.max();
+    if initial <= 0.0 {
+      return environment_contrib;
+    }
+    loop {
+      let n = vertices.len();
+      let (next, throughput, pdf_fwd, pdf_rev) = {
+        let current = &vertices[n - 1];
+        let interaction = current.interaction();
+        // ロシアンルーレットで打ち切る
+        let continue_probability = match self
+          .russian_roulette
+          .survive(n - 2, current.throughput / initial)
+        {
+          None => break,
+          Some(p) => p,
+        };
+        let wi = (vertices[n - 2].position() - current.position()).normalize();
+        let sample = interaction.sample_material();
+        let wo = sample.value;
+        let f = if is_camera {
+          interaction.bsdf(wo)
+        } else {
+          interaction.bsdf_between(wo, wi)
+        };
+        let cos = if is_camera {
+          interaction.cos(wo).abs()
+        } else {
+          interaction.adjoint_cos(wi, wo)
+        };
+        let throughput = current.throughput * f * (cos / sample.pdf.0 / continue_probability);
+        let pdf_rev = interaction.bsdf_pdf_between(wo, wi).0;
+        match interaction.trace(self.structure, wo) {
+          Connection::Hit(geom) => (geom.next, throughput, sample.pdf.0, pdf_rev),
+          Connection::Escape => {
+            if is_camera {
+              // マテリアルに比例した環境光の重点的サンプリング
+              let mis_weight = if current.is_delta || !self.sample_environment {
+                1.0
+              } else {
+                sample.pdf.power_hulistic(self.environment.pdf(wo), 2)
+              };
+              environment_contrib += throughput * self.environment.radiance(wo) * mis_weight;
+            }
+            break;
+          }
+          Connection::Blocked => break,
+        }
+      };
+      let is_delta = next.is_delta();
+      let mut vertex = Vertex {
+        kind: Kind::Surface(next),
+        throughput: throughput,
+        pdf_fwd: 0.0,
+        pdf_rev: 0.0,
+        is_delta: is_delta,
+      };
+      vertex.pdf_fwd = vertices[n - 1].to_area(pdf_fwd, &vertex);
+      vertices[n - 2].pdf_rev = vertices[n - 1].to_area(pdf_rev, &vertices[n - 2]);
+      vertices.push(vertex);
+    }
+    environment_contrib
+  }
+
+  /**
+   * 頂点vertexで前の頂点prevからnextへ散乱する確率密度 (nextでの面積測度)
+   */
+  fn pdf(&self, vertex: &Vertex, prev: Option<&Vertex>, next: &Vertex) -> f32 {
+    let wo = (next.position() - vertex.position()).normalize();
+    let pdf = match vertex.kind {
+      Kind::Camera(_) => self
+        .camera
+        .importance(next.position())
+        .map(|v| v.pdf.0)
+        .unwrap_or(0.0),
+      Kind::Light { normal, light, .. } => light.pdf_direction(normal, wo).0,
+      Kind::Surface(ref interaction) => {
+        let prev = prev.expect("surface vertex without previous vertex");
+        let wi = (prev.position() - vertex.position()).normalize();
+        interaction.bsdf_pdf_between(wi, wo).0
+      }
+    };
+    vertex.to_area(pdf, next)
+  }
+
+  /**
+   * カメラサブパスの頂点vertexが光源として放射する方向nextの確率密度 (nextでの面積測度)
+   */
+  fn pdf_emission(&self, vertex: &Vertex, next: &Vertex) -> f32 {
+    let wo = (next.position() - vertex.position()).normalize();
+    vertex.to_area(vertex.interaction().emission_pdf(wo).0, next)
+  }
+
+  /**
+   * 光源サブパスのs個の頂点とカメラサブパスのt個の頂点をつないだパスのMISの重み
+   *
+   * 他の接続の仕方で同じパスが生成される確率密度との比からべき乗ヒューリスティックで求める
+   */
+  fn mis_weight(&self, camera: &[Vertex<'a>], light: &[Vertex<'a>], s: usize, t: usize) -> f32 {
+    if s + t == 2 {
+      return 1.0;
+    }
+    // (pdf_fwd, pdf_rev, is_delta)
+    let mut c = camera[..t]
+      .iter()
+      .map(|v| (v.pdf_fwd, v.pdf_rev, v.is_delta))
+      .collect::<Vec<_>>();
+    let mut l = light[..s]
+      .iter()
+      .map(|v| (v.pdf_fwd, v.pdf_rev, v.is_delta))
+      .collect::<Vec<_>>();
+    let pt = &camera[t - 1];
+    let pt_minus = if t >= 2 { Some(&camera[t - 2]) } else { None };
+    let qs = if s >= 1 { Some(&light[s - 1]) } else { None };
+    let qs_minus = if s >= 2 { Some(&light[s - 2]) } else { None };
+    // 接続する頂点での逆向きの確率密度を接続したパスに合わせて求め直す
+    c[t - 1].2 = false;
+    c[t - 1].1 = match qs {
+      Some(qs) => self.pdf(qs, qs_minus, pt),
+      None => pt
+        .interaction()
+        .emitter_pdf(&self.light_sampler)
+        .map(|v| v.0)
+        .unwrap_or(0.0),
+    };
+    if let Some(pt_minus) = pt_minus {
+      c[t - 2].1 = match qs {
+        Some(qs) => self.pdf(pt, Some(qs), pt_minus),
+        None => self.pdf_emission(pt, pt_minus),
+      };
+    }
+    if let Some(qs) = qs {
+      l[s - 1].2 = false;
+      l[s - 1].1 = self.pdf(pt, pt_minus, qs);
+      if let Some(qs_minus) = qs_minus {
+        l[s - 2].1 = self.pdf(qs, Some(pt), qs_minus);
+      }
+    }
+    // 完全鏡面反射の確率密度は比を取るときに打ち消し合うものとして扱う
+    let remap = |p: f32| if p != 0.0 { p } else { 1.0 };
+    let mut sum = 0.0;
+    let mut ratio = 1.0;
+    for i in (1..t).rev() {
+      ratio *= remap(c[i].1) / remap(c[i].0);
+      if !c[i].2 && !c[i - 1].2 {
+        sum += ratio * ratio;
+      }
+    }
+    ratio = 1.0;
+    for i in (0..s).rev() {
+      ratio *= remap(l[i].1) / remap(l[i].0);
+      let is_delta_prev = i > 0 && l[i - 1].2;
+      if !l[i].2 && !is_delta_prev {
+        sum += ratio * ratio;
+      }
+    }
+    1.0 / (1.0 + sum)
+  }
+
+  /**
+   * 光源サブパスのs個の頂点とカメラサブパスのt (>= 2) 個の頂点をつないだパスの寄与
+   */
+  fn connect(&self, camera: &[Vertex<'a>], light: &[Vertex<'a>], s: usize, t: usize) -> Vector3 {
+    let pt = &camera[t - 1];
+    let interaction = pt.interaction();
+    if s == 0 {
+      // カメラサブパスが光源に衝突した
+      return pt.throughput * interaction.emittance();
+    }
+    let qs = &light[s - 1];
+    if pt.is_delta || qs.is_delta {
+      return Vector3::zero();
+    }
+    let geom = match interaction.connect_point(self.structure, qs.position()) {
+      None => return Vector3::zero(),
+      Some(geom) => geom,
+    };
+    // 接続先のcos項は、光源では幾何的な法線、光源サブパスの頂点では随伴BSDFの補正を含めたもの
+    let (scatter, cos) = match qs.kind {
+      // 光源が接続の方向に放射する場合のみ受け取る (片面の光源は表側のみ)
+      Kind::Light { .. } => (geom.next.emittance() / qs.pdf_fwd, (-geom.wo).dot(geom.n2)),
+      _ => {
+        let wi = -geom.wo;
+        let wo = (light[s - 2].position() - qs.position()).normalize();
+        if !scatters(qs.interaction(), wi, wo) {
+          return Vector3::zero();
+        }
+        (
+          qs.throughput * qs.interaction().bsdf_between(wi, wo),
+          qs.interaction().adjoint_cos(wo, wi),
+        )
+      }
+    };
+    let g = geom.cos() * cos / (geom.x2 - geom.x_offset).sqr_norm();
+    pt.throughput * geom.bsdf() * scatter * g
+  }
+
+  /**
+   * 光源サブパスのs (>= 2) 個の頂点をカメラに直接つないだパスの寄与と、フィルム上の座標
+   */
+  fn connect_camera(&self, light: &[Vertex<'a>], s: usize) -> Option<((f32, f32), Vector3)> {
+    let qs = &light[s - 1];
+    if qs.is_delta {
+      return None;
+    }
+    let interaction = qs.interaction();
+    let importance = self.camera.importance(qs.position())?;
+    let path = importance.position - qs.position();
+    let wi = path.normalize();
+    let wo = (light[s - 2].position() - qs.position()).normalize();
+    if !scatters(interaction, wi, wo)
+      || !interaction.unoccluded(self.structure, importance.position)
+    {
+      return None;
+    }
+    let g = interaction.adjoint_cos(wo, wi) * importance.importance / path.sqr_norm();
+    Some((
+      importance.uv,
+      qs.throughput * interaction.bsdf_between(wi, wo) * g,
+    ))
+  }
+
+  /**
+   * カメラサブパスの頂点で環境光を明示的にサンプリングした寄与
+   */
+  fn sample_environment(&self, vertex: &Vertex) -> Vector3 {
+    if !self.sample_environment || vertex.is_delta {
+      return Vector3::zero();
+    }
+    let interaction = vertex.interaction();
+    let sample = self.environment.sample();
+    let wo = sample.value;
+    if sample.pdf.0 <= 0.0 || !interaction.connect_environment(self.structure, wo) {
+      return Vector3::zero();
+    }
+    let li = self.environment.radiance(wo);
+    let mis_weight = sample.pdf.power_hulistic(interaction.bsdf_pdf(wo), 2);
+    vertex.throughput * li * interaction.bsdf(wo) * interaction.weight(wo, sample.pdf) * mis_weight
+  }
+
+  fn within_depth(&self, depth: usize) -> bool {
+    self
+      .russian_roulette
+      .max_depth
+      .map(|max| depth <= max)
+      .unwrap_or(true)
+  }
+}
+
+/**
+ * 衝突点で方向wiとwoの間を散乱できるかどうか (面の両側をまたぐのは透過するマテリアルに限られる)
+ */
+fn scatters(interaction: &Interaction, wi: Vector3, wo: Vector3) -> bool {
+  let n = interaction.intersection.normal;
+  wi.dot(n) * wo.dot(n) > 0.0 || interaction.is_transmissive()
+}
+
+impl<'a, S, C> Radiance for Bidirectional<'a, S, C>
+where
+  S: Acceleration,
+  C: Camera,
+{
+  fn radiance(&self, ray: Ray) -> Vector3 {
+    let (camera, mut l) = self.camera_subpath(ray);
+    let light = self.light_subpath();
+    // 環境光
+    for (i, vertex) in camera.iter().enumerate().skip(1) {
+      if self.within_depth(i) {
+        l += self.sample_environment(vertex);
+      }
+    }
+    // 物体の光源
+    for t in 1..camera.len() + 1 {
+      for s in 0..light.len() + 1 {
+        // 光源サブパスの頂点をそのままカメラに写すことはしない
+        if s + t < 2 || (s == 1 && t == 1) || !self.within_depth(s + t - 2) {
+          continue;
+        }
+        if t == 1 {
+          if let Some((uv, contrib)) = self.connect_camera(&light, s) {
+            if contrib.sqr_norm() > 0.0 {
+              self
+                .splat
+                .add(uv, contrib * self.mis_weight(&camera, &light, s, t));
+            }
+          }
+        } else {
+          let contrib = self.connect(&camera, &light, s, t);
+          if contrib.sqr_norm() > 0.0 {
+            l += contrib * self.mis_weight(&camera, &light, s, t);
+          }
+        }
+      }
+    }
+    l
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::{lambertian, quad, ExplicitLight};
+  use super::*;
+  use acceleration::Linear;
+  use camera::IdealPinhole;
+  use environment::Constant as ConstantEnvironment;
+  use film::Film;
+  use geometry::UUID;
+  use material::Material;
+  use object::Object;
+  use sampler::{Independent, Sampling};
+  use std::mem;
+  use texture::Constant;
+
+  // 床と壁を下向きの面光源で照らす
+  fn scene<'a>(
+    white: &'a Box<dyn Material + Send + Sync>,
+    black: &'a Box<dyn Material + Send + Sync>,
+    light: &'a AreaLight,
+  ) -> Linear<'a> {
+    let mut uuid = UUID::new();
+    let mut objects = Vec::new();
+    let floor = [
+      Vector3::new(-10.0, -10.0, 0.0),
+      Vector3::new(10.0, -10.0, 0.0),
+      Vector3::new(10.0, 10.0, 0.0),
+      Vector3::new(-10.0, 10.0, 0.0),
+    ];
+    let wall = [
+      Vector3::new(1.0, -10.0, 0.0),
+      Vector3::new(1.0, -10.0, 3.0),
+      Vector3::new(1.0, 10.0, 3.0),
+      Vector3::new(1.0, 10.0, 0.0),
+    ];
+    for g in quad(floor, &mut uuid)
+      .into_iter()
+      .chain(quad(wall, &mut uuid))
+    {
+      objects.push(Object::new(g, Matrix4::unit(), white));
+    }
+    let panel = [
+      Vector3::new(-0.5, -0.5, 2.0),
+      Vector3::new(-0.5, 0.5, 2.0),
+      Vector3::new(0.5, 0.5, 2.0),
+      Vector3::new(0.5, -0.5, 2.0),
+    ];
+    for g in quad(panel, &mut uuid) {
+      objects.push(Object::new(g, Matrix4::unit(), black).with_area_light(light));
+    }
+    Linear::new(objects)
+  }
+
+  fn camera() -> IdealPinhole {
+    IdealPinhole::new(
+      0.8,
+      1.0,
+      Matrix4::look_at(
+        Vector3::new(0.0, 0.0, 1.0),
+        Vector3::zero(),
+        Vector3::new(0.0, 1.0, 0.0),
+      ),
+    )
+  }
+
+  #[test]
+  fn explicit_light_agreement_test() {
+    // カメラから見た平均の放射輝度は、フィルムに足し込んだ寄与も含めて ExplicitLight の推定と一致する
+    let white = lambertian(0.5);
+    let black = lambertian(0.0);
+    let light = AreaLight::diffuse(Box::new(Constant::new(Vector3::fill(5.0))));
+    let structure = scene(&white, &black, &light);
+    let environment = ConstantEnvironment::black();
+    let camera = camera();
+    let russian_roulette = RussianRoulette::default();
+    let (width, height) = (8, 8);
+    let count = 100000;
+    let splat = Splat::new(width, height);
+    let bidirectional =
+      Bidirectional::new(&structure, &camera, &environment, russian_roulette, &splat);
+    let explicit_light = ExplicitLight::new(&structure, &environment, &[], russian_roulette);
+    // 乱数によって許容誤差を外れないようにシードを固定する
+    Sampling::start_pixel(&Independent::new(1), 0);
+    let mut expected = 0.0;
+    let mut estimate = 0.0;
+    for _ in 0..count {
+      let (u, v) = Sampling::get_2d();
+      let ray = camera.sample(u, v).value;
+      expected += explicit_light.radiance(ray).y;
+      estimate += bidirectional.radiance(ray).y;
+    }
+    let expected = expected / count as f32;
+    let mut film = Film::new(Vector3::zero(), width, height);
+    splat.apply(&mut film, (width * height) as f32 / count as f32);
+    let estimate = estimate / count as f32
+      + film.data.iter().map(|v| v.y).sum::<f32>() / (width * height) as f32;
+    assert!(
+      (estimate - expected).abs() < 0.03 * expected,
+      "{} {}",
+      estimate,
+      expected
+    );
+  }
+
+  #[test]
+  fn mis_weight_test() {
+    // カメラから光源に到達した同じパスを全ての (s, t) の接続で生成したときのMISの重みの和は1
+    let white = lambertian(0.5);
+    let black = lambertian(0.0);
+    let light = AreaLight::diffuse(Box::new(Constant::new(Vector3::fill(5.0))));
+    let structure = scene(&white, &black, &light);
+    let environment = ConstantEnvironment::black();
+    let camera = camera();
+    let splat = Splat::new(1, 1);
+    let bidirectional = Bidirectional::new(
+      &structure,
+      &camera,
+      &environment,
+      RussianRoulette::default(),
+      &splat,
+    );
+    let sampler = Independent::new(1);
+    let mut tested = 0;
+    for i in 0..1000 {
+      let ray = {
+        Sampling::start_pixel(&sampler, i);
+        let (u, v) = Sampling::get_2d();
+        camera.sample(u, v).value
+      };
+      // 光源に衝突したカメラサブパスの頂点までを1本のパスとする
+      Sampling::start_pixel(&sampler, i);
+      let (path, _) = bidirectional.camera_subpath(ray);
+      let k = match path
+        .iter()
+        .skip(3)
+        .position(|v| v.interaction().emittance().max() > 0.0)
+      {
+        None => continue,
+        Some(j) => j + 4,
+      };
+      let mut sum = 0.0;
+      for s in 0..k {
+        let t = k - s;
+        // 同じ乱数で同じカメラサブパスを生成し直し、後ろのs個を光源サブパスとして逆向きに並べる
+        Sampling::start_pixel(&sampler, i);
+        let (mut camera_vertices, _) = bidirectional.camera_subpath(ray);
+        camera_vertices.truncate(k);
+        let mut light_vertices = camera_vertices.split_off(t);
+        light_vertices.reverse();
+        for v in light_vertices.iter_mut() {
+          mem::swap(&mut v.pdf_fwd, &mut v.pdf_rev);
+        }
+        // 光源サブパスの始点と次の頂点は、光源上の点と放射方向のサンプリングの確率密度で生成される
+        if s >= 1 {
+          let emitter_pdf = light_vertices[0]
+            .interaction()
+            .emitter_pdf(&bidirectional.light_sampler)
+            .unwrap()
+            .0;
+          let position = light_vertices[0].position();
+          let normal = light_vertices[0].interaction().intersection.normal;
+          let pdf_rev = light_vertices[0].pdf_rev;
+          light_vertices[0] = Vertex {
+            kind: Kind::Light {
+              position: position,
+              normal: normal,
+              light: &light,
+            },
+            throughput: Vector3::zero(),
+            pdf_fwd: emitter_pdf,
+            pdf_rev: pdf_rev,
+            is_delta: false,
+          };
+        }
+        if s >= 2 {
+          light_vertices[1].pdf_fwd =
+            bidirectional.pdf(&light_vertices[0], None, &light_vertices[1]);
+        }
+        sum += bidirectional.mis_weight(&camera_vertices, &light_vertices, s, t);
+      }
+      assert!((sum - 1.0).abs() < 1e-3, "{} {}", i, sum);
+      tested += 1;
+    }
+    assert!(tested > 0);
+  }
+}
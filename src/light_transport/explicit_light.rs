@@ -1,9 +1,13 @@
+use super::path::PathState;
 use super::radiance::Radiance;
-use acceleration::{Acceleration, AccelerationUtility};
+use super::RussianRoulette;
+use acceleration::Acceleration;
+use environment::Environment;
 use math::*;
-use object::{GeomWeight, Interaction, LightSampler};
+use object::{Connection, DeltaLight, GeomWeight, Interaction, LightSample, LightSampler};
 use ray::Ray;
 use sample::mis::MIS;
+use sample::pdf;
 use util::*;
 
 pub struct ExplicitLight<'a, S>
@@ -11,6 +15,8 @@ where
   S: Acceleration,
 {
   structure: &'a S,
+  environment: &'a (dyn Environment + Send + Sync),
+  russian_roulette: RussianRoulette,
   light_sampler: LightSampler<'a>,
 }
 
@@ -18,104 +24,153 @@ impl<'a, S> ExplicitLight<'a, S>
 where
   S: Acceleration + 'a,
 {
-  pub fn new(structure: &'a S) -> Self {
+  pub fn new(
+    structure: &'a S,
+    environment: &'a (dyn Environment + Send + Sync),
+    delta_lights: &'a [DeltaLight],
+    russian_roulette: RussianRoulette,
+  ) -> Self {
     ExplicitLight {
       structure: structure,
-      light_sampler: structure.light_sampler(),
+      environment: environment,
+      russian_roulette: russian_roulette,
+      light_sampler: LightSampler::new(structure.objects(), delta_lights, environment),
     }
   }
 
-  fn radiance_recursive(&self, point: &Interaction, depth: usize) -> Vector3 {
-    // 視線サブパスが直接光源に接続された場合のみ寄与を取る
-    // 光源からの寄与は前のパスで取っているので含めない
-    let le = if depth == 0 {
-      point.emittance()
-    } else {
-      Vector3::zero()
-    };
-    // スタックオーバーフロー防止
-    // TODO: ロシアンルーレット
-    if depth > 5 {
-      return le;
-    }
+  fn radiance_iterative(&self, first: Interaction<'a>) -> Vector3 {
+    let mut l = Vector3::zero();
+    let mut state = PathState::new(first);
+    loop {
+      // マテリアルに比例した光源サブパスの重点的サンプリング
+      // カメラと完全鏡面の直後では光源を明示的にサンプリングしていないので、重みは1
+      let le = state.point.emittance();
+      if le.sqr_norm() > 0.0 {
+        let mis_weight = state
+          .prev_bsdf_pdf
+          .and_then(|bsdf_pdf| {
+            state
+              .point
+              .light_pdf_solid_angle(&self.light_sampler)
+              .map(|light_pdf| bsdf_pdf.power_hulistic(light_pdf, 2))
+          })
+          .unwrap_or(1.0);
+        debug_assert!(mis_weight.is_finite());
+        l += state.throughput * le * mis_weight;
+      }
+      // ロシアンルーレットで打ち切る
+      // 継続した場合は寄与を継続確率で割る
+      let continue_probability = match self.russian_roulette.survive(state.depth, state.throughput)
+      {
+        None => break,
+        Some(p) => p,
+      };
+      state.throughput /= continue_probability;
 
-    // マテリアルに基づいて方向ベクトルをサンプリング
-    let material_sample = point.sample_material();
-    debug_assert!(
-      material_sample.value.is_finite(),
-      "{}",
-      material_sample.value
-    );
+      // 完全鏡面反射の場合はNEEを行わない
+      let is_delta = state.point.is_delta();
+      if !is_delta {
+        l += state.throughput * self.sample_light(&state.point);
+      }
 
-    // 完全鏡面反射の場合はNEEを行わない
-    if point.is_delta() {
-      let contrib = match point.connect_direction(self.structure, material_sample.value) {
-        None => Vector3::zero(),
-        Some(geom) => {
-          let li = geom.next.emittance();
-          let li_scatter = self.radiance_recursive(&geom.next, depth + 1);
-          (li + li_scatter) * geom.bsdf() * geom.weight(material_sample.pdf)
+      // マテリアルに基づいて方向ベクトルをサンプリング
+      let material_sample = state.point.sample_material();
+      debug_assert!(
+        material_sample.value.is_finite(),
+        "{}",
+        material_sample.value
+      );
+
+      // 衝突点から方向ベクトルを使ってパスを接続
+      let wo = material_sample.value;
+      let (next, f, bsdf_pdf) = match state.point.trace(self.structure, wo) {
+        Connection::Blocked => break,
+        Connection::Escape => {
+          let li = self.environment.radiance(wo);
+          let f = state.point.bsdf(wo) * state.point.weight(wo, material_sample.pdf);
+          // マテリアルに比例した環境光の重点的サンプリング
+          let mis_weight = if is_delta {
+            1.0
+          } else {
+            let bsdf_pdf = state.point.bsdf_pdf(wo);
+            self
+              .light_sampler
+              .environment_pdf(wo)
+              .map(|pdf| bsdf_pdf.power_hulistic(pdf, 2))
+              .unwrap_or(1.0)
+          };
+          l += state.throughput * li * f * mis_weight;
+          break;
+        }
+        Connection::Hit(geom) => {
+          let f = geom.bsdf() * geom.weight(material_sample.pdf);
+          let bsdf_pdf = if is_delta {
+            None
+          } else {
+            let bsdf_pdf = geom.bsdf_pdf();
+            debug_assert!(bsdf_pdf.0.is_finite());
+            Some(bsdf_pdf)
+          };
+          (geom.next, f, bsdf_pdf)
         }
       };
-      return le + contrib;
+      state = state.advance(next, f, bsdf_pdf);
     }
+    l
+  }
 
-    // 衝突点から方向ベクトルを使ってパスを接続
-    let material_oriented_contrib =
-      match point.connect_direction(self.structure, material_sample.value) {
+  /**
+   * 明示的に光源をサンプリングして衝突点と接続した寄与
+   */
+  fn sample_light(&self, point: &Interaction) -> Vector3 {
+    // 明示的に光源の座標をサンプリング
+    let light_sample = self.light_sampler.sample();
+    // 衝突点と光源を接続して光源サブパスを生成
+    match light_sample {
+      None => Vector3::zero(),
+      Some(LightSample::Area(sample)) => match point.connect_point(self.structure, sample.value) {
         None => Vector3::zero(),
         Some(geom) => {
-          // マテリアルに比例した光源サブパスの重点的サンプリング
+          // 明示的な光源サブパスの重点的サンプリング
           let li = geom.next.emittance();
-          let light_pdf = geom.light_pdf(&self.light_sampler);
-          debug_assert!(light_pdf.map(|v| v.0).unwrap_or(0.0).is_finite());
+          let light_pdf = sample
+            .pdf
+            .solid_angle_measure(geom.x_offset, geom.x2, geom.n2);
+          debug_assert!(light_pdf.0.is_finite());
+          debug_assert!(light_pdf.0 > 0.0);
           let bsdf_pdf = geom.bsdf_pdf();
           debug_assert!(bsdf_pdf.0.is_finite());
-          let light_contrib = light_pdf
-            .map(|pdf| {
-              let mis_weight = bsdf_pdf
-                .power_hulistic(pdf.solid_angle_measure(geom.x_offset, geom.x2, geom.n2), 2);
-              debug_assert!(mis_weight.is_finite());
-              debug_assert!(geom.bsdf().is_finite());
-              debug_assert!(geom.weight(bsdf_pdf).is_finite());
-              li * geom.bsdf() * geom.weight(bsdf_pdf) * mis_weight
-            })
-            .unwrap_or(Vector3::zero());
-          debug_assert!(light_contrib.is_finite());
-          // 接続先から再帰的にパスを生成して散乱成分の寄与を蓄積する
-          let li_scatter = self.radiance_recursive(&geom.next, depth + 1);
-          let scatter_contrib = li_scatter * geom.bsdf() * geom.weight(material_sample.pdf);
-          debug_assert!(scatter_contrib.is_finite());
-          light_contrib + scatter_contrib
+          let mis_weight = light_pdf.power_hulistic(bsdf_pdf, 2);
+          debug_assert!(mis_weight.is_finite());
+          li * geom.bsdf() * geom.weight(light_pdf) * mis_weight
         }
-      };
-
-    // 明示的に光源の座標をサンプリング
-    let light_sample = self.light_sampler.sample();
-    // 衝突点と光源を接続して光源サブパスを生成
-    let light_oriented_contrib = light_sample
-      .map(
-        |sample| match point.connect_point(self.structure, sample.value) {
-          None => Vector3::zero(),
-          Some(geom) => {
-            // 明示的な光源サブパスの重点的サンプリング
-            let li = geom.next.emittance();
-            let light_pdf = sample
-              .pdf
-              .solid_angle_measure(geom.x_offset, geom.x2, geom.n2);
-            debug_assert!(light_pdf.0.is_finite());
-            debug_assert!(light_pdf.0 > 0.0);
-            let bsdf_pdf = geom.bsdf_pdf();
-            debug_assert!(bsdf_pdf.0.is_finite());
-            let mis_weight = light_pdf.power_hulistic(bsdf_pdf, 2);
-            debug_assert!(mis_weight.is_finite());
-            li * geom.bsdf() * geom.weight(light_pdf) * mis_weight
-          }
-        },
-      )
-      .unwrap_or(Vector3::zero());
-
-    le + material_oriented_contrib + light_oriented_contrib
+      },
+      Some(LightSample::Delta { light, probability }) => {
+        let incidence = match light.incidence(point.intersection.position) {
+          None => return Vector3::zero(),
+          Some(incidence) => incidence,
+        };
+        let wo = incidence.direction;
+        if !point.connect_light(self.structure, wo, incidence.distance) {
+          return Vector3::zero();
+        }
+        // BSDFのサンプリングでは点光源などに到達しないので、MISの重みは1
+        // (cos項は確率密度1の幾何項として求める)
+        incidence.irradiance * point.bsdf(wo) * point.weight(wo, pdf::SolidAngle(1.0)) / probability
+      }
+      Some(LightSample::Environment(sample)) => {
+        let wo = sample.value;
+        if sample.pdf.0 > 0.0 && point.connect_environment(self.structure, wo) {
+          // 明示的な環境光の重点的サンプリング
+          let li = self.environment.radiance(wo);
+          let bsdf_pdf = point.bsdf_pdf(wo);
+          let mis_weight = sample.pdf.power_hulistic(bsdf_pdf, 2);
+          li * point.bsdf(wo) * point.weight(wo, sample.pdf) * mis_weight
+        } else {
+          Vector3::zero()
+        }
+      }
+    }
   }
 }
 
@@ -127,8 +182,174 @@ where
     let maybe_interaction = self.structure.interact(ray);
 
     match maybe_interaction {
-      None => Vector3::zero(),
-      Some(interaction) => self.radiance_recursive(&interaction, 0),
+      None => self.environment.radiance(ray.direction),
+      Some(interaction) => self.radiance_iterative(interaction),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::{lambertian, quad};
+  use super::*;
+  use acceleration::Linear;
+  use environment::Constant as ConstantEnvironment;
+  use geometry::{Sphere, UUID};
+  use material::{IdealRefraction, Material};
+  use object::{AreaLight, Interact, Object};
+  use sampler::{Independent, Sampling};
+  use texture::Constant;
+
+  /**
+   * 再帰で書いた同じ推定
+   *
+   * 各頂点でロシアンルーレット, 光源のサンプリング, マテリアルのサンプリングの順に乱数を使う
+   */
+  fn radiance_recursive<S>(
+    e: &ExplicitLight<S>,
+    point: &Interaction,
+    depth: usize,
+    throughput: Vector3,
+  ) -> Vector3
+  where
+    S: Acceleration,
+  {
+    let le = if depth == 0 {
+      point.emittance()
+    } else {
+      Vector3::zero()
+    };
+    let continue_probability = match e.russian_roulette.survive(depth, throughput) {
+      None => return le,
+      Some(p) => p,
+    };
+    let is_delta = point.is_delta();
+    let light_oriented_contrib = if is_delta {
+      Vector3::zero()
+    } else {
+      e.sample_light(point)
+    };
+    let material_sample = point.sample_material();
+    let wo = material_sample.value;
+    let material_oriented_contrib = match point.trace(e.structure, wo) {
+      Connection::Blocked => Vector3::zero(),
+      Connection::Escape => {
+        let mis_weight = if is_delta {
+          1.0
+        } else {
+          let bsdf_pdf = point.bsdf_pdf(wo);
+          e.light_sampler
+            .environment_pdf(wo)
+            .map(|pdf| bsdf_pdf.power_hulistic(pdf, 2))
+            .unwrap_or(1.0)
+        };
+        e.environment.radiance(wo)
+          * (point.bsdf(wo) * point.weight(wo, material_sample.pdf))
+          * mis_weight
+      }
+      Connection::Hit(geom) => {
+        let li = geom.next.emittance();
+        let f = geom.bsdf() * geom.weight(material_sample.pdf);
+        let li_scatter = radiance_recursive(
+          e,
+          &geom.next,
+          depth + 1,
+          throughput / continue_probability * f,
+        );
+        if is_delta {
+          (li + li_scatter) * f
+        } else {
+          let bsdf_pdf = geom.bsdf_pdf();
+          let mis_weight = geom
+            .light_pdf(&e.light_sampler)
+            .map(|pdf| {
+              bsdf_pdf.power_hulistic(pdf.solid_angle_measure(geom.x_offset, geom.x2, geom.n2), 2)
+            })
+            .unwrap_or(1.0);
+          li * f * mis_weight + li_scatter * f
+        }
+      }
+    };
+    le + (light_oriented_contrib + material_oriented_contrib) / continue_probability
+  }
+
+  #[test]
+  fn recursive_agreement_test() {
+    // 同じシードのサンプラーで、反復による推定と再帰による推定がサンプルごとに一致する
+    let mut uuid = UUID::new();
+    let white = lambertian(0.8);
+    let black = lambertian(0.0);
+    let glass: Box<dyn Material + Send + Sync> = Box::new(IdealRefraction {
+      reflectance: Vector3::fill(1.0),
+      ior: 1.5,
+    });
+    let light = AreaLight::diffuse(Box::new(Constant::new(Vector3::fill(5.0))));
+    let mut objects = Vec::new();
+    let floor = [
+      Vector3::new(-2.0, -2.0, 0.0),
+      Vector3::new(2.0, -2.0, 0.0),
+      Vector3::new(2.0, 2.0, 0.0),
+      Vector3::new(-2.0, 2.0, 0.0),
+    ];
+    for g in quad(floor, &mut uuid) {
+      objects.push(Object::new(g, Matrix4::unit(), &white));
+    }
+    // 下向きの光源
+    let panel = [
+      Vector3::new(-0.5, -0.5, 2.0),
+      Vector3::new(-0.5, 0.5, 2.0),
+      Vector3::new(0.5, 0.5, 2.0),
+      Vector3::new(0.5, -0.5, 2.0),
+    ];
+    for g in quad(panel, &mut uuid) {
+      objects.push(Object::new(g, Matrix4::unit(), &black).with_area_light(&light));
+    }
+    let sphere = Sphere::new(Vector3::new(0.5, 0.0, 0.5), 0.5, &mut uuid);
+    objects.push(Object::new(Box::new(sphere), Matrix4::unit(), &glass));
+    let structure = Linear::new(objects);
+    let environment = ConstantEnvironment {
+      radiance: Vector3::fill(0.2),
+    };
+    let delta_lights = [DeltaLight::Point {
+      position: Vector3::new(-1.0, 0.0, 1.0),
+      intensity: Vector3::fill(1.0),
+    }];
+    let explicit_light = ExplicitLight::new(
+      &structure,
+      &environment,
+      &delta_lights,
+      RussianRoulette::default(),
+    );
+    let sampler = Independent::new(1);
+    for i in 0..2000 {
+      // 床を斜め上から見る
+      let target = Vector3::new(
+        (i % 40) as f32 / 20.0 - 1.0,
+        (i / 40) as f32 / 25.0 - 1.0,
+        0.0,
+      );
+      let origin = Vector3::new(0.0, -3.0, 1.5);
+      let ray = Ray {
+        from: None,
+        origin: origin,
+        direction: (target - origin).normalize(),
+      };
+      Sampling::start_pixel(&sampler, i);
+      let expected = match structure.interact(ray) {
+        None => environment.radiance(ray.direction),
+        Some(interaction) => {
+          radiance_recursive(&explicit_light, &interaction, 0, Vector3::fill(1.0))
+        }
+      };
+      Sampling::start_pixel(&sampler, i);
+      let estimate = explicit_light.radiance(ray);
+      assert!(
+        (estimate - expected).norm() <= 1e-4 * expected.norm().max(1.0),
+        "{} {} {}",
+        i,
+        estimate,
+        expected
+      );
     }
   }
 }
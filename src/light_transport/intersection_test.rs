@@ -2,7 +2,7 @@ use super::radiance::Radiance;
 use acceleration::Acceleration;
 use math::*;
 use ray::Ray;
-use sampler::Sampler;
+use sampler::Sampling;
 
 pub struct IntersectionTest<'a, S>
 where
@@ -33,7 +33,7 @@ where
       None => Vector3::zero(),
       Some(point) => {
         for _ in 0..1000 {
-          let sample = Sampler::hemisphere_uniform();
+          let sample = Sampling::hemisphere_uniform();
           let basis = point.orienting_normal.orthonormal_basis();
           let direction = &basis * sample;
           match point.connect_direction(self.structure, direction) {
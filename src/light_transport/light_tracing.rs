@@ -0,0 +1,198 @@
+use super::radiance::Radiance;
+use super::walk::{trace_particle, visible_point};
+use super::RussianRoulette;
+use acceleration::{Acceleration, AccelerationUtility};
+use camera::Camera;
+use environment::Environment;
+use film::Splat;
+use math::*;
+use object::{Interaction, LightSampler};
+use ray::Ray;
+
+/**
+ * 光源からの追跡 (light tracing)
+ *
+ * 光源から放出した粒子を散乱させ、拡散面 (完全鏡面以外) での衝突点ごとにカメラへ直接つないで
+ * フィルムに足し込む (レイのサンプリングとは別の画素に寄与する)
+ * 光源や環境光がカメラ (と完全鏡面) から直接見える寄与はカメラからのレイで求める
+ * 拡散面のあとに完全鏡面を経由してカメラに届くパス (鏡に映った拡散面など) は扱えない
+ *
+ * カメラのサンプルごとに1個の粒子を追跡する
+ */
+pub struct LightTracing<'a, S, C>
+where
+  S: Acceleration,
+  C: Camera,
+{
+  structure: &'a S,
+  camera: &'a C,
+  environment: &'a (dyn Environment + Send + Sync),
+  russian_roulette: RussianRoulette,
+  light_sampler: LightSampler<'a>,
+  splat: &'a Splat,
+}
+
+impl<'a, S, C> LightTracing<'a, S, C>
+where
+  S: Acceleration + 'a,
+  C: Camera,
+{
+  pub fn new(
+    structure: &'a S,
+    camera: &'a C,
+    environment: &'a (dyn Environment + Send + Sync),
+    russian_roulette: RussianRoulette,
+    splat: &'a Splat,
+  ) -> Self {
+    LightTracing {
+      structure: structure,
+      camera: camera,
+      environment: environment,
+      russian_roulette: russian_roulette,
+      light_sampler: structure.light_sampler(environment),
+      splat: splat,
+    }
+  }
+
+  /**
+   * 光源から粒子を1個放出して、衝突点ごとにカメラへつないだ寄与をフィルムに足し込む
+   */
+  fn trace_particle(&self) {
+    trace_particle(
+      self.structure,
+      &self.light_sampler,
+      self.russian_roulette,
+      |interaction, wi, power, depth| {
+        // カメラから見ると最初の衝突点になるので、反射の回数は1つ増える
+        if self.within_depth(depth + 1) {
+          self.connect_camera(interaction, wi, power);
+        }
+      },
+    );
+  }
+
+  /**
+   * 方向wiから粒子が入射した衝突点をカメラにつなぐ
+   */
+  fn connect_camera(&self, interaction: &Interaction, wi: Vector3, power: Vector3) {
+    let x = interaction.intersection.position;
+    let importance = match self.camera.importance(x) {
+      None => return,
+      Some(importance) => importance,
+    };
+    let path = importance.position - x;
+    let wo = path.normalize();
+    let n = interaction.intersection.normal;
+    // 面の両側をまたぐのは透過するマテリアルに限られる
+    let scatters = wi.dot(n) * wo.dot(n) > 0.0 || interaction.is_transmissive();
+    if !scatters || !interaction.unoccluded(self.structure, importance.position) {
+      return;
+    }
+    let g = interaction.adjoint_cos(wi, wo) * importance.importance / path.sqr_norm();
+    let contrib = power * interaction.bsdf_between(wo, wi) * g;
+    if contrib.sqr_norm() > 0.0 {
+      self.splat.add(importance.uv, contrib);
+    }
+  }
+
+  fn within_depth(&self, depth: usize) -> bool {
+    self
+      .russian_roulette
+      .max_depth
+      .map(|max| depth <= max)
+      .unwrap_or(true)
+  }
+}
+
+impl<'a, S, C> Radiance for LightTracing<'a, S, C>
+where
+  S: Acceleration,
+  C: Camera,
+{
+  fn radiance(&self, ray: Ray) -> Vector3 {
+    self.trace_particle();
+    visible_point(self.structure, self.environment, self.russian_roulette, ray).0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::{lambertian, quad, ExplicitLight};
+  use super::*;
+  use acceleration::Linear;
+  use camera::IdealPinhole;
+  use environment::Constant as ConstantEnvironment;
+  use film::Film;
+  use geometry::UUID;
+  use object::{AreaLight, Object};
+  use sampler::{Independent, Sampling};
+  use texture::Constant;
+
+  #[test]
+  fn explicit_light_agreement_test() {
+    // 面光源に照らされた拡散面をカメラから見た平均の放射輝度は、
+    // 光源から追跡してフィルムに足し込んだ推定と一致する
+    let mut uuid = UUID::new();
+    let white = lambertian(0.5);
+    let black = lambertian(0.0);
+    let light = AreaLight::diffuse(Box::new(Constant::new(Vector3::fill(5.0))));
+    let mut objects = Vec::new();
+    let floor = [
+      Vector3::new(-10.0, -10.0, 0.0),
+      Vector3::new(10.0, -10.0, 0.0),
+      Vector3::new(10.0, 10.0, 0.0),
+      Vector3::new(-10.0, 10.0, 0.0),
+    ];
+    for g in quad(floor, &mut uuid) {
+      objects.push(Object::new(g, Matrix4::unit(), &white));
+    }
+    // 下向きの光源
+    let panel = [
+      Vector3::new(-0.5, -0.5, 2.0),
+      Vector3::new(-0.5, 0.5, 2.0),
+      Vector3::new(0.5, 0.5, 2.0),
+      Vector3::new(0.5, -0.5, 2.0),
+    ];
+    for g in quad(panel, &mut uuid) {
+      objects.push(Object::new(g, Matrix4::unit(), &black).with_area_light(&light));
+    }
+    let structure = Linear::new(objects);
+    let environment = ConstantEnvironment::black();
+    let camera = IdealPinhole::new(
+      0.8,
+      1.0,
+      Matrix4::look_at(
+        Vector3::new(0.0, 0.0, 1.0),
+        Vector3::zero(),
+        Vector3::new(0.0, 1.0, 0.0),
+      ),
+    );
+    let russian_roulette = RussianRoulette::default();
+    let (width, height) = (8, 8);
+    let count = 200000;
+    let splat = Splat::new(width, height);
+    let light_tracing =
+      LightTracing::new(&structure, &camera, &environment, russian_roulette, &splat);
+    let explicit_light = ExplicitLight::new(&structure, &environment, &[], russian_roulette);
+    // 乱数によって許容誤差を外れないようにシードを固定する
+    Sampling::start_pixel(&Independent::new(1), 0);
+    let mut expected = 0.0;
+    for _ in 0..count {
+      let (u, v) = Sampling::get_2d();
+      let ray = camera.sample(u, v).value;
+      expected += explicit_light.radiance(ray).y;
+      // 光源はカメラから直接見えない
+      assert_eq!(light_tracing.radiance(ray).y, 0.0);
+    }
+    let expected = expected / count as f32;
+    let mut film = Film::new(Vector3::zero(), width, height);
+    splat.apply(&mut film, (width * height) as f32 / count as f32);
+    let estimate = film.data.iter().map(|v| v.y).sum::<f32>() / (width * height) as f32;
+    assert!(
+      (estimate - expected).abs() < 0.03 * expected,
+      "{} {}",
+      estimate,
+      expected
+    );
+  }
+}
@@ -1,15 +1,62 @@
+mod bidirectional;
 mod explicit_light;
 mod id;
 mod intersection_test;
+mod light_tracing;
 mod naive;
 mod normal;
 mod only_light;
+mod path;
+mod photon_map;
+mod photon_mapping;
+mod progressive_photon_mapping;
 mod radiance;
+mod russian_roulette;
+mod volumetric_path;
+mod walk;
 
+pub use self::bidirectional::*;
 pub use self::explicit_light::*;
 pub use self::id::*;
 pub use self::intersection_test::*;
+pub use self::light_tracing::*;
 pub use self::naive::*;
 pub use self::normal::*;
 pub use self::only_light::*;
+pub use self::photon_map::*;
+pub use self::photon_mapping::*;
+pub use self::progressive_photon_mapping::*;
 pub use self::radiance::*;
+pub use self::russian_roulette::*;
+pub use self::volumetric_path::*;
+
+#[cfg(test)]
+use geometry::{Geometry, Triangle, UUID};
+#[cfg(test)]
+use material::{Lambertian, Material};
+#[cfg(test)]
+use math::*;
+#[cfg(test)]
+use texture::Constant;
+
+/**
+ * 4頂点の四角形を2つの三角形に分ける (テストのシーン用)
+ */
+#[cfg(test)]
+fn quad(p: [Vector3; 4], uuid: &mut UUID) -> Vec<Box<dyn Geometry + Send + Sync>> {
+  let n = (p[1] - p[0]).cross(p[2] - p[0]).normalize();
+  vec![
+    Box::new(Triangle::new(p[0], p[1], p[2], n, n, n, uuid)),
+    Box::new(Triangle::new(p[0], p[2], p[3], n, n, n, uuid)),
+  ]
+}
+
+/**
+ * 一様な反射率の拡散面 (テストのシーン用)
+ */
+#[cfg(test)]
+fn lambertian(albedo: f32) -> Box<dyn Material + Send + Sync> {
+  Box::new(Lambertian {
+    albedo: Box::new(Constant::new(Vector3::fill(albedo))),
+  })
+}
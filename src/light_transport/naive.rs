@@ -1,7 +1,10 @@
+use super::path::PathState;
 use super::radiance::Radiance;
+use super::RussianRoulette;
 use acceleration::Acceleration;
+use environment::Environment;
 use math::*;
-use object::{GeomWeight, Interaction};
+use object::{Connection, GeomWeight, Interaction};
 use ray::Ray;
 
 pub struct Naive<'a, S>
@@ -9,39 +12,64 @@ where
   S: Acceleration,
 {
   structure: &'a S,
+  environment: &'a (dyn Environment + Send + Sync),
+  russian_roulette: RussianRoulette,
 }
 
 impl<'a, S> Naive<'a, S>
 where
   S: Acceleration + 'a,
 {
-  pub fn new(structure: &'a S) -> Self {
+  pub fn new(
+    structure: &'a S,
+    environment: &'a (dyn Environment + Send + Sync),
+    russian_roulette: RussianRoulette,
+  ) -> Self {
     Naive {
       structure: structure,
+      environment: environment,
+      russian_roulette: russian_roulette,
     }
   }
 
-  fn radiance_recursive(&self, point: &Interaction, depth: usize) -> Vector3 {
-    let le = point.emittance();
-    // スタックオーバーフロー防止
-    // TODO: ロシアンルーレット
-    if depth > 5 {
-      return le;
-    }
-
-    // マテリアルに基づいて方向ベクトルをサンプリング
-    let material_sample = point.sample_material();
-    // 衝突点から方向ベクトルを使ってパスを接続
-    let material_throughput = match point.connect_direction(self.structure, material_sample.value) {
-      None => Vector3::zero(),
-      Some(geom) => {
-        // 接続先から再帰的にパスを生成する
-        let li = self.radiance_recursive(&geom.next, depth + 1);
-        li * geom.bsdf() * geom.weight(material_sample.pdf)
-      }
-    };
+  fn radiance_iterative(&self, first: Interaction<'a>) -> Vector3 {
+    let mut l = Vector3::zero();
+    let mut state = PathState::new(first);
+    loop {
+      l += state.throughput * state.point.emittance();
+      // ロシアンルーレットで打ち切る
+      // 継続した場合は寄与を継続確率で割る
+      let continue_probability = match self.russian_roulette.survive(state.depth, state.throughput)
+      {
+        None => break,
+        Some(p) => p,
+      };
+      state.throughput /= continue_probability;
 
-    le + material_throughput
+      // マテリアルに基づいて方向ベクトルをサンプリング
+      let material_sample = state.point.sample_material();
+      // 衝突点から方向ベクトルを使ってパスを接続
+      let wo = material_sample.value;
+      let (next, f) = match state.point.trace(self.structure, wo) {
+        Connection::Blocked => break,
+        Connection::Escape => {
+          // 環境光
+          let li = self.environment.radiance(wo);
+          l += state.throughput
+            * li
+            * state.point.bsdf(wo)
+            * state.point.weight(wo, material_sample.pdf);
+          break;
+        }
+        Connection::Hit(geom) => {
+          let f = geom.bsdf() * geom.weight(material_sample.pdf);
+          (geom.next, f)
+        }
+      };
+      // MISを使わないので確率密度は持ち越さない
+      state = state.advance(next, f, None);
+    }
+    l
   }
 }
 
@@ -53,8 +81,8 @@ where
     let maybe_interaction = self.structure.interact(ray);
 
     match maybe_interaction {
-      None => Vector3::zero(),
-      Some(interaction) => self.radiance_recursive(&interaction, 0),
+      None => self.environment.radiance(ray.direction),
+      Some(interaction) => self.radiance_iterative(interaction),
     }
   }
 }
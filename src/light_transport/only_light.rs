@@ -1,14 +1,20 @@
+use super::path::PathState;
 use super::radiance::Radiance;
-use acceleration::{Acceleration, AccelerationUtility};
+use super::RussianRoulette;
+use acceleration::Acceleration;
+use environment::Environment;
 use math::*;
-use object::{GeomWeight, Interaction, LightSampler};
+use object::{DeltaLight, GeomWeight, Interaction, LightSample, LightSampler};
 use ray::Ray;
+use sample::pdf;
 
 pub struct OnlyLight<'a, S>
 where
   S: Acceleration,
 {
   structure: &'a S,
+  environment: &'a (dyn Environment + Send + Sync),
+  russian_roulette: RussianRoulette,
   light_sampler: LightSampler<'a>,
 }
 
@@ -16,61 +22,101 @@ impl<'a, S> OnlyLight<'a, S>
 where
   S: Acceleration + 'a,
 {
-  pub fn new(structure: &'a S) -> Self {
+  pub fn new(
+    structure: &'a S,
+    environment: &'a (dyn Environment + Send + Sync),
+    delta_lights: &'a [DeltaLight],
+    russian_roulette: RussianRoulette,
+  ) -> Self {
     OnlyLight {
       structure: structure,
-      light_sampler: structure.light_sampler(),
+      environment: environment,
+      russian_roulette: russian_roulette,
+      light_sampler: LightSampler::new(structure.objects(), delta_lights, environment),
     }
   }
 
-  fn radiance_recursive(&self, point: &Interaction, depth: usize) -> Vector3 {
+  fn radiance_iterative(&self, first: Interaction<'a>) -> Vector3 {
     // 視線サブパスが直接光源に接続された場合のみ寄与を取る
-    // 光源からの寄与は前のパスで取っているので含めない
-    let le = if depth == 0 {
-      point.emittance()
-    } else {
-      Vector3::zero()
-    };
-    // スタックオーバーフロー防止
-    // TODO: ロシアンルーレット
-    if depth > 5 {
-      return le;
-    }
+    // 光源からの寄与は前の頂点で取っているので、以降の頂点の放射は含めない
+    let mut l = first.emittance();
+    let mut state = PathState::new(first);
+    loop {
+      // ロシアンルーレットで打ち切る
+      // 継続した場合は寄与を継続確率で割る
+      let continue_probability = match self.russian_roulette.survive(state.depth, state.throughput)
+      {
+        None => break,
+        Some(p) => p,
+      };
+      state.throughput /= continue_probability;
 
-    // マテリアルに基づいて方向ベクトルをサンプリング
-    let material_sample = point.sample_material();
-    // 衝突点から方向ベクトルを使ってパスを接続
-    let material_oriented_contrib =
-      match point.connect_direction(self.structure, material_sample.value) {
-        None => Vector3::zero(),
+      // 完全鏡面反射の場合はNEEを行わない
+      if !state.point.is_delta() {
+        l += state.throughput * self.sample_light(&state.point);
+      }
+
+      // マテリアルに基づいて方向ベクトルをサンプリング
+      let material_sample = state.point.sample_material();
+      // 衝突点から方向ベクトルを使ってパスを接続
+      // 散乱成分の寄与だけを蓄積する
+      let (next, f) = match state
+        .point
+        .connect_direction(self.structure, material_sample.value)
+      {
+        None => break,
         Some(geom) => {
-          // 接続先から再帰的にパスを生成して散乱成分の寄与を蓄積する
-          let li_scatter = self.radiance_recursive(&geom.next, depth + 1);
-          let scatter_contrib = li_scatter * geom.bsdf() * geom.weight(material_sample.pdf);
-          scatter_contrib
+          let f = geom.bsdf() * geom.weight(material_sample.pdf);
+          (geom.next, f)
         }
       };
+      state = state.advance(next, f, None);
+    }
+    l
+  }
 
+  /**
+   * 明示的に光源をサンプリングして衝突点と接続した寄与
+   */
+  fn sample_light(&self, point: &Interaction) -> Vector3 {
     // 明示的に光源の座標をサンプリング
     let light_sample = self.light_sampler.sample();
     // 衝突点と光源を接続して光源サブパスを生成
-    let light_oriented_contrib = light_sample
-      .map(
-        |sample| match point.connect_point(self.structure, sample.value) {
-          None => Vector3::zero(),
-          Some(geom) => {
-            // 明示的な光源サブパスの重点的サンプリング
-            let li = geom.next.emittance();
-            let light_pdf = sample
-              .pdf
-              .solid_angle_measure(geom.x_offset, geom.x2, geom.n2);
-            li * geom.bsdf() * geom.weight(light_pdf)
-          }
-        },
-      )
-      .unwrap_or(Vector3::zero());
-
-    le + material_oriented_contrib + light_oriented_contrib
+    match light_sample {
+      None => Vector3::zero(),
+      Some(LightSample::Area(sample)) => match point.connect_point(self.structure, sample.value) {
+        None => Vector3::zero(),
+        Some(geom) => {
+          // 明示的な光源サブパスの重点的サンプリング
+          let li = geom.next.emittance();
+          let light_pdf = sample
+            .pdf
+            .solid_angle_measure(geom.x_offset, geom.x2, geom.n2);
+          li * geom.bsdf() * geom.weight(light_pdf)
+        }
+      },
+      Some(LightSample::Delta { light, probability }) => {
+        let incidence = match light.incidence(point.intersection.position) {
+          None => return Vector3::zero(),
+          Some(incidence) => incidence,
+        };
+        let wo = incidence.direction;
+        if !point.connect_light(self.structure, wo, incidence.distance) {
+          return Vector3::zero();
+        }
+        incidence.irradiance * point.bsdf(wo) * point.weight(wo, pdf::SolidAngle(1.0)) / probability
+      }
+      Some(LightSample::Environment(sample)) => {
+        let wo = sample.value;
+        if sample.pdf.0 > 0.0 && point.connect_environment(self.structure, wo) {
+          // 明示的な環境光の重点的サンプリング
+          let li = self.environment.radiance(wo);
+          li * point.bsdf(wo) * point.weight(wo, sample.pdf)
+        } else {
+          Vector3::zero()
+        }
+      }
+    }
   }
 }
 
@@ -82,8 +128,64 @@ where
     let maybe_interaction = self.structure.interact(ray);
 
     match maybe_interaction {
-      None => Vector3::zero(),
-      Some(interaction) => self.radiance_recursive(&interaction, 0),
+      None => self.environment.radiance(ray.direction),
+      Some(interaction) => self.radiance_iterative(interaction),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::quad;
+  use super::*;
+  use acceleration::Linear;
+  use environment::Constant as ConstantEnvironment;
+  use geometry::UUID;
+  use material::{ComplexIor, IdealConductor, Material};
+  use object::Object;
+  use sampler::{Independent, Sampling};
+
+  #[test]
+  fn delta_vertex_test() {
+    // 鏡面で反射した方向には何もないので、点光源があっても寄与は0になる
+    let mut uuid = UUID::new();
+    let mirror: Box<dyn Material + Send + Sync> = Box::new(IdealConductor {
+      ior: ComplexIor::preset("Ag").unwrap(),
+    });
+    let floor = [
+      Vector3::new(-2.0, -2.0, 0.0),
+      Vector3::new(2.0, -2.0, 0.0),
+      Vector3::new(2.0, 2.0, 0.0),
+      Vector3::new(-2.0, 2.0, 0.0),
+    ];
+    let objects = quad(floor, &mut uuid)
+      .into_iter()
+      .map(|g| Object::new(g, Matrix4::unit(), &mirror))
+      .collect();
+    let structure = Linear::new(objects);
+    let environment = ConstantEnvironment {
+      radiance: Vector3::zero(),
+    };
+    let delta_lights = [DeltaLight::Point {
+      position: Vector3::new(0.0, 0.0, 1.0),
+      intensity: Vector3::fill(1.0),
+    }];
+    let only_light = OnlyLight::new(
+      &structure,
+      &environment,
+      &delta_lights,
+      RussianRoulette::default(),
+    );
+    let sampler = Independent::new(1);
+    let origin = Vector3::new(0.0, -3.0, 1.5);
+    let ray = Ray {
+      from: None,
+      origin: origin,
+      direction: (Vector3::zero() - origin).normalize(),
+    };
+    for i in 0..100 {
+      Sampling::start_pixel(&sampler, i);
+      assert_eq!(only_light.radiance(ray), Vector3::zero());
     }
   }
 }
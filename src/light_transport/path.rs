@@ -0,0 +1,49 @@
+use math::*;
+use object::Interaction;
+use sample::pdf;
+
+/**
+ * 反復的なパス追跡の状態
+ *
+ * 頂点ごとの寄与はその場で throughput を掛けて足し込むので、
+ * パスの長さによらず状態の大きさは変わらない
+ */
+pub struct PathState<'a> {
+  pub point: Interaction<'a>,
+  // カメラから現在の頂点までの寄与の重み (ロシアンルーレットの継続確率で割ったもの)
+  pub throughput: Vector3,
+  // 直前の頂点でマテリアルから現在の頂点への方向がサンプリングされる確率密度
+  // カメラと完全鏡面の直後ではNoneで、光源の寄与をMISで重み付けしない
+  pub prev_bsdf_pdf: Option<pdf::SolidAngle>,
+  pub depth: usize,
+}
+
+impl<'a> PathState<'a> {
+  pub fn new(point: Interaction<'a>) -> Self {
+    PathState {
+      point: point,
+      throughput: Vector3::fill(1.0),
+      prev_bsdf_pdf: None,
+      depth: 0,
+    }
+  }
+
+  /**
+   * 接続先nextにパスを延ばす
+   *
+   * f は現在の頂点での BSDF * cos項 / 確率密度
+   */
+  pub fn advance(
+    self,
+    next: Interaction<'a>,
+    f: Vector3,
+    bsdf_pdf: Option<pdf::SolidAngle>,
+  ) -> Self {
+    PathState {
+      point: next,
+      throughput: self.throughput * f,
+      prev_bsdf_pdf: bsdf_pdf,
+      depth: self.depth + 1,
+    }
+  }
+}
@@ -0,0 +1,291 @@
+use super::walk::trace_particle;
+use super::RussianRoulette;
+use acceleration::Acceleration;
+use math::*;
+use object::LightSampler;
+use rayon::prelude::*;
+use sampler::{Sampler, Sampling};
+
+/**
+ * 光子の密度推定のパラメータ
+ *
+ * photonsは1回の放出 (SPPMでは1反復) で光源から追跡する光子の数
+ * nearestは半径を決めるのに使う近傍の光子の数
+ * alphaはSPPMで反復ごとに残す光子の割合 (半径の縮小の速さ)
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DensityEstimation {
+  pub photons: usize,
+  pub nearest: usize,
+  pub alpha: f32,
+}
+
+impl Default for DensityEstimation {
+  fn default() -> Self {
+    DensityEstimation {
+      photons: 100000,
+      nearest: 50,
+      alpha: 0.7,
+    }
+  }
+}
+
+/**
+ * 光源から追跡して拡散面に記録した光子
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Photon {
+  pub position: Vector3,
+  // 光子が入射してきた方向 (記録した点から外向き)
+  pub wi: Vector3,
+  // 光子が運ぶ放射束
+  pub power: Vector3,
+}
+
+/**
+ * 光子を近傍探索するためのkd-tree
+ *
+ * 光子の列を再帰的に中央値で分割して並べ替え、
+ * 範囲の中央の光子をその範囲の節とする (子への参照は持たない)
+ */
+pub struct PhotonMap {
+  photons: Vec<Photon>,
+  // 各節で分割した軸
+  axes: Vec<u8>,
+}
+
+impl PhotonMap {
+  pub fn new(mut photons: Vec<Photon>) -> Self {
+    let mut axes = vec![0; photons.len()];
+    Self::build(&mut photons, &mut axes);
+    PhotonMap {
+      photons: photons,
+      axes: axes,
+    }
+  }
+
+  fn build(photons: &mut [Photon], axes: &mut [u8]) {
+    if photons.len() <= 1 {
+      return;
+    }
+    // 最も広がっている軸で分割する
+    let aabb = photons.iter().fold(
+      (Vector3::fill(INF), Vector3::fill(-INF)),
+      |(min, max), p| {
+        (
+          Vector3::new(
+            min.x.min(p.position.x),
+            min.y.min(p.position.y),
+            min.z.min(p.position.z),
+          ),
+          Vector3::new(
+            max.x.max(p.position.x),
+            max.y.max(p.position.y),
+            max.z.max(p.position.z),
+          ),
+        )
+      },
+    );
+    let extent = aabb.1 - aabb.0;
+    let axis = if extent.x > extent.y && extent.x > extent.z {
+      0
+    } else if extent.y > extent.z {
+      1
+    } else {
+      2
+    };
+    photons.sort_by(|a, b| {
+      a.position[axis]
+        .partial_cmp(&b.position[axis])
+        .unwrap_or(::std::cmp::Ordering::Equal)
+    });
+    let mid = photons.len() / 2;
+    axes[mid] = axis as u8;
+    let (left, right) = photons.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    Self::build(left, left_axes);
+    Self::build(&mut right[1..], &mut right_axes[1..]);
+  }
+
+  pub fn len(&self) -> usize {
+    self.photons.len()
+  }
+
+  /**
+   * 点xから半径radius以内の光子
+   */
+  pub fn within<F>(&self, x: Vector3, radius: f32, mut f: F)
+  where
+    F: FnMut(&Photon),
+  {
+    let mut sqr_radius = radius * radius;
+    self.search(
+      0,
+      self.photons.len(),
+      x,
+      &mut sqr_radius,
+      &mut |photon, _, _| f(photon),
+    );
+  }
+
+  /**
+   * 点xに近い順にk個までの光子と、その距離の2乗
+   */
+  pub fn nearest(&self, x: Vector3, k: usize) -> Vec<(f32, &Photon)> {
+    let mut found: Vec<(f32, &Photon)> = Vec::with_capacity(k + 1);
+    if k == 0 {
+      return found;
+    }
+    let mut sqr_radius = INF;
+    self.search(
+      0,
+      self.photons.len(),
+      x,
+      &mut sqr_radius,
+      &mut |photon, sqr_distance, sqr_radius| {
+        let i = found
+          .iter()
+          .position(|&(d, _)| d > sqr_distance)
+          .unwrap_or(found.len());
+        found.insert(i, (sqr_distance, photon));
+        found.truncate(k);
+        // k個見つかったら、それより遠い光子は探さない
+        if found.len() == k {
+          *sqr_radius = found[k - 1].0;
+        }
+      },
+    );
+    found
+  }
+
+  /**
+   * [begin, end) の範囲の光子のうち、点xからの距離の2乗がsqr_radius以内のものを訪れる
+   *
+   * 訪問先でsqr_radiusを縮めると探索範囲が狭まる
+   */
+  fn search<'a, F>(&'a self, begin: usize, end: usize, x: Vector3, sqr_radius: &mut f32, f: &mut F)
+  where
+    F: FnMut(&'a Photon, f32, &mut f32),
+  {
+    if begin >= end {
+      return;
+    }
+    let mid = begin + (end - begin) / 2;
+    let photon = &self.photons[mid];
+    let axis = self.axes[mid] as usize;
+    let d = x[axis] - photon.position[axis];
+    // 点がある側から先に探す
+    let (near, far) = if d < 0.0 {
+      ((begin, mid), (mid + 1, end))
+    } else {
+      ((mid + 1, end), (begin, mid))
+    };
+    self.search(near.0, near.1, x, sqr_radius, f);
+    let sqr_distance = (photon.position - x).sqr_norm();
+    if sqr_distance <= *sqr_radius {
+      f(photon, sqr_distance, sqr_radius);
+    }
+    if d * d <= *sqr_radius {
+      self.search(far.0, far.1, x, sqr_radius, f);
+    }
+  }
+}
+
+/**
+ * 光源から光子を放出して、衝突した拡散面 (完全鏡面以外) に記録する
+ *
+ * 光子ごとに画素と重ならないサンプル列を使うので、シードを固定すると同じ光子が得られる
+ * iterationはサンプル番号として使う (SPPMの反復ごとに異なる光子になる)
+ * 放射束は放出した光子の数で割ってある
+ */
+pub fn trace_photons<'a, S>(
+  structure: &'a S,
+  light_sampler: &LightSampler<'a>,
+  russian_roulette: RussianRoulette,
+  sampler: &(dyn Sampler + Sync),
+  count: usize,
+  iteration: usize,
+) -> Vec<Photon>
+where
+  S: Acceleration + Sync,
+{
+  (0..count)
+    .into_par_iter()
+    .flat_map(|i| {
+      Sampling::start_pixel(sampler, usize::MAX - i);
+      Sampling::start_sample(iteration);
+      trace_photon(structure, light_sampler, russian_roulette, count)
+    })
+    .collect()
+}
+
+fn trace_photon<'a, S>(
+  structure: &'a S,
+  light_sampler: &LightSampler<'a>,
+  russian_roulette: RussianRoulette,
+  count: usize,
+) -> Vec<Photon>
+where
+  S: Acceleration,
+{
+  let mut photons = Vec::new();
+  trace_particle(
+    structure,
+    light_sampler,
+    russian_roulette,
+    |interaction, wi, power, _| {
+      photons.push(Photon {
+        position: interaction.intersection.position,
+        wi: wi,
+        power: power / count as f32,
+      })
+    },
+  );
+  photons
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn search_test() {
+    // kd-treeの探索結果は全ての光子を調べた結果と一致する
+    let photons = (0..500)
+      .map(|i| {
+        let t = i as f32;
+        Photon {
+          position: Vector3::new((t * 0.37).sin(), (t * 0.71).cos(), (t * 1.13).sin() * 0.5),
+          wi: Vector3::new(0.0, 1.0, 0.0),
+          power: Vector3::fill(t),
+        }
+      })
+      .collect::<Vec<_>>();
+    let map = PhotonMap::new(photons.clone());
+    let x = Vector3::new(0.1, -0.2, 0.05);
+    let mut expected = photons
+      .iter()
+      .map(|p| ((p.position - x).sqr_norm(), p.power.x))
+      .collect::<Vec<_>>();
+    expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let mut within = Vec::new();
+    map.within(x, 0.3, |p| within.push(p.power.x));
+    within.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mut brute = expected
+      .iter()
+      .filter(|&&(d, _)| d <= 0.09)
+      .map(|&(_, v)| v)
+      .collect::<Vec<_>>();
+    brute.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(within, brute);
+
+    let nearest = map
+      .nearest(x, 20)
+      .iter()
+      .map(|&(_, p)| p.power.x)
+      .collect::<Vec<_>>();
+    let brute = expected[..20].iter().map(|&(_, v)| v).collect::<Vec<_>>();
+    assert_eq!(nearest, brute);
+  }
+}
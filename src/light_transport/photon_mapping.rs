@@ -0,0 +1,97 @@
+use super::photon_map::{self, DensityEstimation, PhotonMap};
+use super::radiance::Radiance;
+use super::walk::visible_point;
+use super::RussianRoulette;
+use acceleration::{Acceleration, AccelerationUtility};
+use environment::Environment;
+use math::*;
+use object::Interaction;
+use ray::Ray;
+use sampler::Sampler;
+
+/**
+ * 光子マップによる密度推定
+ *
+ * 最初に光源から光子を放出して光子マップを作り、
+ * カメラから完全鏡面を辿って見える拡散面での放射輝度を近傍の光子の密度から推定する
+ * 拡散面を経由する鏡面反射・屈折のパス (コースティクスを鏡越しに見るなど) も扱えるが、
+ * 推定の半径の分だけぼけた (偏りのある) 結果になる
+ */
+pub struct PhotonMapping<'a, S>
+where
+  S: Acceleration,
+{
+  structure: &'a S,
+  environment: &'a (dyn Environment + Send + Sync),
+  russian_roulette: RussianRoulette,
+  photon_map: PhotonMap,
+  nearest: usize,
+}
+
+impl<'a, S> PhotonMapping<'a, S>
+where
+  S: Acceleration + Sync + 'a,
+{
+  pub fn new(
+    structure: &'a S,
+    environment: &'a (dyn Environment + Send + Sync),
+    russian_roulette: RussianRoulette,
+    sampler: &(dyn Sampler + Sync),
+    density_estimation: DensityEstimation,
+  ) -> Self {
+    let light_sampler = structure.light_sampler(environment);
+    let photons = photon_map::trace_photons(
+      structure,
+      &light_sampler,
+      russian_roulette,
+      sampler,
+      density_estimation.photons,
+      0,
+    );
+    PhotonMapping {
+      structure: structure,
+      environment: environment,
+      russian_roulette: russian_roulette,
+      photon_map: PhotonMap::new(photons),
+      nearest: density_estimation.nearest,
+    }
+  }
+}
+
+impl<'a, S> Radiance for PhotonMapping<'a, S>
+where
+  S: Acceleration,
+{
+  fn radiance(&self, ray: Ray) -> Vector3 {
+    let (l, visible) = visible_point(self.structure, self.environment, self.russian_roulette, ray);
+    match visible {
+      None => l,
+      Some((interaction, throughput)) => {
+        // 近傍のk個の光子を含む円の面積で密度を推定する
+        let nearest = self
+          .photon_map
+          .nearest(interaction.intersection.position, self.nearest);
+        let sqr_radius = match nearest.last() {
+          Some(&(sqr_radius, _)) if sqr_radius > 0.0 => sqr_radius,
+          _ => return l,
+        };
+        let flux = nearest.iter().fold(Vector3::zero(), |sum, &(_, photon)| {
+          sum + gather(&interaction, photon.wi, photon.power)
+        });
+        l + throughput * flux / (PI * sqr_radius)
+      }
+    }
+  }
+}
+
+/**
+ * 光子が運ぶ放射束のうち、衝突点からカメラの方向に散乱する分
+ *
+ * 透過するマテリアルでなければ、面の裏側から入射した光子は数えない
+ */
+pub fn gather(interaction: &Interaction, wi: Vector3, power: Vector3) -> Vector3 {
+  if wi.dot(interaction.orienting_normal) <= 0.0 && !interaction.is_transmissive() {
+    return Vector3::zero();
+  }
+  interaction.bsdf(wi) * power
+}
@@ -0,0 +1,187 @@
+use super::photon_map::{self, DensityEstimation, PhotonMap};
+use super::photon_mapping::gather;
+use super::walk::visible_point;
+use super::RussianRoulette;
+use acceleration::{Acceleration, AccelerationUtility};
+use camera::Camera;
+use environment::Environment;
+use film::Film;
+use integrator::ProgressIndicator;
+use math::*;
+use object::{Interaction, LightSampler};
+use rayon::prelude::*;
+use sampler::{Sampler, Sampling};
+
+/**
+ * 確率的プログレッシブ光子マッピング (SPPM)
+ *
+ * Hachisuka and Jensen, "Stochastic Progressive Photon Mapping" (2009)
+ * 反復ごとに全画素でカメラから見える点を求め直し、新しい光子マップで統計量を更新する
+ * 画素ごとの推定の半径を反復ごとに縮めるので、反復を増やすと偏りなく収束する
+ *
+ * 画素ごとの統計量を反復の間で持ち越すので、レイごとに放射輝度を求める積分器は使わない
+ */
+pub struct ProgressivePhotonMapping<'a, S, C>
+where
+  S: Acceleration,
+  C: Camera,
+{
+  structure: &'a S,
+  camera: &'a C,
+  environment: &'a (dyn Environment + Send + Sync),
+  russian_roulette: RussianRoulette,
+  light_sampler: LightSampler<'a>,
+  density_estimation: DensityEstimation,
+}
+
+/**
+ * 画素ごとの統計量
+ */
+#[derive(Debug, Clone)]
+struct Statistics {
+  // 直接見えた光源と環境光の寄与の和
+  direct: Vector3,
+  // 推定の半径 (0の場合はまだ決まっていない)
+  radius: f32,
+  // 推定に使った光子の数
+  count: f32,
+  // 半径内の光子の放射束の和 (半径の縮小に合わせて減らす)
+  flux: Vector3,
+}
+
+impl Statistics {
+  fn radiance(&self, iterations: usize) -> Vector3 {
+    let n = iterations as f32;
+    if self.radius > 0.0 {
+      self.direct / n + self.flux / (n * PI * self.radius * self.radius)
+    } else {
+      self.direct / n
+    }
+  }
+}
+
+impl<'a, S, C> ProgressivePhotonMapping<'a, S, C>
+where
+  S: Acceleration + Sync + 'a,
+  C: Camera + Sync,
+{
+  pub fn new(
+    structure: &'a S,
+    camera: &'a C,
+    environment: &'a (dyn Environment + Send + Sync),
+    russian_roulette: RussianRoulette,
+    density_estimation: DensityEstimation,
+  ) -> Self {
+    ProgressivePhotonMapping {
+      structure: structure,
+      camera: camera,
+      environment: environment,
+      russian_roulette: russian_roulette,
+      light_sampler: structure.light_sampler(environment),
+      density_estimation: density_estimation,
+    }
+  }
+
+  pub fn render(
+    &self,
+    film: &mut Film<Vector3>,
+    iterations: usize,
+    sampler: &(dyn Sampler + Sync),
+  ) {
+    let uv = film.uv();
+    let mut statistics = vec![
+      Statistics {
+        direct: Vector3::zero(),
+        radius: 0.0,
+        count: 0.0,
+        flux: Vector3::zero(),
+      };
+      film.data.len()
+    ];
+
+    println!("Resolution: {} x {}", film.width, film.height);
+    println!("Iterations: {}", iterations);
+    println!("Photons: {}", self.density_estimation.photons);
+    let mut progress = ProgressIndicator::new(iterations);
+
+    for iteration in 0..iterations {
+      // カメラから見える点
+      let visible = (0..statistics.len())
+        .into_par_iter()
+        .map(|i| {
+          Sampling::start_pixel(sampler, i);
+          Sampling::start_sample(iteration);
+          let (u, v) = uv(i);
+          let ray = self.camera.sample(u, v).value;
+          visible_point(self.structure, self.environment, self.russian_roulette, ray)
+        })
+        .collect::<Vec<_>>();
+
+      // 光子マップ
+      let photon_map = PhotonMap::new(photon_map::trace_photons(
+        self.structure,
+        &self.light_sampler,
+        self.russian_roulette,
+        sampler,
+        self.density_estimation.photons,
+        iteration,
+      ));
+
+      statistics.par_iter_mut().zip(visible.par_iter()).for_each(
+        |(statistics, &(direct, ref visible))| {
+          statistics.direct += direct;
+          if let Some((ref interaction, throughput)) = *visible {
+            self.update(statistics, &photon_map, interaction, throughput);
+          }
+        },
+      );
+
+      progress.next();
+    }
+    progress.end();
+
+    for (pixel, statistics) in film.data.iter_mut().zip(statistics.iter()) {
+      *pixel = statistics.radiance(iterations);
+    }
+  }
+
+  /**
+   * 見える点の近傍の光子を集めて、半径を縮めながら統計量を更新する
+   */
+  fn update(
+    &self,
+    statistics: &mut Statistics,
+    photon_map: &PhotonMap,
+    interaction: &Interaction,
+    throughput: Vector3,
+  ) {
+    let x = interaction.intersection.position;
+    if statistics.radius == 0.0 {
+      // 最初の半径は近傍のk個の光子を含む大きさにする
+      match photon_map
+        .nearest(x, self.density_estimation.nearest)
+        .last()
+      {
+        Some(&(sqr_radius, _)) if sqr_radius > 0.0 => statistics.radius = sqr_radius.sqrt(),
+        _ => return,
+      }
+    }
+    let mut m = 0;
+    let mut flux = Vector3::zero();
+    photon_map.within(x, statistics.radius, |photon| {
+      m += 1;
+      flux += gather(interaction, photon.wi, photon.power);
+    });
+    if m == 0 {
+      return;
+    }
+    // 新しい光子のうちalphaの割合だけを残したとみなして半径を縮める
+    let m = m as f32;
+    let count = statistics.count + self.density_estimation.alpha * m;
+    let radius = statistics.radius * (count / (statistics.count + m)).sqrt();
+    let shrink = (radius / statistics.radius).powi(2);
+    statistics.flux = (statistics.flux + throughput * flux) * shrink;
+    statistics.count = count;
+    statistics.radius = radius;
+  }
+}
@@ -0,0 +1,76 @@
+use math::*;
+use sampler::Roulette;
+
+/**
+ * ロシアンルーレットによるパスの打ち切り
+ *
+ * depthは衝突点での反射の回数 (カメラから見た最初の衝突点が0)
+ * min_depth未満では必ず継続し、max_depth以上では必ず打ち切る
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RussianRoulette {
+  pub min_depth: usize,
+  pub max_depth: Option<usize>,
+}
+
+impl Default for RussianRoulette {
+  fn default() -> Self {
+    RussianRoulette {
+      min_depth: 3,
+      max_depth: None,
+    }
+  }
+}
+
+impl RussianRoulette {
+  // 完全鏡面で継続確率が1のまま無限に反射しないための上限
+  const MAX_PROBABILITY: f32 = 0.95;
+
+  /**
+   * 衝突点から先にパスを継続する確率
+   *
+   * throughputはカメラからその衝突点までの寄与の重み
+   */
+  pub fn probability(&self, depth: usize, throughput: Vector3) -> f32 {
+    if self.max_depth.map(|max| depth >= max).unwrap_or(false) {
+      return 0.0;
+    }
+    if depth < self.min_depth {
+      return 1.0;
+    }
+    throughput.max().clamp(0.0, Self::MAX_PROBABILITY)
+  }
+
+  /**
+   * パスを継続するか判定して継続確率を返す (打ち切る場合はNone)
+   */
+  pub fn survive(&self, depth: usize, throughput: Vector3) -> Option<f32> {
+    let probability = self.probability(depth, throughput);
+    if probability >= 1.0 || (probability > 0.0 && Roulette::within(probability)) {
+      Some(probability)
+    } else {
+      None
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn probability_test() {
+    let rr = RussianRoulette {
+      min_depth: 2,
+      max_depth: Some(5),
+    };
+    let t = Vector3::new(0.1, 0.5, 0.2);
+    assert_eq!(rr.probability(0, t), 1.0);
+    assert_eq!(rr.probability(1, Vector3::zero()), 1.0);
+    assert_eq!(rr.probability(2, t), 0.5);
+    assert_eq!(rr.probability(3, Vector3::fill(2.0)), 0.95);
+    assert_eq!(rr.probability(5, Vector3::fill(1.0)), 0.0);
+    assert!(rr.survive(5, Vector3::fill(1.0)).is_none());
+    assert_eq!(rr.survive(0, Vector3::zero()), Some(1.0));
+  }
+}
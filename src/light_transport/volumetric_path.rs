@@ -0,0 +1,403 @@
+use super::radiance::Radiance;
+use super::RussianRoulette;
+use acceleration::Acceleration;
+use environment::Environment;
+use math::*;
+use medium::{HenyeyGreenstein, Medium, MediumSample};
+use object::{DeltaLight, GeomWeight, Interaction, LightSample, LightSampler};
+use ray::Ray;
+use sample::mis::MIS;
+use sample::pdf;
+
+/**
+ * 関与媒質を扱うパストレーシング
+ *
+ * explicit_light と同じく光源を明示的にサンプリングし、マテリアルのサンプリングとMISで重み付けする
+ * レイが媒質の中を進む間は媒質の散乱をサンプリングし、散乱した点では位相関数で方向を選ぶ
+ * 光源への接続には、接続する区間の媒質の透過率を掛ける
+ *
+ * 物体の境界を透過するたびに、物体の内外の媒質を切り替える
+ * 光源への接続は (完全鏡面の) 物体の境界を通り抜けない
+ */
+pub struct VolumetricPath<'a, S>
+where
+  S: Acceleration,
+{
+  structure: &'a S,
+  environment: &'a (dyn Environment + Send + Sync),
+  russian_roulette: RussianRoulette,
+  light_sampler: LightSampler<'a>,
+  // カメラがある媒質
+  medium: Option<&'a (dyn Medium + Send + Sync)>,
+}
+
+impl<'a, S> VolumetricPath<'a, S>
+where
+  S: Acceleration + 'a,
+{
+  pub fn new(
+    structure: &'a S,
+    environment: &'a (dyn Environment + Send + Sync),
+    delta_lights: &'a [DeltaLight],
+    russian_roulette: RussianRoulette,
+    medium: Option<&'a (dyn Medium + Send + Sync)>,
+  ) -> Self {
+    VolumetricPath {
+      structure: structure,
+      environment: environment,
+      russian_roulette: russian_roulette,
+      light_sampler: LightSampler::new(structure.objects(), delta_lights, environment),
+      medium: medium,
+    }
+  }
+
+  /**
+   * 媒質mediumの中を、レイの原点から距離distanceまで進んだときの透過率
+   */
+  fn transmittance(
+    medium: Option<&(dyn Medium + Send + Sync)>,
+    ray: &Ray,
+    distance: f32,
+  ) -> Vector3 {
+    medium
+      .map(|m| m.transmittance(ray, distance))
+      .unwrap_or(Vector3::fill(1.0))
+  }
+
+  /**
+   * 明示的に光源をサンプリングして物体の表面上の点と接続した寄与
+   *
+   * 透過するマテリアルでは光源が境界の反対側にもあるので、
+   * 接続する区間の媒質はレイがいた媒質mediumから境界を接続の向きに越えた側のものにする
+   */
+  fn sample_light_surface(
+    &self,
+    point: &Interaction<'a>,
+    medium: Option<&'a (dyn Medium + Send + Sync)>,
+  ) -> Vector3 {
+    match self.light_sampler.sample() {
+      None => Vector3::zero(),
+      Some(LightSample::Area(sample)) => match point.connect_point(self.structure, sample.value) {
+        None => Vector3::zero(),
+        Some(geom) => {
+          let li = geom.next.emittance();
+          let light_pdf = sample
+            .pdf
+            .solid_angle_measure(geom.x_offset, geom.x2, geom.n2);
+          let mis_weight = light_pdf.power_hulistic(geom.bsdf_pdf(), 2);
+          let ray = Ray {
+            from: None,
+            origin: geom.x_offset,
+            direction: geom.wo,
+          };
+          let medium = point.medium_towards(geom.wo, medium);
+          let tr = Self::transmittance(medium, &ray, geom.next.intersection.distance);
+          li * tr * geom.bsdf() * geom.weight(light_pdf) * mis_weight
+        }
+      },
+      Some(LightSample::Delta { light, probability }) => {
+        let incidence = match light.incidence(point.intersection.position) {
+          None => return Vector3::zero(),
+          Some(incidence) => incidence,
+        };
+        let wo = incidence.direction;
+        if !point.connect_light(self.structure, wo, incidence.distance) {
+          return Vector3::zero();
+        }
+        // BSDFのサンプリングでは点光源などに到達しないので、MISの重みは1
+        let medium = point.medium_towards(wo, medium);
+        let tr = Self::transmittance(medium, &point.ray(wo), incidence.distance.unwrap_or(INF));
+        incidence.irradiance * tr * point.bsdf(wo) * point.weight(wo, pdf::SolidAngle(1.0))
+          / probability
+      }
+      Some(LightSample::Environment(sample)) => {
+        let wo = sample.value;
+        if sample.pdf.0 > 0.0 && point.connect_environment(self.structure, wo) {
+          let li = self.environment.radiance(wo);
+          let mis_weight = sample.pdf.power_hulistic(point.bsdf_pdf(wo), 2);
+          let medium = point.medium_towards(wo, medium);
+          let tr = Self::transmittance(medium, &point.ray(wo), INF);
+          li * tr * point.bsdf(wo) * point.weight(wo, sample.pdf) * mis_weight
+        } else {
+          Vector3::zero()
+        }
+      }
+    }
+  }
+
+  /**
+   * 明示的に光源をサンプリングして媒質中で散乱した点と接続した寄与
+   */
+  fn sample_light_medium(
+    &self,
+    position: Vector3,
+    wi: Vector3,
+    phase: HenyeyGreenstein,
+    medium: &(dyn Medium + Send + Sync),
+  ) -> Vector3 {
+    match self.light_sampler.sample() {
+      None => Vector3::zero(),
+      Some(LightSample::Area(sample)) => {
+        let path = sample.value - position;
+        let distance = path.norm();
+        let wo = path / distance;
+        let ray = Ray {
+          from: None,
+          origin: position,
+          direction: wo,
+        };
+        let light = match self.structure.interact(ray) {
+          Some(light) => light,
+          None => return Vector3::zero(),
+        };
+        // 可視チェック
+        if !light.intersection.distance.approx_eq(distance) {
+          return Vector3::zero();
+        }
+        let li = light.emittance();
+        let light_pdf =
+          sample
+            .pdf
+            .solid_angle_measure(position, sample.value, light.orienting_normal);
+        // 光源の縁をかすめる方向は確率密度が発散する
+        if li.sqr_norm() == 0.0 || light_pdf.0 <= 0.0 || !light_pdf.0.is_finite() {
+          return Vector3::zero();
+        }
+        let f = phase.p(wi, wo);
+        let mis_weight = light_pdf.power_hulistic(pdf::SolidAngle(f), 2);
+        li * medium.transmittance(&ray, distance) * (f * mis_weight / light_pdf.0)
+      }
+      Some(LightSample::Delta { light, probability }) => {
+        let incidence = match light.incidence(position) {
+          None => return Vector3::zero(),
+          Some(incidence) => incidence,
+        };
+        let wo = incidence.direction;
+        let ray = Ray {
+          from: None,
+          origin: position,
+          direction: wo,
+        };
+        // 可視チェック
+        let distance = incidence.distance.unwrap_or(INF);
+        if let Some(blocker) = self.structure.interact(ray) {
+          if blocker.intersection.distance < distance - EPS {
+            return Vector3::zero();
+          }
+        }
+        let f = phase.p(wi, wo);
+        incidence.irradiance * medium.transmittance(&ray, distance) * (f / probability)
+      }
+      Some(LightSample::Environment(sample)) => {
+        let wo = sample.value;
+        let ray = Ray {
+          from: None,
+          origin: position,
+          direction: wo,
+        };
+        if sample.pdf.0 <= 0.0 || self.structure.interact(ray).is_some() {
+          return Vector3::zero();
+        }
+        let f = phase.p(wi, wo);
+        let mis_weight = sample.pdf.power_hulistic(pdf::SolidAngle(f), 2);
+        self.environment.radiance(wo)
+          * medium.transmittance(&ray, INF)
+          * (f * mis_weight / sample.pdf.0)
+      }
+    }
+  }
+}
+
+impl<'a, S> Radiance for VolumetricPath<'a, S>
+where
+  S: Acceleration,
+{
+  fn radiance(&self, ray: Ray) -> Vector3 {
+    let mut l = Vector3::zero();
+    let mut throughput = Vector3::fill(1.0);
+    let mut ray = ray;
+    let mut medium = self.medium;
+    let mut depth = 0;
+    // 直前に散乱した方向の確率密度 (カメラと完全鏡面ではNoneで、光源の寄与をそのまま足す)
+    let mut scatter_pdf: Option<pdf::SolidAngle> = None;
+    loop {
+      let hit = self.structure.interact(ray);
+      let t_max = hit.as_ref().map(|v| v.intersection.distance).unwrap_or(INF);
+
+      // 媒質中での散乱
+      if let Some(m) = medium {
+        match m.sample(&ray, t_max) {
+          MediumSample::Scatter { distance, weight } => {
+            throughput = throughput * weight;
+            let continue_probability = match self.russian_roulette.survive(depth, throughput) {
+              None => break,
+              Some(p) => p,
+            };
+            throughput /= continue_probability;
+            let position = ray.origin + ray.direction * distance;
+            let wi = -ray.direction;
+            let phase = m.phase();
+            l += throughput * self.sample_light_medium(position, wi, phase, m);
+            // 位相関数に比例してサンプリングするので重みは1
+            let sample = phase.sample(wi);
+            scatter_pdf = Some(sample.pdf);
+            ray = Ray {
+              from: None,
+              origin: position,
+              direction: sample.value,
+            };
+            depth += 1;
+            continue;
+          }
+          MediumSample::Pass { weight } => throughput = throughput * weight,
+        }
+      }
+
+      let interaction = match hit {
+        None => {
+          // マテリアル (位相関数) に比例した環境光の重点的サンプリング
+          let mis_weight = scatter_pdf
+            .and_then(|pdf| {
+              self
+                .light_sampler
+                .environment_pdf(ray.direction)
+                .map(|light_pdf| pdf.power_hulistic(light_pdf, 2))
+            })
+            .unwrap_or(1.0);
+          l += throughput * self.environment.radiance(ray.direction) * mis_weight;
+          break;
+        }
+        Some(interaction) => interaction,
+      };
+
+      // マテリアル (位相関数) に比例した光源の重点的サンプリング
+      let le = interaction.emittance();
+      if le.sqr_norm() > 0.0 {
+        let mis_weight = scatter_pdf
+          .and_then(|pdf| {
+            interaction.light_pdf(&self.light_sampler).map(|light_pdf| {
+              let light_pdf = light_pdf.solid_angle_measure(
+                ray.origin,
+                interaction.intersection.position,
+                interaction.orienting_normal,
+              );
+              pdf.power_hulistic(light_pdf, 2)
+            })
+          })
+          .unwrap_or(1.0);
+        l += throughput * le * mis_weight;
+      }
+
+      // ロシアンルーレットで打ち切る
+      let continue_probability = match self.russian_roulette.survive(depth, throughput) {
+        None => break,
+        Some(p) => p,
+      };
+      throughput /= continue_probability;
+
+      // 完全鏡面反射の場合はNEEを行わない
+      let is_delta = interaction.is_delta();
+      if !is_delta {
+        l += throughput * self.sample_light_surface(&interaction, medium);
+      }
+
+      // マテリアルに基づいて方向ベクトルをサンプリング
+      let sample = interaction.sample_material();
+      let wo = sample.value;
+      let cos = interaction.cos(wo).abs();
+      throughput = throughput * interaction.bsdf(wo) * (cos / sample.pdf.0);
+      scatter_pdf = if is_delta {
+        None
+      } else {
+        Some(interaction.bsdf_pdf(wo))
+      };
+      // 境界を透過したら媒質が変わる
+      medium = interaction.medium_towards(wo, medium);
+      ray = interaction.ray(wo);
+      depth += 1;
+    }
+    l
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::{lambertian, quad};
+  use super::*;
+  use acceleration::Linear;
+  use environment::Constant as ConstantEnvironment;
+  use geometry::{Sphere, UUID};
+  use material::{Material, RoughRefraction};
+  use medium::{Homogeneous, MediumInterface};
+  use object::{AreaLight, Interact, Object};
+  use sampler::{Independent, Sampling};
+  use texture::Constant;
+
+  #[test]
+  fn fog_in_rough_glass_test() {
+    // 霧を満たした粗いガラス球の内側の面から、球の外の光源への接続は霧で減衰しない
+    let mut uuid = UUID::new();
+    let black = lambertian(0.0);
+    let glass: Box<dyn Material + Send + Sync> = Box::new(RoughRefraction {
+      reflectance: Box::new(Constant::new(Vector3::fill(1.0))),
+      roughness: Box::new(Constant::new(0.3)),
+      ior: 1.5,
+    });
+    let fog = Homogeneous {
+      absorption: Vector3::zero(),
+      scattering: Vector3::fill(2.0),
+      phase: HenyeyGreenstein { g: 0.0 },
+    };
+    let light = AreaLight::diffuse(Box::new(Constant::new(Vector3::fill(5.0))));
+    let mut objects = Vec::new();
+    let sphere = Sphere::new(Vector3::zero(), 1.0, &mut uuid);
+    objects.push(
+      Object::new(Box::new(sphere), Matrix4::unit(), &glass).with_medium(MediumInterface {
+        interior: Some(&fog),
+        exterior: None,
+      }),
+    );
+    // 下向きの光源
+    let panel = [
+      Vector3::new(-0.5, -0.5, 3.0),
+      Vector3::new(-0.5, 0.5, 3.0),
+      Vector3::new(0.5, 0.5, 3.0),
+      Vector3::new(0.5, -0.5, 3.0),
+    ];
+    for g in quad(panel, &mut uuid) {
+      objects.push(Object::new(g, Matrix4::unit(), &black).with_area_light(&light));
+    }
+    let structure = Linear::new(objects);
+    let environment = ConstantEnvironment::black();
+    let volumetric_path = VolumetricPath::new(
+      &structure,
+      &environment,
+      &[],
+      RussianRoulette::default(),
+      None,
+    );
+    // 球の中心から真上に向かって、霧の中から内側の面に衝突する
+    let interaction = structure
+      .interact(Ray {
+        from: None,
+        origin: Vector3::zero(),
+        direction: Vector3::new(0.0, 0.0, 1.0),
+      })
+      .unwrap();
+    let sampler = Independent::new(1);
+    let (mut inside, mut vacuum) = (Vector3::zero(), Vector3::zero());
+    for i in 0..1000 {
+      Sampling::start_pixel(&sampler, i);
+      inside += volumetric_path.sample_light_surface(&interaction, Some(&fog));
+      Sampling::start_pixel(&sampler, i);
+      vacuum += volumetric_path.sample_light_surface(&interaction, None);
+    }
+    assert!(vacuum.y > 0.0);
+    assert!(
+      (inside - vacuum).norm() <= 1e-4 * vacuum.norm(),
+      "{} {}",
+      inside,
+      vacuum
+    );
+  }
+}
@@ -0,0 +1,105 @@
+use super::RussianRoulette;
+use acceleration::Acceleration;
+use environment::Environment;
+use math::*;
+use object::{Connection, Interaction, LightSampler};
+use ray::Ray;
+
+/**
+ * 光源から粒子を1個放出して散乱させ、衝突した拡散面 (完全鏡面以外) ごとに
+ * f(衝突点, 粒子が入射してきた方向 (衝突点から外向き), 放射束, 反射の回数) を呼ぶ
+ *
+ * 反射の回数は光源から見た最初の衝突点が0
+ * ロシアンルーレットは放出時からの放射束の比で行う
+ */
+pub fn trace_particle<'a, S, F>(
+  structure: &'a S,
+  light_sampler: &LightSampler<'a>,
+  russian_roulette: RussianRoulette,
+  mut f: F,
+) where
+  S: Acceleration,
+  F: FnMut(&Interaction, Vector3, Vector3, usize),
+{
+  let (ray, power) = match light_sampler.emit() {
+    None => return,
+    Some(emission) => emission,
+  };
+  let initial = power.max();
+  if initial <= 0.0 {
+    return;
+  }
+  let mut power = power;
+  let mut wi = -ray.direction;
+  let mut interaction = match structure.interact(ray) {
+    None => return,
+    Some(interaction) => interaction,
+  };
+  let mut depth = 0;
+  loop {
+    if !interaction.is_delta() {
+      f(&interaction, wi, power, depth);
+    }
+    let continue_probability = match russian_roulette.survive(depth, power / initial) {
+      None => break,
+      Some(p) => p,
+    };
+    let sample = interaction.sample_material();
+    let wo = sample.value;
+    // 光の進む向きに散乱させる
+    let cos = interaction.adjoint_cos(wi, wo);
+    power = power * interaction.bsdf_between(wo, wi) * (cos / sample.pdf.0 / continue_probability);
+    let next = match interaction.trace(structure, wo) {
+      Connection::Hit(geom) => geom.next,
+      _ => break,
+    };
+    interaction = next;
+    wi = -wo;
+    depth += 1;
+  }
+}
+
+/**
+ * カメラからのレイを完全鏡面で反射・屈折させて、最初に見える拡散面 (完全鏡面以外) 上の点を求める
+ *
+ * 途中で直接見えた光源と環境光の寄与と、
+ * 見つかった点とそこまでの寄与の重みを返す (見つからなかった場合はNone)
+ */
+pub fn visible_point<'a, S>(
+  structure: &'a S,
+  environment: &(dyn Environment + Send + Sync),
+  russian_roulette: RussianRoulette,
+  ray: Ray,
+) -> (Vector3, Option<(Interaction<'a>, Vector3)>)
+where
+  S: Acceleration,
+{
+  let mut interaction = match structure.interact(ray) {
+    None => return (environment.radiance(ray.direction), None),
+    Some(interaction) => interaction,
+  };
+  let mut l = Vector3::zero();
+  let mut throughput = Vector3::fill(1.0);
+  let mut depth = 0;
+  loop {
+    l += throughput * interaction.emittance();
+    if !interaction.is_delta() {
+      return (l, Some((interaction, throughput)));
+    }
+    let continue_probability = match russian_roulette.survive(depth, throughput) {
+      None => return (l, None),
+      Some(p) => p,
+    };
+    let sample = interaction.sample_material();
+    let wo = sample.value;
+    let cos = interaction.cos(wo).abs();
+    throughput = throughput * interaction.bsdf(wo) * (cos / sample.pdf.0 / continue_probability);
+    let next = match interaction.trace(structure, wo) {
+      Connection::Hit(geom) => geom.next,
+      Connection::Escape => return (l + throughput * environment.radiance(wo), None),
+      Connection::Blocked => return (l, None),
+    };
+    interaction = next;
+    depth += 1;
+  }
+}
@@ -1,20 +1,47 @@
+use geometry::Mesh;
 use geometry::Triangle;
 use geometry::UUID;
 use material;
 use material::Material;
 use math::*;
-use object::Object;
+use object::{AreaLight, Object};
 use std::path::Path;
+use texture;
+use texture::{ImageTexture, NormalMap, Texture, Wrap};
 
 pub struct Obj {
-  models: Vec<tobj::Model>,
-  materials: Vec<tobj::Material>,
+  // tobjのモデルごとのメッシュとマテリアル
+  meshes: Vec<(Mesh, Option<usize>)>,
   material_library: Vec<Box<dyn Material + Sync + Send>>,
+  // マテリアルごとの法線マップ (バンプマップ)
+  normal_maps: Vec<Option<NormalMap>>,
+  // マテリアルごとの放射 (`Ke`, `map_Ke`)
+  emitters: Vec<Option<AreaLight>>,
 }
 
 impl Obj {
   pub fn new(path: &Path) -> Self {
-    let (models, materials) = tobj::load_obj(&path, true).expect("ERROR! failed to load models.");
+    Obj::load(path).expect("ERROR! failed to load models.")
+  }
+
+  /**
+   * OBJファイルとMTLファイルを読み込む
+   *
+   * MTLの `map_Kd` はアルベドのテクスチャになる
+   * `Ke` (`map_Ke`) があるマテリアルの面は片面の完全拡散の光源になる (`Ke` がある場合は `map_Ke` に掛ける)
+   * PBRの拡張 (`Pr` ラフネス, `Pm` 金属らしさ, `Ps` シーン, `Pc` クリアコート, `Pcr` クリアコートのラフネス,
+   * `aniso` 異方性) があるか `d` が1未満の場合は `Kd` を基本色とする `Principled` になり、
+   * `1 - d` が透過の割合、`Tf` が透過光の色、`Ni` (1より大きい場合) が屈折率になる
+   * (それ以外は `Lambertian`)
+   * 拡張の `metal <name>` (`Au`, `Ag`, `Cu`, `Al`, `Cr`) がある場合は測定された複素屈折率の金属になり、
+   * `Pr` (`map_Pr`) があれば `RoughConductor`、なければ鏡面の `IdealConductor` になる
+   * `Pr`, `Pm` は `map_Pr`, `map_Pm` でテクスチャにできる
+   * `norm` は接空間の法線マップ、`map_Bump` (`bump`) は `-bm` を倍率とするバンプマップになる
+   * テクスチャのパスはOBJファイルからの相対パスで、`-clamp on` で端の画素を延ばす
+   */
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let (models, materials) = tobj::load_obj(&path, true).map_err(|e| format!("{:?}", e))?;
+    let base = path.parent().unwrap_or(Path::new(""));
     let material_library = materials
       .iter()
       .map(|v| {
@@ -23,25 +50,150 @@ impl Obj {
           .get("Ke")
           .and_then(|s| Obj::parse_vector(s))
           .unwrap_or(Vector3::zero());
-        let roughness = v.unknown_param.get("Pr").and_then(|s| Obj::parse_float(s));
-        let albedo = v.diffuse[..].into();
-        match roughness {
-          Some(r) => Box::new(material::GGX {
-            roughness: r,
-            reflectance: albedo,
-          }) as Box<dyn Material + Sync + Send>,
-          None => Box::new(material::Lambertian {
-            emittance: emittance,
-            albedo: albedo,
+        let emitter = match v.unknown_param.get("map_Ke") {
+          Some(map) => {
+            let scale = if emittance.sqr_norm() > 0.0 {
+              emittance
+            } else {
+              Vector3::fill(1.0)
+            };
+            Some(AreaLight::diffuse(Box::new(
+              Obj::texture(base, map, true)?.scale(scale),
+            )))
+          }
+          None if emittance.sqr_norm() > 0.0 => Some(AreaLight::diffuse(Box::new(
+            texture::Constant::new(emittance),
+          ))),
+          None => None,
+        };
+        let albedo: Box<dyn Texture<Vector3> + Send + Sync> = if v.diffuse_texture.is_empty() {
+          Box::new(texture::Constant::new(v.diffuse[..].into()))
+        } else {
+          Box::new(Obj::texture(base, &v.diffuse_texture, true)?)
+        };
+        let roughness = Obj::float_texture(base, v, "Pr")?;
+        let metallic = Obj::float_texture(base, v, "Pm")?;
+        let param = |name: &str| v.unknown_param.get(name).and_then(Obj::parse_float);
+        let normal_map = match v.unknown_param.get("norm") {
+          Some(map) => Some(NormalMap::Normal(Obj::texture(base, map, false)?)),
+          None if !v.normal_texture.is_empty() => Some(NormalMap::Bump {
+            height: Obj::texture(base, &v.normal_texture, false)?,
+            scale: Obj::texture_option(&v.normal_texture, "-bm").unwrap_or(1.0),
           }),
-        }
+          None => None,
+        };
+        let is_principled = roughness.is_some()
+          || metallic.is_some()
+          || ["Ps", "Pc", "Pcr", "aniso"]
+            .iter()
+            .any(|name| v.unknown_param.contains_key(*name))
+          || v.dissolve < 1.0;
+        let metal = match v.unknown_param.get("metal") {
+          Some(name) => Some(
+            material::ComplexIor::preset(name.trim())
+              .ok_or_else(|| format!("unknown metal `{}`", name.trim()))?,
+          ),
+          None => None,
+        };
+        let material = if let Some(metal) = metal {
+          // 金属はラフネスがあれば粗い導体、なければ鏡面
+          match roughness {
+            Some(r) => Box::new(material::RoughConductor {
+              ior: metal,
+              roughness: r,
+              anisotropy: param("aniso").map(|a| a.clamp(0.0, 0.99)).unwrap_or(0.0),
+            }) as Box<dyn Material + Sync + Send>,
+            None => Box::new(material::IdealConductor { ior: metal }),
+          }
+        } else if is_principled {
+          // 屈折率から誘電体の垂直入射の反射率を決める (specular 0.5 が屈折率1.5相当)
+          let ior = if v.optical_density > 1.0 {
+            v.optical_density
+          } else {
+            1.5
+          };
+          let f0 = ((ior - 1.0) / (ior + 1.0)).powi(2);
+          let unit = |value: f32| value.clamp(0.0, 1.0);
+          Box::new(material::Principled {
+            base_color: albedo,
+            metallic: metallic.unwrap_or(Box::new(texture::Constant::new(0.0))),
+            roughness: roughness.unwrap_or(Box::new(texture::Constant::new(0.5))),
+            anisotropy: param("aniso").map(unit).unwrap_or(0.0).min(0.99),
+            specular: unit(f0 / 0.08),
+            sheen: param("Ps").map(unit).unwrap_or(0.0),
+            clearcoat: param("Pc").map(unit).unwrap_or(0.0),
+            clearcoat_roughness: param("Pcr").map(unit).unwrap_or(0.3),
+            transmission: unit(1.0 - v.dissolve),
+            transmission_color: v
+              .unknown_param
+              .get("Tf")
+              .and_then(Obj::parse_vector)
+              .unwrap_or(Vector3::fill(1.0)),
+            ior: ior,
+          }) as Box<dyn Material + Sync + Send>
+        } else {
+          Box::new(material::Lambertian { albedo: albedo })
+        };
+        Ok((material, (normal_map, emitter)))
       })
-      .collect::<Vec<_>>();
-    Obj {
-      models: models,
-      materials: materials,
+      .collect::<Result<Vec<_>, String>>()?;
+    let (material_library, maps): (_, Vec<_>) = material_library.into_iter().unzip();
+    let (normal_maps, emitters) = maps.into_iter().unzip();
+    let meshes = models
+      .iter()
+      .filter(|m| m.mesh.indices.len() >= 3)
+      .map(|m| (Obj::mesh(&m.mesh), m.mesh.material_id))
+      .collect();
+    Ok(Obj {
+      meshes: meshes,
       material_library: material_library,
-    }
+      normal_maps: normal_maps,
+      emitters: emitters,
+    })
+  }
+
+  /**
+   * MTLのテクスチャの指定 (`[-clamp on] path`) を読み込む
+   */
+  fn texture(base: &Path, input: &str, srgb: bool) -> Result<ImageTexture, String> {
+    let tokens = input.split_ascii_whitespace().collect::<Vec<_>>();
+    let file = tokens
+      .last()
+      .ok_or_else(|| "empty texture path".to_string())?;
+    let clamp = tokens.windows(2).any(|w| w[0] == "-clamp" && w[1] == "on");
+    let wrap = if clamp { Wrap::Clamp } else { Wrap::Repeat };
+    let path = base.join(file);
+    ImageTexture::load(&path, wrap, srgb)
+      .map_err(|e| format!("failed to load texture `{}`: {}", path.display(), e))
+  }
+
+  /**
+   * MTLのテクスチャの指定の数値のオプション (`-bm 0.5` など)
+   */
+  fn texture_option(input: &str, name: &str) -> Option<f32> {
+    let tokens = input.split_ascii_whitespace().collect::<Vec<_>>();
+    tokens
+      .windows(2)
+      .find(|w| w[0] == name)
+      .and_then(|w| w[1].parse::<f32>().ok())
+  }
+
+  /**
+   * MTLの数値のパラメータ (`map_<name>` のテクスチャか `<name>` の値)
+   */
+  fn float_texture(
+    base: &Path,
+    material: &tobj::Material,
+    name: &str,
+  ) -> Result<Option<Box<dyn Texture<f32> + Send + Sync>>, String> {
+    let param = &material.unknown_param;
+    Ok(match param.get(&format!("map_{}", name)) {
+      Some(map) => Some(Box::new(Obj::texture(base, map, false)?)),
+      None => param
+        .get(name)
+        .and_then(Obj::parse_float)
+        .map(|r| Box::new(texture::Constant::new(r)) as Box<dyn Texture<f32> + Send + Sync>),
+    })
   }
 
   fn parse_float(input: &String) -> Option<f32> {
@@ -60,47 +212,112 @@ impl Obj {
     }
   }
 
-  pub fn instances<'a>(
-    &'a self,
-    fallback_material: &'a Box<dyn Material + Send + Sync>,
-    uuid: &mut UUID,
-  ) -> Vec<Object> {
-    let mut instances: Vec<Object> =
-      Vec::with_capacity(self.models.iter().map(|m| m.mesh.indices.len() / 3).sum());
-    for m in &self.models {
-      for f in 0..m.mesh.indices.len() / 3 {
+  fn mesh(m: &tobj::Mesh) -> Mesh {
+    let faces = (0..m.indices.len() / 3)
+      .map(|f| {
+        let index = [
+          m.indices[f * 3] as usize,
+          m.indices[f * 3 + 1] as usize,
+          m.indices[f * 3 + 2] as usize,
+        ];
         let mut coord = [Vector3::zero(); 3];
-        let mut normal = [Vector3::zero(); 3];
         for i in 0..3 {
-          let index: usize = f * 3 + i;
-          let a = m.mesh.indices[index] as usize * 3;
-          coord[i] = Vector3::new(
-            m.mesh.positions[a],
-            m.mesh.positions[a + 1],
-            m.mesh.positions[a + 2],
-          );
-          normal[i] = if m.mesh.normals.len() < a + 2 {
-            Triangle::normal(coord[0], coord[1], coord[2])
-          } else {
-            Vector3::new(
-              m.mesh.normals[a],
-              m.mesh.normals[a + 1],
-              m.mesh.normals[a + 2],
-            )
-          };
+          let a = index[i] * 3;
+          coord[i] = Vector3::new(m.positions[a], m.positions[a + 1], m.positions[a + 2]);
+        }
+        // 頂点法線がなければ面の法線 (3頂点が揃ってから計算する)
+        let mut normal = [Triangle::normal(coord[0], coord[1], coord[2]); 3];
+        for i in 0..3 {
+          let a = index[i] * 3;
+          if m.normals.len() >= a + 3 {
+            normal[i] = Vector3::new(m.normals[a], m.normals[a + 1], m.normals[a + 2]);
+          }
+        }
+        let uv = if index.iter().all(|&a| m.texcoords.len() >= a * 2 + 2) {
+          Some([
+            (m.texcoords[index[0] * 2], m.texcoords[index[0] * 2 + 1]),
+            (m.texcoords[index[1] * 2], m.texcoords[index[1] * 2 + 1]),
+            (m.texcoords[index[2] * 2], m.texcoords[index[2] * 2 + 1]),
+          ])
+        } else {
+          None
+        };
+        (index, coord, normal, uv)
+      })
+      .collect::<Vec<_>>();
+    // 頂点の接線は、頂点を共有する面の接線の平均
+    let mut tangents = vec![Vector3::zero(); m.positions.len() / 3];
+    for &(index, coord, _, uv) in &faces {
+      if let Some(uv) = uv {
+        let tangent = Triangle::tangent(coord[0], coord[1], coord[2], uv[0], uv[1], uv[2]);
+        for &a in &index {
+          tangents[a] += tangent;
         }
-        instances.push(Object::new(
-          Box::new(Triangle::new(
-            coord[0], coord[1], coord[2], normal[0], normal[1], normal[2], uuid,
-          )),
-          Matrix4::unit(),
-          m.mesh
-            .material_id
-            .map(|id| &self.material_library[id])
-            .unwrap_or(fallback_material),
-        ));
       }
     }
-    instances
+    // 三角形のidはメッシュ内で一意であればよい
+    let mut uuid = UUID::new();
+    let triangles = faces
+      .iter()
+      .map(|&(index, coord, normal, uv)| {
+        let triangle = Triangle::new(
+          coord[0], coord[1], coord[2], normal[0], normal[1], normal[2], &mut uuid,
+        );
+        // テクスチャ座標がなければ三角形の既定のテクスチャ座標と接線
+        let uv = match uv {
+          None => return triangle,
+          Some(uv) => uv,
+        };
+        let triangle = triangle.with_uv(uv[0], uv[1], uv[2]);
+        let tangent = [tangents[index[0]], tangents[index[1]], tangents[index[2]]];
+        if tangent.iter().any(|t| t.sqr_norm() == 0.0) {
+          return triangle;
+        }
+        triangle.with_tangent(
+          tangent[0].normalize(),
+          tangent[1].normalize(),
+          tangent[2].normalize(),
+        )
+      })
+      .collect();
+    Mesh::new(triangles)
+  }
+
+  /**
+   * メッシュを共有したオブジェクトを作る
+   *
+   * マテリアルは override_material, OBJのマテリアル, fallback_material の優先順
+   * 法線マップは override_normal_map, OBJのマテリアルの法線マップの優先順
+   * 放射は override_emitter, 使われたマテリアルの放射 (OBJのマテリアルは `Ke`) の優先順
+   */
+  pub fn instances<'a>(
+    &'a self,
+    fallback_material: (&'a Box<dyn Material + Send + Sync>, Option<&'a AreaLight>),
+    override_material: Option<(&'a Box<dyn Material + Send + Sync>, Option<&'a AreaLight>)>,
+    override_normal_map: Option<&'a NormalMap>,
+    override_emitter: Option<&'a AreaLight>,
+    matrix: &Matrix4,
+    uuid: &mut UUID,
+  ) -> Vec<Object<'a>> {
+    self
+      .meshes
+      .iter()
+      .map(|(mesh, material_id)| {
+        let (material, emitter) = override_material
+          .or(material_id.map(|id| (&self.material_library[id], self.emitters[id].as_ref())))
+          .unwrap_or(fallback_material);
+        let object = Object::instance(mesh, matrix.clone(), material, uuid);
+        let normal_map =
+          override_normal_map.or(material_id.and_then(|id| self.normal_maps[id].as_ref()));
+        let object = match normal_map {
+          Some(normal_map) => object.with_normal_map(normal_map),
+          None => object,
+        };
+        match override_emitter.or(emitter) {
+          Some(emitter) => object.with_area_light(emitter),
+          None => object,
+        }
+      })
+      .collect()
   }
 }
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+extern crate exr;
 extern crate image;
 extern crate rand;
 extern crate rand_core;
@@ -11,6 +12,8 @@ extern crate tobj;
 
 mod acceleration;
 mod camera;
+mod cli;
+mod environment;
 mod film;
 mod geometry;
 mod integrator;
@@ -18,113 +21,299 @@ mod light_transport;
 mod loader;
 mod material;
 mod math;
+mod medium;
 mod object;
 mod ray;
 mod sample;
 mod sampler;
+mod scene;
+mod texture;
 mod util;
 
-use camera::{Camera, IdealPinhole};
-use film::{tonemap, Film, Format, Save, Validate, PNG};
-use geometry::UUID;
+use camera::Camera;
+use cli::{FormatKind, IntegratorKind, Options};
+use film::{tonemap, Film, Format, Save, Validate, PNG, PPM};
 use integrator::Integrator;
 use light_transport::Radiance;
-use material::Material;
-use math::*;
-use rand::SeedableRng;
-use std::cell::RefCell;
-use std::path::Path;
-
-const WIDTH: usize = 800;
-const HEIGHT: usize = 800;
-const SPP: usize = 100;
-type Image = PNG;
-type RNG = rand::rngs::StdRng;
+use math::Vector3;
+use sampler::Sampler;
+use scene::{SamplerKind, Scene, TonemapKind, TransportKind};
+use std::path::{Path, PathBuf};
+use std::process;
 
-thread_local! {
-  pub static RNG: RefCell<RNG> = RefCell::new(SeedableRng::from_entropy());
-}
+type RNG = rand::rngs::StdRng;
 
 fn main() {
-  // フィルム
-  let mut film = Film::new(Vector3::zero(), WIDTH, HEIGHT);
+  // コマンドライン引数
+  let options = match Options::parse(std::env::args().skip(1)) {
+    Ok(options) => options,
+    Err(error) => {
+      eprintln!("ERROR! {}\n\n{}", error, cli::USAGE);
+      process::exit(2);
+    }
+  };
+  if options.help {
+    println!("{}", cli::USAGE);
+    return;
+  }
 
-  // カメラ
-  // let camera_matrix = Matrix4::look_at(
-  //   Vector3::new(0.0, 2.0, 15.0),
-  //   Vector3::new(0.0, 1.69522, 14.0476),
-  //   Vector3::new(0.0, 1.0, 0.0),
-  // );
-  // let camera = IdealPinhole::new(36.7774 * PI / 180.0, film.aspect(), camera_matrix);
-  let camera_matrix = Matrix4::look_at(
-    Vector3::new(278.0, 273.0, -800.0),
-    Vector3::new(278.0, 273.0, 0.0),
-    Vector3::new(0.0, 1.0, 0.0),
-  );
-  let camera = IdealPinhole::new(39.3077 * PI / 180.0, film.aspect(), camera_matrix);
+  // スレッド数
+  if let Some(threads) = options.threads {
+    if let Err(error) = rayon::ThreadPoolBuilder::new()
+      .num_threads(threads)
+      .build_global()
+    {
+      eprintln!("ERROR! failed to set up {} threads: {}", threads, error);
+      process::exit(1);
+    }
+  }
 
   // シーン
-  let mat: Box<dyn Material + Send + Sync> = Box::new(material::Lambertian {
-    emittance: Vector3::zero(),
-    albedo: Vector3::new(0.75, 0.75, 0.75),
-  });
-  let grossy1: Box<dyn Material + Send + Sync> = Box::new(material::IdealRefraction {
-    reflectance: Vector3::new(1.0, 1.0, 1.0),
-    ior: 1.5,
+  let mut scene = match Scene::load(&options.scene) {
+    Ok(scene) => scene,
+    Err(error) => {
+      eprintln!("ERROR! {}: {}", options.scene.display(), error);
+      process::exit(1);
+    }
+  };
+  if let Some((width, height)) = options.resolution {
+    scene.width = width;
+    scene.height = height;
+  }
+  if let Some(spp) = options.spp {
+    scene.spp = spp;
+  }
+  if let Some(sampler) = options.sampler {
+    scene.sampler = sampler;
+  }
+  if let Some(transport) = options.transport {
+    scene.transport = transport;
+  }
+  if let Some(min_depth) = options.min_depth {
+    scene.russian_roulette.min_depth = min_depth;
+  }
+  if let Some(max_depth) = options.max_depth {
+    scene.russian_roulette.max_depth = Some(max_depth);
+  }
+  if let Some(max_depth) = scene.russian_roulette.max_depth {
+    if max_depth < scene.russian_roulette.min_depth {
+      eprintln!(
+        "ERROR! maximum depth {} must not be less than minimum depth {}",
+        max_depth, scene.russian_roulette.min_depth
+      );
+      process::exit(1);
+    }
+  }
+  if let Some(tonemap) = options.tonemap {
+    scene.tonemap = tonemap;
+  }
+  let integrator_kind = options.integrator.unwrap_or(IntegratorKind::ParPixel);
+
+  // SPPMは画素ごとの反復で描画するので、積分器を選べない
+  if scene.transport == TransportKind::ProgressivePhotonMapping && options.integrator.is_some() {
+    eprintln!("ERROR! `--integrator` cannot be used with progressive_photon_mapping");
+    process::exit(1);
+  }
+
+  // MLTの変異の重みは、光輸送が直接フィルムに書き込む寄与には掛けられない
+  if integrator_kind == IntegratorKind::Metropolis {
+    match scene.transport {
+      TransportKind::Bidirectional | TransportKind::LightTracing => {
+        eprintln!(
+          "ERROR! `--integrator metropolis` cannot be used with bidirectional or light_tracing"
+        );
+        process::exit(1);
+      }
+      _ => (),
+    }
+  }
+
+  // 点光源などは光源を明示的にサンプリングする光輸送でしか描画できない
+  if !scene.delta_lights().is_empty() {
+    match scene.transport {
+      TransportKind::Naive
+      | TransportKind::Bidirectional
+      | TransportKind::LightTracing
+      | TransportKind::PhotonMapping
+      | TransportKind::ProgressivePhotonMapping => {
+        eprintln!(
+          "ERROR! point, spot and directional lights can only be rendered with explicit_light, only_light or volumetric_path"
+        );
+        process::exit(1);
+      }
+      _ => (),
+    }
+  }
+
+  // 保存先
+  let file_path = options.output.clone().unwrap_or_else(|| {
+    PathBuf::from(format!(
+      "images/image_{}_{}.{}",
+      time::now().strftime("%Y%m%d%H%M%S").unwrap(),
+      scene.spp,
+      match options.format {
+        FormatKind::PNG => PNG::ext(),
+        FormatKind::PPM => PPM::ext(),
+      },
+    ))
   });
-  let mut uuid = UUID::new();
-  let mut objects = Vec::new();
-  let cbox = loader::Obj::new(Path::new("models/simple/cbox.obj"));
-  let luminaire = loader::Obj::new(Path::new("models/simple/cbox_luminaire.obj"));
-  // let bunny = loader::Obj::new(Path::new("models/bunny/cbox_bunny.obj"));
-  objects.append(&mut cbox.instances(&mat, &mut uuid));
-  objects.append(&mut luminaire.instances(&mat, &mut uuid));
-  // objects.append(&mut bunny.instances(&grossy1, &mut uuid));
-  let ball = Box::new(geometry::Sphere::new(
-    Vector3::new(278.0 - 100.0, 100.0, 278.0 - 100.0),
-    100.0,
-    &mut uuid,
-  ));
-  objects.push(object::Object::new(ball, Matrix4::unit(), &grossy1));
-  // let veach_mis = loader::Obj::new(Path::new("models/veach-mis/veach-mis.obj"));
-  // objects.append(&mut veach_mis.instances(&mat, &mut uuid));
+  // レンダリング後に保存できないことが分からないように先に確認する
+  match file_path.parent() {
+    Some(dir) if dir != Path::new("") && !dir.is_dir() => {
+      eprintln!("ERROR! output directory `{}` does not exist", dir.display());
+      process::exit(1);
+    }
+    _ => (),
+  }
+
+  // フィルム
+  let mut film = scene.film();
+
+  // カメラ
+  let camera = scene.camera();
 
   // 空間構造
-  let structure = acceleration::BVH::new(objects);
+  let structure = scene.structure();
 
-  // シードの読み込み
-  let args: Vec<String> = std::env::args().collect();
+  // カメラに直接接続した寄与を足し込むフィルム
+  let splat = film::Splat::new(scene.width, scene.height);
 
-  let seed: u64 = if args.len() >= 2 {
-    args[1].parse().unwrap()
-  } else {
-    rand::random()
+  // サンプラー
+  let seed: u64 = options.seed.unwrap_or_else(rand::random);
+  println!("Using seed: {}", seed);
+  let sampler: Box<dyn Sampler + Sync> = match scene.sampler {
+    SamplerKind::Independent => Box::new(sampler::Independent::new(seed)),
+    SamplerKind::Stratified => Box::new(sampler::Stratified::new(seed, scene.spp)),
+    SamplerKind::Halton => Box::new(sampler::Halton::new(seed)),
+    SamplerKind::Sobol => Box::new(sampler::Sobol::new(seed)),
   };
 
-  // 積分器
-  let mut integrator = integrator::ParPixel::new(&mut film, SPP);
   // 光輸送
-  let light_transporter = light_transport::Id::new(structure);
+  let environment = scene.environment();
+  let russian_roulette = scene.russian_roulette;
+  let light_transporter: Option<Box<dyn Radiance + Send + Sync>> = match scene.transport {
+    TransportKind::Naive => Some(Box::new(light_transport::Naive::new(
+      &structure,
+      environment,
+      russian_roulette,
+    ))),
+    TransportKind::ExplicitLight => Some(Box::new(light_transport::ExplicitLight::new(
+      &structure,
+      environment,
+      scene.delta_lights(),
+      russian_roulette,
+    ))),
+    TransportKind::OnlyLight => Some(Box::new(light_transport::OnlyLight::new(
+      &structure,
+      environment,
+      scene.delta_lights(),
+      russian_roulette,
+    ))),
+    TransportKind::VolumetricPath => Some(Box::new(light_transport::VolumetricPath::new(
+      &structure,
+      environment,
+      scene.delta_lights(),
+      russian_roulette,
+      scene.camera_medium(),
+    ))),
+    TransportKind::Bidirectional => Some(Box::new(light_transport::Bidirectional::new(
+      &structure,
+      &camera,
+      environment,
+      russian_roulette,
+      &splat,
+    ))),
+    TransportKind::LightTracing => Some(Box::new(light_transport::LightTracing::new(
+      &structure,
+      &camera,
+      environment,
+      russian_roulette,
+      &splat,
+    ))),
+    TransportKind::PhotonMapping => Some(Box::new(light_transport::PhotonMapping::new(
+      &structure,
+      environment,
+      russian_roulette,
+      &*sampler,
+      scene.density_estimation,
+    ))),
+    // 画素ごとの統計量を反復して更新するので、積分器を使わずにここで描画する
+    // (sppを反復回数として使う)
+    TransportKind::ProgressivePhotonMapping => {
+      light_transport::ProgressivePhotonMapping::new(
+        &structure,
+        &camera,
+        environment,
+        russian_roulette,
+        scene.density_estimation,
+      )
+      .render(&mut film, scene.spp, &*sampler);
+      None
+    }
+    TransportKind::Normal => Some(Box::new(light_transport::Normal::new(structure))),
+    TransportKind::Id => Some(Box::new(light_transport::Id::new(structure))),
+    TransportKind::IntersectionTest => {
+      Some(Box::new(light_transport::IntersectionTest::new(&structure)))
+    }
+  };
 
-  integrator.each(|u, v| {
-    let ray = camera.sample(u, v);
-    light_transporter.radiance(ray.value)
-  });
+  // 積分器
+  if let Some(light_transporter) = light_transporter {
+    match integrator_kind {
+      IntegratorKind::ParPixel => render(
+        &mut integrator::ParPixel::new(&mut film, scene.spp, &*sampler),
+        &camera,
+        &*light_transporter,
+      ),
+      IntegratorKind::ParDebug => render(
+        &mut integrator::ParDebug::new(&mut film, scene.spp, &*sampler),
+        &camera,
+        &*light_transporter,
+      ),
+      IntegratorKind::Debug => render(
+        &mut integrator::Debug::new(&mut film, scene.spp, &*sampler),
+        &camera,
+        &*light_transporter,
+      ),
+      IntegratorKind::Metropolis => render(
+        &mut integrator::Metropolis::new(&mut film, scene.spp, seed),
+        &camera,
+        &*light_transporter,
+      ),
+    }
+  }
+
+  splat.apply(&mut film, 1.0 / scene.spp as f32);
 
   // NAN, INFINITY チェック
   film.validate();
 
   // 保存
-  let file_path = &format!(
-    "images/image_{}_{}.{}",
-    time::now().strftime("%Y%m%d%H%M%S").unwrap(),
-    SPP,
-    Image::ext(),
-  );
-  // let tonemap = tonemap::Debug {
-  //   colormap: scarlet::colormap::ListedColorMap::viridis(),
-  //   clamp: (0.0, 1.0),
-  // };
-  let tonemap = tonemap::Gamma::default();
-  Image::save(&film, Path::new(&file_path), tonemap)
+  match options.format {
+    FormatKind::PNG => save::<PNG>(&film, &file_path, scene.tonemap),
+    FormatKind::PPM => save::<PPM>(&film, &file_path, scene.tonemap),
+  }
+  println!("saved: {}", file_path.display());
+}
+
+fn render<I, C>(integrator: &mut I, camera: &C, light_transporter: &(dyn Radiance + Send + Sync))
+where
+  I: Integrator<Vector3>,
+  C: Camera + Sync,
+{
+  integrator.each(|u, v| {
+    let ray = camera.sample(u, v);
+    light_transporter.radiance(ray.value)
+  });
+}
+
+fn save<F>(film: &Film<Vector3>, path: &Path, tonemap: TonemapKind)
+where
+  F: Save<Vector3, Output = [u8; 3]>,
+{
+  match tonemap {
+    TonemapKind::Linear => F::save(film, path, tonemap::Linear),
+    TonemapKind::Gamma(gamma) => F::save(film, path, tonemap::Gamma::new(gamma)),
+    TonemapKind::Debug => F::save(film, path, tonemap::Debug::default()),
+  }
 }
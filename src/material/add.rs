@@ -0,0 +1,73 @@
+use super::Material;
+use math::*;
+use sample::*;
+use sampler::Roulette;
+
+/**
+ * 2つのマテリアルのBSDFの和
+ *
+ * 同じ確率でどちらかのマテリアルを選んでサンプリングする
+ * エネルギーが保存されるかどうかは組み合わせるマテリアルによる
+ * 完全鏡面のマテリアルは足せない
+ */
+pub struct Add {
+  pub a: Box<dyn Material + Send + Sync>,
+  pub b: Box<dyn Material + Send + Sync>,
+}
+
+impl Material for Add {
+  fn brdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Vector3 {
+    self.a.brdf(wi, wo, n, t, uv, in_to_out) + self.b.brdf(wi, wo, n, t, uv, in_to_out)
+  }
+
+  fn sample(
+    &self,
+    wi: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Sample<Vector3, pdf::SolidAngle> {
+    let wo = if Roulette::within(0.5) {
+      self.b.sample(wi, n, t, uv, in_to_out).value
+    } else {
+      self.a.sample(wi, n, t, uv, in_to_out).value
+    };
+    // 選ばなかったマテリアルも含めた確率密度
+    let pdf = self.pdf(wi, wo, n, t, uv, in_to_out);
+    Sample {
+      value: wo,
+      pdf: pdf,
+    }
+  }
+
+  fn pdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> pdf::SolidAngle {
+    let pdf =
+      (self.a.pdf(wi, wo, n, t, uv, in_to_out).0 + self.b.pdf(wi, wo, n, t, uv, in_to_out).0) / 2.0;
+    pdf::SolidAngle(pdf)
+  }
+
+  fn is_delta(&self) -> bool {
+    self.a.is_delta() && self.b.is_delta()
+  }
+
+  fn is_transmissive(&self) -> bool {
+    self.a.is_transmissive() || self.b.is_transmissive()
+  }
+}
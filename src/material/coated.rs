@@ -0,0 +1,165 @@
+use super::physics::*;
+use super::GGXDistribution;
+use super::Material;
+use math::*;
+use sample::*;
+use sampler::Roulette;
+use texture::Texture;
+
+/**
+ * 誘電体のコーティング層に覆われたマテリアル
+ *
+ * 表面でGGXのマイクロファセットによる鏡面反射をし、
+ * 入射と出射で透過した割合 (1 - F) の光が下のマテリアルで散乱する
+ * (層の中での屈折による方向の変化と多重反射は無視する近似)
+ * 入射方向のフレネル反射率の確率でコーティングを選んでサンプリングする
+ */
+pub struct Coated {
+  // コーティングの下のマテリアル
+  pub base: Box<dyn Material + Send + Sync>,
+  // コーティングの表面のラフネス
+  pub roughness: Box<dyn Texture<f32> + Send + Sync>,
+  // コーティングの屈折率
+  pub ior: f32,
+}
+
+impl Coated {
+  fn distribution(&self, uv: (f32, f32)) -> GGXDistribution {
+    GGXDistribution::new(self.roughness.value(uv), 0.0)
+  }
+
+  /**
+   * 外側から方向wで入射した光がコーティングで反射する割合
+   */
+  fn fresnel(&self, w: Vector3, n: Vector3) -> f32 {
+    let n = if w.dot(n) < 0.0 { -n } else { n };
+    w.refract(n, 1.0 / self.ior)
+      .map(|wt| Fresnel::ior(w, wt, n, 1.0, self.ior))
+      .unwrap_or(1.0)
+  }
+}
+
+impl Material for Coated {
+  fn brdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Vector3 {
+    // コーティングを透過して下のマテリアルで散乱する成分
+    let transmittance = (1.0 - self.fresnel(wi, n)) * (1.0 - self.fresnel(wo, n));
+    let base = self.base.brdf(wi, wo, n, t, uv, in_to_out) * transmittance;
+    // コーティングの表面の鏡面反射 (接空間で評価する)
+    let inverse = GGXDistribution::frame(n, t).transpose();
+    let (wi, wo) = (&inverse * wi, &inverse * wo);
+    if wi.z <= 0.0 || wo.z <= 0.0 {
+      return base;
+    }
+    let distribution = self.distribution(uv);
+    // ハーフベクトル
+    let wh = (wo + wi).normalize();
+    let f = self.fresnel(wi, wh);
+    let g = distribution.g(wi, wo, wh);
+    let d = distribution.d(wh);
+    base + Vector3::fill(f * g * d / (4.0 * wi.z * wo.z))
+  }
+
+  fn sample(
+    &self,
+    wi: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Sample<Vector3, pdf::SolidAngle> {
+    // フレネル反射率の確率でコーティングか下のマテリアルを選ぶ
+    let wo = if Roulette::within(self.fresnel(wi, n)) {
+      let frame = GGXDistribution::frame(n, t);
+      &frame
+        * self
+          .distribution(uv)
+          .sample_reflection(&frame.transpose() * wi)
+    } else {
+      self.base.sample(wi, n, t, uv, in_to_out).value
+    };
+    // 選ばなかった方も含めた確率密度
+    let pdf = self.pdf(wi, wo, n, t, uv, in_to_out);
+    Sample {
+      value: wo,
+      pdf: pdf,
+    }
+  }
+
+  fn pdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> pdf::SolidAngle {
+    let p = self.fresnel(wi, n);
+    let inverse = GGXDistribution::frame(n, t).transpose();
+    let coat = self
+      .distribution(uv)
+      .pdf_reflection(&inverse * wi, &inverse * wo);
+    let base = self.base.pdf(wi, wo, n, t, uv, in_to_out).0;
+    pdf::SolidAngle(p * coat + (1.0 - p) * base)
+  }
+
+  fn is_delta(&self) -> bool {
+    self.base.is_delta()
+  }
+
+  fn is_transmissive(&self) -> bool {
+    self.base.is_transmissive()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::{integrate_sphere, Lambertian};
+  use super::*;
+  use texture::Constant;
+
+  #[test]
+  fn energy_conservation_test() {
+    // 白いLambertianをコーティングしても反射率は1を超えず、
+    // サンプリングによる推定は半球全体の数値積分と一致する
+    let (n, t) = (Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+    let m = Coated {
+      base: Box::new(Lambertian {
+        albedo: Box::new(Constant::new(Vector3::fill(1.0))),
+      }),
+      roughness: Box::new(Constant::new(0.5)),
+      ior: 1.5,
+    };
+    for &theta in &[0.0f32, 45.0, 80.0] {
+      let theta = theta.to_radians();
+      let wi = Vector3::new(theta.sin(), 0.0, theta.cos());
+      let count = 200000;
+      let mut sampled = 0.0;
+      for _ in 0..count {
+        let s = m.sample(wi, n, t, (0.0, 0.0), false);
+        if s.pdf.0 > 0.0 && s.value.z > 0.0 {
+          let f = m.brdf(wi, s.value, n, t, (0.0, 0.0), false);
+          sampled += f.x * s.value.z / s.pdf.0;
+        }
+      }
+      let sampled = sampled / count as f32;
+      let integrated = integrate_sphere(&m, wi).0.x;
+      assert!(sampled <= 1.0, "{}", sampled);
+      assert!(
+        (sampled - integrated).abs() < 2e-2 * integrated,
+        "{}: {} {}",
+        theta,
+        sampled,
+        integrated
+      );
+    }
+  }
+}
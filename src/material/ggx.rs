@@ -1,83 +1,203 @@
 use super::physics::*;
+use super::GGXDistribution;
 use super::Material;
 use math::*;
 use sample::*;
+use texture::Texture;
 use util::Finite;
 
 pub struct GGX {
   // 反射率
-  pub reflectance: Vector3,
+  pub reflectance: Box<dyn Texture<Vector3> + Send + Sync>,
   // ラフネス
-  pub roughness: f32,
+  pub roughness: Box<dyn Texture<f32> + Send + Sync>,
+  // 異方性 [0, 1) (0で等方的、大きいほど接線方向に伸びたハイライト)
+  pub anisotropy: f32,
 }
 
 impl GGX {
-  fn alpha(&self) -> f32 {
-    self.roughness * self.roughness
-  }
-
-  fn g_ggx(&self, wi: Vector3, wo: Vector3, n: Vector3) -> f32 {
-    self.g1(wo, n) * self.g1(wi, n)
-  }
-
-  fn g1(&self, v: Vector3, n: Vector3) -> f32 {
-    let a2 = self.alpha() * self.alpha();
-    let cos = v.dot(n);
-    let tan2 = 1.0 / cos.powi(2) - 1.0;
-    special::chi(cos) * 2.0 / (1.0 + (1.0 + a2 * tan2).sqrt())
-  }
-
-  fn d_ggx(&self, m: Vector3, n: Vector3) -> f32 {
-    let a2 = self.alpha().powi(2);
-    a2 / (PI * ((a2 - 1.0) * m.dot(n).powi(2) + 1.0).powi(2))
+  fn distribution(&self, uv: (f32, f32)) -> GGXDistribution {
+    GGXDistribution::new(self.roughness.value(uv), self.anisotropy)
   }
 }
 
 impl Material for GGX {
-  fn emittance(&self) -> Vector3 {
-    Vector3::zero()
-  }
-
-  fn brdf(&self, wi: Vector3, wo: Vector3, n: Vector3, _x: Vector3, _in_to_out: bool) -> Vector3 {
+  fn brdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    _in_to_out: bool,
+  ) -> Vector3 {
+    // 接空間で評価する
+    let inverse = GGXDistribution::frame(n, t).transpose();
+    let (wi, wo) = (&inverse * wi, &inverse * wo);
+    if wi.z <= 0.0 || wo.z <= 0.0 {
+      return Vector3::zero();
+    }
+    let distribution = self.distribution(uv);
     // ハーフベクトル
     let wh = (wo + wi).normalize();
     // Torrance-Sparrow model
-    let f = Fresnel::schlick(self.reflectance, wo, wh);
-    debug_assert!(f.x > 0.0 && f.is_finite(), "f: {}", f);
-    let g = self.g_ggx(wi, wo, n);
+    let f = Fresnel::schlick(self.reflectance.value(uv), wo, wh);
+    debug_assert!(f.x >= 0.0 && f.is_finite(), "f: {}", f);
+    let g = distribution.g(wi, wo, wh);
     debug_assert!(g >= 0.0 && g <= 1.0 && g.is_finite(), "g: {}", g);
-    let d = self.d_ggx(wh, n);
+    let d = distribution.d(wh);
     debug_assert!(d >= 0.0 && d.is_finite(), "d: {}", d);
-    f * (g * d / (4.0 * wi.dot(n) * wo.dot(n)))
+    f * (g * d / (4.0 * wi.z * wo.z))
   }
 
-  fn sample(&self, wi: Vector3, n: Vector3, in_to_out: bool) -> Sample<Vector3, pdf::SolidAngle> {
-    // 法線方向を基準にした正規直交基底を生成
-    let basis = n.orthonormal_basis();
-    // 球面極座標を用いて反射点から単位半球面上のある一点へのベクトルを生成
-    // (brdfの分布にしたがって重点的にサンプル)
-    let r1 = 2.0 * PI * rand::random::<f32>();
-    let r2 = rand::random::<f32>();
-    let tan = self.alpha() * r2.sqrt() / (1.0 - r2).sqrt();
-    let cos = 1.0 / (1.0 + tan * tan).sqrt();
-    let sin = tan * cos;
-    // ハーフベクトルをサンプリング
-    let wh = &basis * Vector3::new(r1.cos() * sin, r1.sin() * sin, cos);
-    // 入射ベクトル
-    let wo = wi.reflect(wh);
+  fn sample(
+    &self,
+    wi: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Sample<Vector3, pdf::SolidAngle> {
+    let frame = GGXDistribution::frame(n, t);
+    // wiから見えるマイクロファセット法線で反射 (マイクロファセットの裏側に出た場合はBRDFが0になる)
+    let wo = &frame
+      * self
+        .distribution(uv)
+        .sample_reflection(&frame.transpose() * wi);
     // 確率密度関数
-    let pdf = self.pdf(wi, wo, n, in_to_out);
+    let pdf = self.pdf(wi, wo, n, t, uv, in_to_out);
     Sample {
       value: wo,
       pdf: pdf,
     }
   }
 
-  fn pdf(&self, wi: Vector3, wo: Vector3, n: Vector3, _in_to_out: bool) -> pdf::SolidAngle {
-    // ハーフベクトル
-    let wh = (wo + wi).normalize();
-    // 確率密度関数
-    let pdf = self.d_ggx(wh, n) * wh.dot(n) / (4.0 * wo.dot(n) * wi.dot(wh));
-    pdf::SolidAngle(pdf)
+  fn pdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    _in_to_out: bool,
+  ) -> pdf::SolidAngle {
+    let inverse = GGXDistribution::frame(n, t).transpose();
+    let (wi, wo) = (&inverse * wi, &inverse * wo);
+    pdf::SolidAngle(self.distribution(uv).pdf_reflection(wi, wo))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use texture::Constant;
+
+  fn material(roughness: f32, anisotropy: f32) -> GGX {
+    GGX {
+      reflectance: Box::new(Constant::new(Vector3::fill(1.0))),
+      roughness: Box::new(Constant::new(roughness)),
+      anisotropy: anisotropy,
+    }
+  }
+
+  fn direction(theta: f32, phi: f32) -> Vector3 {
+    let theta = theta.to_radians();
+    let phi = phi.to_radians();
+    Vector3::new(
+      theta.sin() * phi.cos(),
+      theta.sin() * phi.sin(),
+      theta.cos(),
+    )
+  }
+
+  #[test]
+  fn furnace_test() {
+    let (n, t) = (Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+    // 反射率1では BRDF * cos / pdf = G1(wo) <= 1 で、期待値 (白色炉試験) は1に近くなる
+    // (多重散乱を無視しているので、ラフネスが大きいほど1より小さくなる)
+    for &(roughness, anisotropy, theta) in &[
+      (0.2, 0.0, 30.0),
+      (0.5, 0.0, 60.0),
+      (0.5, 0.8, 45.0),
+      (0.3, 0.0, 85.0),
+    ] {
+      let m = material(roughness, anisotropy);
+      let wi = direction(theta, 20.0);
+      let count = 100000;
+      let mut sum = 0.0;
+      for _ in 0..count {
+        let s = m.sample(wi, n, t, (0.0, 0.0), false);
+        let w = m.brdf(wi, s.value, n, t, (0.0, 0.0), false).x * s.value.dot(n).max(0.0) / s.pdf.0;
+        assert!(w <= 1.0 + 1e-3, "{}", w);
+        sum += w;
+      }
+      let mean = sum / count as f32;
+      assert!(
+        mean > 0.75 && mean <= 1.0,
+        "{} {} {}: {}",
+        roughness,
+        anisotropy,
+        theta,
+        mean
+      );
+    }
+  }
+
+  #[test]
+  fn chi_square_test() {
+    let (n, t) = (Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+    // サンプリングした方向の度数分布と、pdfを各区間で積分した期待度数が一致する
+    let (nt, np) = (16, 32);
+    let count = 200000;
+    for &(roughness, anisotropy, theta) in &[(0.4, 0.0, 40.0), (0.6, 0.7, 70.0)] {
+      let m = material(roughness, anisotropy);
+      let wi = direction(theta, 30.0);
+      // 球面を cosθ と φ で等分した区間 (面の裏側に出た方向も含める)
+      let bin = |w: Vector3| {
+        let i = (((1.0 - w.z) / 2.0 * nt as f32) as usize).min(nt - 1);
+        let phi = w.y.atan2(w.x) + PI;
+        let j = ((phi / (2.0 * PI) * np as f32) as usize).min(np - 1);
+        i * np + j
+      };
+      let mut observed = vec![0.0; nt * np];
+      for _ in 0..count {
+        let s = m.sample(wi, n, t, (0.0, 0.0), false);
+        observed[bin(s.value)] += 1.0;
+      }
+      let sub = 8;
+      let mut expected = vec![0.0; nt * np];
+      for i in 0..nt * sub {
+        for j in 0..np * sub {
+          let cos = 1.0 - 2.0 * (i as f32 + 0.5) / (nt * sub) as f32;
+          let sin = (1.0 - cos * cos).sqrt();
+          let phi = 2.0 * PI * (j as f32 + 0.5) / (np * sub) as f32 - PI;
+          let w = Vector3::new(sin * phi.cos(), sin * phi.sin(), cos);
+          let dw = 4.0 * PI / (nt * np * sub * sub) as f32;
+          expected[bin(w)] += m.pdf(wi, w, n, t, (0.0, 0.0), false).0 * dw * count as f32;
+        }
+      }
+      // 期待度数が少ない区間はまとめる
+      let mut chi2 = 0.0;
+      let mut dof = 0;
+      let (mut pooled_o, mut pooled_e) = (0.0, 0.0);
+      for k in 0..nt * np {
+        if expected[k] < 5.0 {
+          pooled_o += observed[k];
+          pooled_e += expected[k];
+        } else {
+          chi2 += (observed[k] - expected[k]).powi(2) / expected[k];
+          dof += 1;
+        }
+      }
+      if pooled_e >= 5.0 {
+        chi2 += (pooled_o - pooled_e).powi(2) / pooled_e;
+        dof += 1;
+      }
+      let dof = (dof - 1) as f32;
+      // Wilson-Hilferty近似で標準正規分布に直した値
+      let z =
+        ((chi2 / dof).powf(1.0 / 3.0) - (1.0 - 2.0 / (9.0 * dof))) / (2.0 / (9.0 * dof)).sqrt();
+      assert!(z < 4.0, "chi2 = {}, dof = {}, z = {}", chi2, dof, z);
+    }
   }
 }
@@ -0,0 +1,64 @@
+use super::physics::*;
+use super::Material;
+use math::*;
+use sample::*;
+
+/**
+ * 滑らかな導体 (金属) の完全鏡面反射
+ *
+ * 反射率は複素屈折率による導体のフレネル反射率
+ */
+pub struct IdealConductor {
+  // 複素屈折率
+  pub ior: ComplexIor,
+}
+
+impl Material for IdealConductor {
+  fn brdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    _t: Vector3,
+    _uv: (f32, f32),
+    _in_to_out: bool,
+  ) -> Vector3 {
+    if wo.dot(n) <= 0.0 {
+      return Vector3::zero();
+    }
+    Fresnel::conductor(wi.dot(n), &self.ior) * (distribution::DELTA_FUNCTION / wo.dot(n))
+  }
+
+  fn sample(
+    &self,
+    wi: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Sample<Vector3, pdf::SolidAngle> {
+    let wo = wi.reflect(n);
+    // 確率密度関数
+    let pdf = self.pdf(wi, wo, n, t, uv, in_to_out);
+    Sample {
+      value: wo,
+      pdf: pdf,
+    }
+  }
+
+  fn pdf(
+    &self,
+    _wi: Vector3,
+    _wo: Vector3,
+    _n: Vector3,
+    _t: Vector3,
+    _uv: (f32, f32),
+    _in_to_out: bool,
+  ) -> pdf::SolidAngle {
+    pdf::SolidAngle(distribution::DELTA_FUNCTION)
+  }
+
+  fn is_delta(&self) -> bool {
+    true
+  }
+}
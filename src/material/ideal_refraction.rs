@@ -23,11 +23,15 @@ impl IdealRefraction {
 }
 
 impl Material for IdealRefraction {
-  fn emittance(&self) -> Vector3 {
-    Vector3::zero()
-  }
-
-  fn brdf(&self, wi: Vector3, wo: Vector3, n: Vector3, _x: Vector3, in_to_out: bool) -> Vector3 {
+  fn brdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    _t: Vector3,
+    _uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Vector3 {
     let (ni, no) = self.ior(in_to_out);
     let coef = wi
       // 屈折
@@ -48,7 +52,14 @@ impl Material for IdealRefraction {
     self.reflectance * distribution::DELTA_FUNCTION * coef
   }
 
-  fn sample(&self, wi: Vector3, n: Vector3, in_to_out: bool) -> Sample<Vector3, pdf::SolidAngle> {
+  fn sample(
+    &self,
+    wi: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Sample<Vector3, pdf::SolidAngle> {
     debug_assert!(wi.dot(n) > 0.0);
     let (ni, no) = self.ior(in_to_out);
     let wo = wi
@@ -67,14 +78,22 @@ impl Material for IdealRefraction {
       // 全反射
       .unwrap_or(wi.reflect(n));
     // 確率密度関数
-    let pdf = self.pdf(wi, wo, n, in_to_out);
+    let pdf = self.pdf(wi, wo, n, t, uv, in_to_out);
     Sample {
       value: wo,
       pdf: pdf,
     }
   }
 
-  fn pdf(&self, wi: Vector3, wo: Vector3, n: Vector3, in_to_out: bool) -> pdf::SolidAngle {
+  fn pdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    _t: Vector3,
+    _uv: (f32, f32),
+    in_to_out: bool,
+  ) -> pdf::SolidAngle {
     let (ni, no) = self.ior(in_to_out);
     let p = wi
       .refract(n, ni / no)
@@ -93,6 +112,10 @@ impl Material for IdealRefraction {
   fn is_delta(&self) -> bool {
     true
   }
+
+  fn is_transmissive(&self) -> bool {
+    true
+  }
 }
 
 #[cfg(test)]
@@ -2,16 +2,42 @@ use math::*;
 use sample::*;
 
 pub trait Material {
-  // 物体自体の放射成分
-  fn emittance(&self) -> Vector3;
-  // 入射ベクトル, 出射ベクトル, 法線ベクトル, 座標 -> BRDF
-  fn brdf(&self, wi: Vector3, wo: Vector3, n: Vector3, x: Vector3, in_to_out: bool) -> Vector3;
-  // 入射ベクトル, 法線ベクトル -> 出射ベクトル, 確率密度
-  fn sample(&self, wi: Vector3, n: Vector3, in_to_out: bool) -> Sample<Vector3, pdf::SolidAngle>;
-  // 入射ベクトル, 出射ベクトル, 法線ベクトル -> 確率密度
-  fn pdf(&self, wi: Vector3, wo: Vector3, n: Vector3, in_to_out: bool) -> pdf::SolidAngle;
+  // 入射ベクトル, 出射ベクトル, 法線ベクトル, 接線ベクトル, テクスチャ座標 -> BRDF
+  // (接線は異方性の向きで、法線に直交するとは限らない)
+  fn brdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Vector3;
+  // 入射ベクトル, 法線ベクトル, 接線ベクトル, テクスチャ座標 -> 出射ベクトル, 確率密度
+  fn sample(
+    &self,
+    wi: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Sample<Vector3, pdf::SolidAngle>;
+  // 入射ベクトル, 出射ベクトル, 法線ベクトル, 接線ベクトル, テクスチャ座標 -> 確率密度
+  fn pdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> pdf::SolidAngle;
   // 確率密度関数がDirac-Delta関数に依存するかどうか
   fn is_delta(&self) -> bool {
     false
   }
+  // 面の裏側に透過するかどうか (裏側の光源とも接続できる)
+  fn is_transmissive(&self) -> bool {
+    false
+  }
 }
@@ -0,0 +1,169 @@
+use super::physics::*;
+use math::*;
+use sampler::Sampling;
+use util::Finite;
+
+// 数値的に破綻しないラフネスの下限
+const MIN_ALPHA: f32 = 1e-3;
+
+/**
+ * 異方性GGXのマイクロファセット法線の分布
+ *
+ * 接空間 (接線, 従法線, 法線がそれぞれ x, y, z 軸) の方向ベクトルで評価する
+ * alpha_x, alpha_y は接線と従法線の方向のラフネス (ラフネスの2乗)
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct GGXDistribution {
+  pub alpha_x: f32,
+  pub alpha_y: f32,
+}
+
+impl GGXDistribution {
+  /**
+   * ラフネスと異方性 [0, 1) から分布を作る (Disney BRDFのパラメータ化)
+   */
+  pub fn new(roughness: f32, anisotropy: f32) -> Self {
+    let aspect = (1.0 - 0.9 * anisotropy).sqrt();
+    let alpha = roughness * roughness;
+    GGXDistribution {
+      alpha_x: (alpha / aspect).max(MIN_ALPHA),
+      alpha_y: (alpha * aspect).max(MIN_ALPHA),
+    }
+  }
+
+  /**
+   * 法線nと接線tから作る接空間の基底 (接空間 -> ワールド座標系)
+   *
+   * 接線は法線に直交化し、縮退している場合は任意の基底
+   */
+  pub fn frame(n: Vector3, t: Vector3) -> Matrix4 {
+    let t = t - n * n.dot(t);
+    if t.sqr_norm() < 1e-12 {
+      return n.orthonormal_basis();
+    }
+    let t = t.normalize();
+    [t, n.cross(t), n].into()
+  }
+
+  /**
+   * 法線分布 D(m)
+   */
+  pub fn d(&self, m: Vector3) -> f32 {
+    if m.z <= 0.0 {
+      return 0.0;
+    }
+    let x = m.x / self.alpha_x;
+    let y = m.y / self.alpha_y;
+    let e = x * x + y * y + m.z * m.z;
+    1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+  }
+
+  /**
+   * Smithのマスキング関数の Λ(v)
+   */
+  fn lambda(&self, v: Vector3) -> f32 {
+    let tan2 = ((self.alpha_x * v.x).powi(2) + (self.alpha_y * v.y).powi(2)) / (v.z * v.z);
+    ((1.0 + tan2).sqrt() - 1.0) / 2.0
+  }
+
+  /**
+   * マスキング関数 G1(v, m)
+   *
+   * 方向vがマイクロファセット法線mと法線の表裏の同じ側にある場合のみ見える (屈折にも使える)
+   */
+  pub fn g1(&self, v: Vector3, m: Vector3) -> f32 {
+    if v.z == 0.0 || v.dot(m) * v.z <= 0.0 {
+      return 0.0;
+    }
+    1.0 / (1.0 + self.lambda(v))
+  }
+
+  /**
+   * 入射方向と出射方向のマスキング・シャドウイング関数 (分離形)
+   */
+  pub fn g(&self, wi: Vector3, wo: Vector3, m: Vector3) -> f32 {
+    self.g1(wi, m) * self.g1(wo, m)
+  }
+
+  /**
+   * 方向v (v.z > 0) から見える法線分布 G1(v) max(0, v・m) D(m) / v.z に比例したマイクロファセット法線のサンプリング
+   *
+   * vから見て裏を向いたマイクロファセットは選ばれない (Heitz 2018)
+   */
+  pub fn sample_visible(&self, v: Vector3) -> Vector3 {
+    debug_assert!(v.z > 0.0, "{}", v);
+    // ラフネス1の半球に引き伸ばした空間での視線
+    let vh = Vector3::new(self.alpha_x * v.x, self.alpha_y * v.y, v.z).normalize();
+    // 視線に直交する基底
+    let sqr_len = vh.x * vh.x + vh.y * vh.y;
+    let t1 = if sqr_len > 0.0 {
+      Vector3::new(-vh.y, vh.x, 0.0) / sqr_len.sqrt()
+    } else {
+      Vector3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = vh.cross(t1);
+    // 視線から見た半球の射影 (円板と半楕円) 上で一様に点をサンプリング
+    let (u1, u2) = Sampling::get_2d();
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+    let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+    // 元の空間に戻す
+    Vector3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize()
+  }
+
+  /**
+   * sample_visible でマイクロファセット法線mがサンプリングされる確率密度
+   */
+  pub fn pdf_visible(&self, v: Vector3, m: Vector3) -> f32 {
+    if v.z <= 0.0 {
+      return 0.0;
+    }
+    self.g1(v, m) * v.dot(m).max(0.0) * self.d(m) / v.z
+  }
+
+  /**
+   * 見える法線で鏡面反射した方向のサンプリング (マイクロファセットの裏側に出る場合もある)
+   */
+  pub fn sample_reflection(&self, wi: Vector3) -> Vector3 {
+    wi.reflect(self.sample_visible(wi))
+  }
+
+  /**
+   * sample_reflection で方向woが生成される確率密度
+   */
+  pub fn pdf_reflection(&self, wi: Vector3, wo: Vector3) -> f32 {
+    // ハーフベクトル
+    let wh = (wo + wi).normalize();
+    if wo.dot(wh) <= 0.0 || !wh.is_finite() {
+      return 0.0;
+    }
+    // 見える法線分布の確率密度 * dωh / dωo (= 1 / (4 wo・wh))
+    self.pdf_visible(wi, wh) / (4.0 * wo.dot(wh))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::quadrature;
+  use super::*;
+
+  #[test]
+  fn normalization_test() {
+    // 投影した法線分布 D(m) m.z と見える法線分布は半球全体で積分すると1になる
+    let v = Vector3::new(0.6, 0.3, 0.74).normalize();
+    for &(roughness, anisotropy) in &[(0.3, 0.0), (0.6, 0.0), (0.5, 0.8)] {
+      let dist = GGXDistribution::new(roughness, anisotropy);
+      let mut projected = 0.0;
+      let mut visible = 0.0;
+      quadrature(true, |m, dw| {
+        projected += dist.d(m) * m.z * dw;
+        visible += dist.pdf_visible(v, m) * dw;
+      });
+      assert!((projected - 1.0).abs() < 2e-2, "{}", projected);
+      assert!((visible - 1.0).abs() < 2e-2, "{}", visible);
+    }
+  }
+}
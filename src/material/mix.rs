@@ -0,0 +1,117 @@
+use super::Material;
+use math::*;
+use sample::*;
+use sampler::Roulette;
+use texture::Texture;
+
+/**
+ * 2つのマテリアルを重み (マスク) で線形補間したマテリアル
+ *
+ * 重みの確率でどちらかのマテリアルを選んでサンプリングし、確率密度も同じ重みで混ぜる
+ * 完全鏡面のマテリアルは混ぜられない
+ */
+pub struct Mix {
+  pub a: Box<dyn Material + Send + Sync>,
+  pub b: Box<dyn Material + Send + Sync>,
+  // bの割合 [0, 1]
+  pub weight: Box<dyn Texture<f32> + Send + Sync>,
+}
+
+impl Mix {
+  fn weight(&self, uv: (f32, f32)) -> f32 {
+    self.weight.value(uv).clamp(0.0, 1.0)
+  }
+}
+
+impl Material for Mix {
+  fn brdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Vector3 {
+    let w = self.weight(uv);
+    self.a.brdf(wi, wo, n, t, uv, in_to_out) * (1.0 - w)
+      + self.b.brdf(wi, wo, n, t, uv, in_to_out) * w
+  }
+
+  fn sample(
+    &self,
+    wi: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Sample<Vector3, pdf::SolidAngle> {
+    // 重みの確率でマテリアルを選ぶ
+    let wo = if Roulette::within(self.weight(uv)) {
+      self.b.sample(wi, n, t, uv, in_to_out).value
+    } else {
+      self.a.sample(wi, n, t, uv, in_to_out).value
+    };
+    // 選ばなかったマテリアルも含めた確率密度
+    let pdf = self.pdf(wi, wo, n, t, uv, in_to_out);
+    Sample {
+      value: wo,
+      pdf: pdf,
+    }
+  }
+
+  fn pdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> pdf::SolidAngle {
+    let w = self.weight(uv);
+    let pdf = self.a.pdf(wi, wo, n, t, uv, in_to_out).0 * (1.0 - w)
+      + self.b.pdf(wi, wo, n, t, uv, in_to_out).0 * w;
+    pdf::SolidAngle(pdf)
+  }
+
+  fn is_delta(&self) -> bool {
+    self.a.is_delta() && self.b.is_delta()
+  }
+
+  fn is_transmissive(&self) -> bool {
+    self.a.is_transmissive() || self.b.is_transmissive()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::{integrate_sphere, Lambertian, GGX};
+  use super::*;
+  use texture::Constant;
+
+  #[test]
+  fn pdf_test() {
+    // 混ぜた確率密度は球面全体で積分すると1になり、BSDFはそれぞれの重み付きの和になる
+    let (n, t) = (Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+    let m = Mix {
+      a: Box::new(Lambertian {
+        albedo: Box::new(Constant::new(Vector3::fill(0.5))),
+      }),
+      b: Box::new(GGX {
+        reflectance: Box::new(Constant::new(Vector3::fill(0.9))),
+        roughness: Box::new(Constant::new(0.5)),
+        anisotropy: 0.0,
+      }),
+      weight: Box::new(Constant::new(0.3)),
+    };
+    let wi = Vector3::new(0.3, 0.0, 0.95).normalize();
+    let wo = Vector3::new(-0.2, 0.4, 0.9).normalize();
+    let f = m.brdf(wi, wo, n, t, (0.0, 0.0), false);
+    let fa = m.a.brdf(wi, wo, n, t, (0.0, 0.0), false);
+    let fb = m.b.brdf(wi, wo, n, t, (0.0, 0.0), false);
+    assert!(f.x.approx_eq(fa.x * 0.7 + fb.x * 0.3));
+    let (_, probability) = integrate_sphere(&m, wi);
+    assert!((probability - 1.0).abs() < 2e-2, "{}", probability);
+  }
+}
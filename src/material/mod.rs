@@ -1,13 +1,77 @@
+mod add;
 mod blinn;
+mod coated;
 mod ggx;
+mod ideal_conductor;
 mod ideal_refraction;
 mod lambertian;
 mod material;
+mod microfacet;
+mod mix;
 mod physics;
+mod principled;
+mod rough_conductor;
+mod rough_refraction;
 
+pub use self::add::*;
 pub use self::blinn::*;
+pub use self::coated::*;
 pub use self::ggx::*;
+pub use self::ideal_conductor::*;
 pub use self::ideal_refraction::*;
 pub use self::lambertian::*;
 pub use self::material::*;
+pub use self::microfacet::*;
+pub use self::mix::*;
 pub use self::physics::*;
+pub use self::principled::*;
+pub use self::rough_conductor::*;
+pub use self::rough_refraction::*;
+
+#[cfg(test)]
+use math::*;
+
+/**
+ * 球面上の方向wと立体角dωについての和 (テストでの数値積分用)
+ *
+ * cosθ = ±(1 - u^2) で極付近を細かく刻む (upper_only なら上半球だけ)
+ */
+#[cfg(test)]
+fn quadrature<F: FnMut(Vector3, f32)>(upper_only: bool, mut f: F) {
+  let (nt, np) = (400, 400);
+  let signs: &[f32] = if upper_only { &[1.0] } else { &[1.0, -1.0] };
+  for &sign in signs {
+    for i in 0..nt {
+      let u0 = i as f32 / nt as f32;
+      let u1 = (i + 1) as f32 / nt as f32;
+      let cos0 = 1.0 - u0 * u0;
+      let cos1 = 1.0 - u1 * u1;
+      let cos = (cos0 + cos1) / 2.0;
+      let sin = (1.0 - cos * cos).sqrt();
+      for j in 0..np {
+        let phi = 2.0 * PI * (j as f32 + 0.5) / np as f32;
+        let w = Vector3::new(sin * phi.cos(), sin * phi.sin(), sign * cos);
+        f(w, (cos0 - cos1) * 2.0 * PI / np as f32);
+      }
+    }
+  }
+}
+
+/**
+ * 法線 +z, 接線 +x の面で方向wiから見た BSDF * |cos| と確率密度の積分 (テスト用)
+ *
+ * 確率密度は球面全体で、透過しないマテリアルのBSDFは上半球だけで積分する
+ */
+#[cfg(test)]
+fn integrate_sphere(m: &dyn Material, wi: Vector3) -> (Vector3, f32) {
+  let (n, t) = (Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+  let mut albedo = Vector3::zero();
+  let mut probability = 0.0;
+  quadrature(false, |wo, dw| {
+    if wo.z > 0.0 || m.is_transmissive() {
+      albedo += m.brdf(wi, wo, n, t, (0.0, 0.0), false) * (wo.z.abs() * dw);
+    }
+    probability += m.pdf(wi, wo, n, t, (0.0, 0.0), false).0 * dw;
+  });
+  (albedo, probability)
+}
@@ -38,4 +38,90 @@ impl Fresnel {
     let rp = ((ni * cos2 - no * cos1) / (ni * cos2 + no * cos1)).powi(2);
     (rs + rp) / 2.0
   }
+
+  /**
+   * 導体 (金属) のフレネル反射率
+   *
+   * cosは入射方向と法線のなす角のcos、複素屈折率 eta + ik はRGBそれぞれの値
+   */
+  pub fn conductor(cos: f32, ior: &ComplexIor) -> Vector3 {
+    let cos = cos.clamp(0.0, 1.0);
+    let cos2 = cos * cos;
+    let sin2 = 1.0 - cos2;
+    let f = |i: usize| {
+      let eta2 = ior.eta[i] * ior.eta[i];
+      let k2 = ior.k[i] * ior.k[i];
+      let t0 = eta2 - k2 - sin2;
+      let a2b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+      let t1 = a2b2 + cos2;
+      let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+      let t2 = 2.0 * cos * a;
+      let rs = (t1 - t2) / (t1 + t2);
+      let t3 = cos2 * a2b2 + sin2 * sin2;
+      let t4 = t2 * sin2;
+      let rp = rs * (t3 - t4) / (t3 + t4);
+      (rs + rp) / 2.0
+    };
+    Vector3::new(f(0), f(1), f(2))
+  }
+}
+
+/**
+ * 導体の複素屈折率 eta + ik (RGBの代表的な波長での値)
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct ComplexIor {
+  pub eta: Vector3,
+  pub k: Vector3,
+}
+
+impl ComplexIor {
+  /**
+   * 測定された金属の複素屈折率 (`Au`, `Ag`, `Cu`, `Al`, `Cr`)
+   */
+  pub fn preset(name: &str) -> Option<Self> {
+    let (eta, k) = match name {
+      "Au" => ((0.143119, 0.374957, 1.44248), (3.98316, 2.38572, 1.60322)),
+      "Ag" => ((0.155265, 0.116723, 0.138342), (4.82835, 3.12225, 2.14696)),
+      "Cu" => ((0.200438, 0.924033, 1.10221), (3.91295, 2.45285, 2.14219)),
+      "Al" => ((1.65746, 0.880369, 0.521229), (9.22387, 6.26952, 4.837)),
+      "Cr" => ((4.36968, 2.9167, 1.6547), (5.20643, 4.23136, 3.75495)),
+      _ => return None,
+    };
+    Some(ComplexIor {
+      eta: Vector3::new(eta.0, eta.1, eta.2),
+      k: Vector3::new(k.0, k.1, k.2),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn conductor_test() {
+    let gold = ComplexIor::preset("Au").unwrap();
+    // 垂直入射では ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2)
+    let f = Fresnel::conductor(1.0, &gold);
+    for i in 0..3 {
+      let (eta, k) = (gold.eta[i], gold.k[i]);
+      let expected = ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+      assert!((f[i] - expected).abs() < 1e-4, "{} {}", f[i], expected);
+    }
+    // 金は赤が青より強く反射し、すれすれの入射では全反射に近づく
+    assert!(f.x > f.z);
+    assert!(Fresnel::conductor(1e-4, &gold).x > 0.99);
+    // 消衰係数が0なら誘電体のフレネル反射率に一致する
+    let glass = ComplexIor {
+      eta: Vector3::fill(1.5),
+      k: Vector3::zero(),
+    };
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let wi = Vector3::new(0.6, 0.0, 0.8);
+    let wt = wi.refract(n, 1.0 / 1.5).unwrap();
+    let dielectric = Fresnel::ior(wi, wt, n, 1.0, 1.5);
+    let f = Fresnel::conductor(wi.z, &glass);
+    assert!((f.x - dielectric).abs() < 1e-4, "{} {}", f.x, dielectric);
+  }
 }
@@ -0,0 +1,314 @@
+use super::physics::*;
+use super::GGXDistribution;
+use super::Material;
+use super::RoughRefraction;
+use math::*;
+use sample::*;
+use sampler::Sampling;
+use texture::Texture;
+
+// クリアコートの垂直入射の反射率 (屈折率1.5相当)
+const CLEARCOAT_F0: f32 = 0.04;
+
+/**
+ * Disneyの原理に基づくBSDF (Burley 2012, 2015)
+ *
+ * 拡散反射とシーン (sheen)、金属と誘電体の鏡面反射、クリアコート、粗い誘電体の透過を
+ * パラメータで重み付けした和で、入射方向から見たおおよその寄与に比例してローブを選んでサンプリングする
+ */
+pub struct Principled {
+  // 基本色 (拡散反射の色と金属の反射率)
+  pub base_color: Box<dyn Texture<Vector3> + Send + Sync>,
+  // 金属らしさ [0, 1]
+  pub metallic: Box<dyn Texture<f32> + Send + Sync>,
+  // ラフネス
+  pub roughness: Box<dyn Texture<f32> + Send + Sync>,
+  // 異方性 [0, 1)
+  pub anisotropy: f32,
+  // 誘電体の鏡面反射の強さ [0, 1] (垂直入射の反射率は 0.08 * specular で、0.5が屈折率1.5相当)
+  pub specular: f32,
+  // 布のような縁の光沢の強さ [0, 1]
+  pub sheen: f32,
+  // 表面のコーティング層の強さ [0, 1] とラフネス
+  pub clearcoat: f32,
+  pub clearcoat_roughness: f32,
+  // 透過の割合 [0, 1] と透過光の色
+  pub transmission: f32,
+  pub transmission_color: Vector3,
+  // 透過に使う屈折率
+  pub ior: f32,
+}
+
+/**
+ * テクスチャ座標で評価したパラメータとローブの重み
+ */
+struct Lobes {
+  base_color: Vector3,
+  roughness: f32,
+  // 鏡面反射の垂直入射の反射率
+  f0: Vector3,
+  // 拡散反射, 鏡面反射, 透過の重み
+  diffuse: f32,
+  specular: f32,
+  transmission: f32,
+  distribution: GGXDistribution,
+  clearcoat: GGXDistribution,
+}
+
+impl Principled {
+  fn ior(&self, in_to_out: bool) -> (f32, f32) {
+    // (入射媒質屈折率, 出射媒質屈折率)
+    if in_to_out {
+      (self.ior, 1.0)
+    } else {
+      (1.0, self.ior)
+    }
+  }
+
+  fn lobes(&self, uv: (f32, f32)) -> Lobes {
+    let base_color = self.base_color.value(uv);
+    let metallic = self.metallic.value(uv).clamp(0.0, 1.0);
+    let roughness = self.roughness.value(uv);
+    let dielectric = Vector3::fill(0.08 * self.specular);
+    Lobes {
+      base_color: base_color,
+      roughness: roughness,
+      f0: dielectric * (1.0 - metallic) + base_color * metallic,
+      diffuse: (1.0 - metallic) * (1.0 - self.transmission),
+      specular: 1.0 - (1.0 - metallic) * self.transmission,
+      transmission: (1.0 - metallic) * self.transmission,
+      distribution: GGXDistribution::new(roughness, self.anisotropy),
+      clearcoat: GGXDistribution::new(self.clearcoat_roughness, 0.0),
+    }
+  }
+
+  /**
+   * 接空間の入射ベクトル -> 拡散反射, 鏡面反射, クリアコート, 透過のローブを選ぶ確率
+   *
+   * BSDFが0にならないローブの確率は0にしない
+   */
+  fn probabilities(&self, lobes: &Lobes, wi: Vector3) -> [f32; 4] {
+    let weights = [
+      lobes.diffuse * (lobes.base_color.max() + self.sheen),
+      lobes.specular
+        * Fresnel::schlick(lobes.f0, wi, Vector3::new(0.0, 0.0, 1.0))
+          .max()
+          .max(0.1),
+      0.25 * self.clearcoat * Self::schlick(CLEARCOAT_F0, wi.z),
+      lobes.transmission * self.transmission_color.max(),
+    ];
+    let sum = weights.iter().sum::<f32>();
+    if sum > 0.0 {
+      [
+        weights[0] / sum,
+        weights[1] / sum,
+        weights[2] / sum,
+        weights[3] / sum,
+      ]
+    } else {
+      [1.0, 0.0, 0.0, 0.0]
+    }
+  }
+
+  fn schlick(f0: f32, cos: f32) -> f32 {
+    f0 + (1.0 - f0) * (1.0 - cos).max(0.0).powi(5)
+  }
+
+  /**
+   * 接空間の入射ベクトル, 出射ベクトル -> BSDF
+   */
+  fn bsdf_local(&self, lobes: &Lobes, wi: Vector3, wo: Vector3, in_to_out: bool) -> Vector3 {
+    if wi.z <= 0.0 {
+      return Vector3::zero();
+    }
+    let (ni, no) = self.ior(in_to_out);
+    // 粗い誘電体の透過 (とその表面の反射)
+    let mut f = if lobes.transmission > 0.0 {
+      self.transmission_color
+        * (lobes.transmission * RoughRefraction::bsdf_local(&lobes.distribution, wi, wo, ni, no))
+    } else {
+      Vector3::zero()
+    };
+    if wo.z <= 0.0 {
+      return f;
+    }
+    // ハーフベクトル
+    let wh = (wo + wi).normalize();
+    let cos_d = wo.dot(wh);
+    // 拡散反射 (縁でのフレネルの減衰とラフネスによる再帰反射) とシーン (布のような縁の光沢)
+    if lobes.diffuse > 0.0 {
+      let fd90 = 0.5 + 2.0 * lobes.roughness * cos_d * cos_d;
+      let fd =
+        (1.0 + (fd90 - 1.0) * (1.0 - wi.z).powi(5)) * (1.0 + (fd90 - 1.0) * (1.0 - wo.z).powi(5));
+      let sheen = self.sheen * (1.0 - cos_d).max(0.0).powi(5);
+      f += (lobes.base_color * (fd / PI) + Vector3::fill(sheen)) * lobes.diffuse;
+    }
+    // 金属と誘電体の鏡面反射
+    if lobes.specular > 0.0 {
+      let distribution = &lobes.distribution;
+      let gd = distribution.g(wi, wo, wh) * distribution.d(wh);
+      f += Fresnel::schlick(lobes.f0, wo, wh) * (lobes.specular * gd / (4.0 * wi.z * wo.z));
+    }
+    // クリアコート
+    if self.clearcoat > 0.0 {
+      let distribution = &lobes.clearcoat;
+      let gd = distribution.g(wi, wo, wh) * distribution.d(wh);
+      let fc = Self::schlick(CLEARCOAT_F0, cos_d);
+      f += Vector3::fill(0.25 * self.clearcoat * fc * gd / (4.0 * wi.z * wo.z));
+    }
+    f
+  }
+
+  /**
+   * 接空間の入射ベクトル, 出射ベクトル -> 確率密度
+   */
+  fn pdf_local(&self, lobes: &Lobes, wi: Vector3, wo: Vector3, in_to_out: bool) -> f32 {
+    if wi.z <= 0.0 {
+      return 0.0;
+    }
+    let (ni, no) = self.ior(in_to_out);
+    let p = self.probabilities(lobes, wi);
+    let mut pdf = 0.0;
+    if p[0] > 0.0 && wo.z > 0.0 {
+      pdf += p[0] * wo.z / PI;
+    }
+    if p[1] > 0.0 {
+      pdf += p[1] * lobes.distribution.pdf_reflection(wi, wo);
+    }
+    if p[2] > 0.0 {
+      pdf += p[2] * lobes.clearcoat.pdf_reflection(wi, wo);
+    }
+    if p[3] > 0.0 {
+      pdf += p[3] * RoughRefraction::pdf_local(&lobes.distribution, wi, wo, ni, no);
+    }
+    pdf
+  }
+}
+
+impl Material for Principled {
+  fn brdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Vector3 {
+    // 接空間で評価する
+    let inverse = GGXDistribution::frame(n, t).transpose();
+    self.bsdf_local(&self.lobes(uv), &inverse * wi, &inverse * wo, in_to_out)
+  }
+
+  fn sample(
+    &self,
+    wi: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Sample<Vector3, pdf::SolidAngle> {
+    let frame = GGXDistribution::frame(n, t);
+    let inverse = frame.transpose();
+    let wi_local = &inverse * wi;
+    let lobes = self.lobes(uv);
+    let p = self.probabilities(&lobes, wi_local);
+    // 確率に従ってローブを選ぶ
+    let u = Sampling::get_1d();
+    let wo_local = if u < p[0] {
+      Sampling::hemisphere_cos_importance()
+    } else if u < p[0] + p[1] {
+      lobes.distribution.sample_reflection(wi_local)
+    } else if u < p[0] + p[1] + p[2] {
+      lobes.clearcoat.sample_reflection(wi_local)
+    } else {
+      let (ni, no) = self.ior(in_to_out);
+      RoughRefraction::sample_local(&lobes.distribution, wi_local, ni, no)
+    };
+    // 選ばなかったローブも含めた確率密度
+    let pdf = self.pdf_local(&lobes, wi_local, wo_local, in_to_out);
+    Sample {
+      value: &frame * wo_local,
+      pdf: pdf::SolidAngle(pdf),
+    }
+  }
+
+  fn pdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> pdf::SolidAngle {
+    let inverse = GGXDistribution::frame(n, t).transpose();
+    let pdf = self.pdf_local(&self.lobes(uv), &inverse * wi, &inverse * wo, in_to_out);
+    pdf::SolidAngle(pdf)
+  }
+
+  fn is_transmissive(&self) -> bool {
+    self.transmission > 0.0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::integrate_sphere;
+  use super::*;
+  use texture::Constant;
+
+  fn material(metallic: f32, roughness: f32, clearcoat: f32, transmission: f32) -> Principled {
+    Principled {
+      base_color: Box::new(Constant::new(Vector3::new(0.8, 0.5, 0.3))),
+      metallic: Box::new(Constant::new(metallic)),
+      roughness: Box::new(Constant::new(roughness)),
+      anisotropy: 0.0,
+      specular: 0.5,
+      sheen: 0.5,
+      clearcoat: clearcoat,
+      clearcoat_roughness: 0.3,
+      transmission: transmission,
+      transmission_color: Vector3::fill(1.0),
+      ior: 1.5,
+    }
+  }
+
+  #[test]
+  fn sampling_test() {
+    // サンプリングによる BSDF * |cos| / pdf の平均と、球面全体の数値積分が一致する
+    // (確率密度がサンプリングした方向の分布と一致していれば、どちらも同じ反射率の推定になる)
+    let (n, t) = (Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+    let wi = Vector3::new(0.5, 0.2, 0.84).normalize();
+    for &(metallic, roughness, clearcoat, transmission) in &[
+      (0.0, 0.5, 0.0, 0.0),
+      (1.0, 0.4, 1.0, 0.0),
+      (0.3, 0.6, 0.5, 0.7),
+    ] {
+      let m = material(metallic, roughness, clearcoat, transmission);
+      let count = 200000;
+      let mut sampled = 0.0;
+      for _ in 0..count {
+        let s = m.sample(wi, n, t, (0.0, 0.0), false);
+        if s.pdf.0 > 0.0 {
+          let f = m.brdf(wi, s.value, n, t, (0.0, 0.0), false);
+          sampled += f.y * s.value.dot(n).abs() / s.pdf.0;
+        }
+      }
+      let sampled = sampled / count as f32;
+      let (integrated, probability) = integrate_sphere(&m, wi);
+      let integrated = integrated.y;
+      assert!((probability - 1.0).abs() < 2e-2, "{}", probability);
+      assert!(
+        (sampled - integrated).abs() < 2e-2 * integrated.max(0.1),
+        "{} {} {} {}: {} {}",
+        metallic,
+        roughness,
+        clearcoat,
+        transmission,
+        sampled,
+        integrated
+      );
+    }
+  }
+}
@@ -0,0 +1,123 @@
+use super::physics::*;
+use super::GGXDistribution;
+use super::Material;
+use math::*;
+use sample::*;
+use texture::Texture;
+
+/**
+ * GGXのマイクロファセットによる粗い導体 (金属)
+ *
+ * 反射率は複素屈折率による導体のフレネル反射率で、Schlickの近似より縁の色の変化が正確になる
+ */
+pub struct RoughConductor {
+  // 複素屈折率
+  pub ior: ComplexIor,
+  // ラフネス
+  pub roughness: Box<dyn Texture<f32> + Send + Sync>,
+  // 異方性 [0, 1)
+  pub anisotropy: f32,
+}
+
+impl RoughConductor {
+  fn distribution(&self, uv: (f32, f32)) -> GGXDistribution {
+    GGXDistribution::new(self.roughness.value(uv), self.anisotropy)
+  }
+}
+
+impl Material for RoughConductor {
+  fn brdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    _in_to_out: bool,
+  ) -> Vector3 {
+    // 接空間で評価する
+    let inverse = GGXDistribution::frame(n, t).transpose();
+    let (wi, wo) = (&inverse * wi, &inverse * wo);
+    if wi.z <= 0.0 || wo.z <= 0.0 {
+      return Vector3::zero();
+    }
+    let distribution = self.distribution(uv);
+    // ハーフベクトル
+    let wh = (wo + wi).normalize();
+    let f = Fresnel::conductor(wo.dot(wh), &self.ior);
+    let g = distribution.g(wi, wo, wh);
+    let d = distribution.d(wh);
+    f * (g * d / (4.0 * wi.z * wo.z))
+  }
+
+  fn sample(
+    &self,
+    wi: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Sample<Vector3, pdf::SolidAngle> {
+    let frame = GGXDistribution::frame(n, t);
+    // wiから見えるマイクロファセット法線で反射
+    let wo = &frame
+      * self
+        .distribution(uv)
+        .sample_reflection(&frame.transpose() * wi);
+    // 確率密度関数
+    let pdf = self.pdf(wi, wo, n, t, uv, in_to_out);
+    Sample {
+      value: wo,
+      pdf: pdf,
+    }
+  }
+
+  fn pdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    _in_to_out: bool,
+  ) -> pdf::SolidAngle {
+    let inverse = GGXDistribution::frame(n, t).transpose();
+    let (wi, wo) = (&inverse * wi, &inverse * wo);
+    pdf::SolidAngle(self.distribution(uv).pdf_reflection(wi, wo))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use texture::Constant;
+
+  #[test]
+  fn reflectance_test() {
+    // BRDF * cos / pdf = F * G1(wo) <= 1 で、金は青より赤を、銀は金より青をよく反射する
+    let (n, t) = (Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+    let wi = Vector3::new(0.5, 0.0, 0.87).normalize();
+    let mean = |name: &str| {
+      let m = RoughConductor {
+        ior: ComplexIor::preset(name).unwrap(),
+        roughness: Box::new(Constant::new(0.3)),
+        anisotropy: 0.0,
+      };
+      let count = 50000;
+      let mut sum = Vector3::zero();
+      for _ in 0..count {
+        let s = m.sample(wi, n, t, (0.0, 0.0), false);
+        if s.pdf.0 > 0.0 {
+          let w = m.brdf(wi, s.value, n, t, (0.0, 0.0), false) * s.value.z / s.pdf.0;
+          assert!(w.max() <= 1.0 + 1e-3, "{}", w);
+          sum += w;
+        }
+      }
+      sum / count as f32
+    };
+    let gold = mean("Au");
+    let silver = mean("Ag");
+    assert!(gold.x > gold.z, "{}", gold);
+    assert!(silver.z > gold.z, "{} {}", silver, gold);
+  }
+}
@@ -0,0 +1,262 @@
+use super::physics::*;
+use super::GGXDistribution;
+use super::Material;
+use math::*;
+use sample::*;
+use sampler::Roulette;
+use texture::Texture;
+use util::Finite;
+
+/**
+ * GGXのマイクロファセットによる粗い誘電体 (Walter et al. 2007)
+ *
+ * 入射方向から見えるマイクロファセット法線をサンプリングし、
+ * フレネル反射率でロシアンルーレットして反射か屈折を選ぶ
+ */
+pub struct RoughRefraction {
+  // 反射率
+  pub reflectance: Box<dyn Texture<Vector3> + Send + Sync>,
+  // ラフネス
+  pub roughness: Box<dyn Texture<f32> + Send + Sync>,
+  // 屈折率
+  pub ior: f32,
+}
+
+impl RoughRefraction {
+  fn ior(&self, in_to_out: bool) -> (f32, f32) {
+    // (入射媒質屈折率, 出射媒質屈折率)
+    if in_to_out {
+      (self.ior, 1.0)
+    } else {
+      (1.0, self.ior)
+    }
+  }
+
+  fn distribution(&self, uv: (f32, f32)) -> GGXDistribution {
+    GGXDistribution::new(self.roughness.value(uv), 0.0)
+  }
+
+  /**
+   * 接空間の入射ベクトル, 出射ベクトル -> マイクロファセット法線 (法線の側を向く)
+   *
+   * 反射ではハーフベクトル、屈折では -(ni wi + no wo) の向き
+   */
+  fn half_vector(wi: Vector3, wo: Vector3, ni: f32, no: f32, reflect: bool) -> Vector3 {
+    let wh = if reflect {
+      (wi + wo).normalize()
+    } else {
+      -(wi * ni + wo * no).normalize()
+    };
+    if wh.z < 0.0 {
+      -wh
+    } else {
+      wh
+    }
+  }
+
+  /**
+   * 反射 (reflect) または屈折のマイクロファセット法線を選んで接空間の方向woが生成される確率密度
+   */
+  fn pdf_lobe(
+    distribution: &GGXDistribution,
+    wi: Vector3,
+    wo: Vector3,
+    ni: f32,
+    no: f32,
+    reflect: bool,
+  ) -> f32 {
+    let wh = Self::half_vector(wi, wo, ni, no, reflect);
+    if wi.dot(wh) <= 0.0 || !wh.is_finite() {
+      return 0.0;
+    }
+    let f = Self::fresnel(wi, wh, ni, no);
+    // 見えるマイクロファセット法線の確率密度
+    let pdf_h = distribution.pdf_visible(wi, wh);
+    if reflect {
+      // dωh / dωo = 1 / (4 |wo・wh|)
+      if wo.dot(wh) <= 0.0 {
+        return 0.0;
+      }
+      f * pdf_h / (4.0 * wo.dot(wh))
+    } else {
+      // dωh / dωo = no^2 |wo・wh| / (ni wi・wh + no wo・wh)^2
+      let denom = ni * wi.dot(wh) + no * wo.dot(wh);
+      if wo.dot(wh) >= 0.0 || denom == 0.0 {
+        return 0.0;
+      }
+      (1.0 - f) * pdf_h * no * no * -wo.dot(wh) / (denom * denom)
+    }
+  }
+
+  /**
+   * 接空間の入射ベクトル, 出射ベクトル -> BSDF (反射と屈折)
+   */
+  pub fn bsdf_local(
+    distribution: &GGXDistribution,
+    wi: Vector3,
+    wo: Vector3,
+    ni: f32,
+    no: f32,
+  ) -> f32 {
+    let wh = Self::half_vector(wi, wo, ni, no, wo.z > 0.0);
+    if wi.z <= 0.0 || wo.z == 0.0 || wi.dot(wh) <= 0.0 {
+      return 0.0;
+    }
+    let f = Self::fresnel(wi, wh, ni, no);
+    let g = distribution.g(wi, wo, wh);
+    let d = distribution.d(wh);
+    if wo.z > 0.0 {
+      // 反射
+      f * g * d / (4.0 * wi.z * wo.z)
+    } else {
+      // 屈折 (カメラ側の屈折率の2乗で放射輝度が変わる)
+      let denom = ni * wi.dot(wh) + no * wo.dot(wh);
+      if wo.dot(wh) >= 0.0 || denom == 0.0 {
+        return 0.0;
+      }
+      (1.0 - f) * g * d * ni * ni * wi.dot(wh) * -wo.dot(wh) / (wi.z * -wo.z * denom * denom)
+    }
+  }
+
+  /**
+   * 接空間の入射ベクトル -> 出射ベクトル
+   *
+   * wiから見えるマイクロファセット法線をサンプリングし、フレネル反射率で反射か屈折を選ぶ
+   */
+  pub fn sample_local(distribution: &GGXDistribution, wi: Vector3, ni: f32, no: f32) -> Vector3 {
+    let wh = distribution.sample_visible(wi);
+    let f = Self::fresnel(wi, wh, ni, no);
+    // ロシアンルーレットで反射か屈折に分岐
+    if Roulette::within(f) {
+      wi.reflect(wh)
+    } else {
+      wi.refract(wh, ni / no).unwrap_or(wi.reflect(wh))
+    }
+  }
+
+  /**
+   * sample_local で接空間の出射ベクトルwoが生成される確率密度
+   */
+  pub fn pdf_local(
+    distribution: &GGXDistribution,
+    wi: Vector3,
+    wo: Vector3,
+    ni: f32,
+    no: f32,
+  ) -> f32 {
+    // ラフネスが大きいと反射で面の裏側に、屈折で表側に出る方向も生成されるので両方の確率密度を足す
+    Self::pdf_lobe(distribution, wi, wo, ni, no, true)
+      + Self::pdf_lobe(distribution, wi, wo, ni, no, false)
+  }
+
+  /**
+   * マイクロファセット法線mに対するフレネル反射率 (全反射では1)
+   */
+  fn fresnel(wi: Vector3, m: Vector3, ni: f32, no: f32) -> f32 {
+    wi.refract(m, ni / no)
+      .map(|wt| Fresnel::ior(wi, wt, m, ni, no))
+      .unwrap_or(1.0)
+  }
+}
+
+impl Material for RoughRefraction {
+  fn brdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Vector3 {
+    let (ni, no) = self.ior(in_to_out);
+    // 接空間で評価する
+    let inverse = GGXDistribution::frame(n, t).transpose();
+    let (wi, wo) = (&inverse * wi, &inverse * wo);
+    let coef = Self::bsdf_local(&self.distribution(uv), wi, wo, ni, no);
+    self.reflectance.value(uv) * coef
+  }
+
+  fn sample(
+    &self,
+    wi: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> Sample<Vector3, pdf::SolidAngle> {
+    let (ni, no) = self.ior(in_to_out);
+    let frame = GGXDistribution::frame(n, t);
+    let wo = &frame * Self::sample_local(&self.distribution(uv), &frame.transpose() * wi, ni, no);
+    // 確率密度関数
+    let pdf = self.pdf(wi, wo, n, t, uv, in_to_out);
+    Sample {
+      value: wo,
+      pdf: pdf,
+    }
+  }
+
+  fn pdf(
+    &self,
+    wi: Vector3,
+    wo: Vector3,
+    n: Vector3,
+    t: Vector3,
+    uv: (f32, f32),
+    in_to_out: bool,
+  ) -> pdf::SolidAngle {
+    let (ni, no) = self.ior(in_to_out);
+    let inverse = GGXDistribution::frame(n, t).transpose();
+    let (wi, wo) = (&inverse * wi, &inverse * wo);
+    pdf::SolidAngle(Self::pdf_local(&self.distribution(uv), wi, wo, ni, no))
+  }
+
+  fn is_transmissive(&self) -> bool {
+    true
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use texture::Constant;
+
+  fn material(roughness: f32) -> RoughRefraction {
+    RoughRefraction {
+      reflectance: Box::new(Constant::new(Vector3::fill(1.0))),
+      roughness: Box::new(Constant::new(roughness)),
+      ior: 1.5,
+    }
+  }
+
+  #[test]
+  fn energy_conservation_test() {
+    // 吸収がない場合、BSDF * cos / pdf の期待値 (白色炉試験) はほぼ1になる
+    // (屈折では放射輝度が屈折率の2乗で変わるので、出射側の屈折率との比を戻す)
+    let (n, t) = (Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0));
+    let wi = Vector3::new(0.6, 0.0, 0.8);
+    for &(roughness, in_to_out) in &[(0.2, false), (0.5, false), (0.2, true)] {
+      let m = material(roughness);
+      let (ni, no) = m.ior(in_to_out);
+      let count = 200000;
+      let mut sum = 0.0;
+      for _ in 0..count {
+        let s = m.sample(wi, n, t, (0.0, 0.0), in_to_out);
+        if s.pdf.0 <= 0.0 {
+          continue;
+        }
+        let cos = s.value.dot(n);
+        let scale = if cos > 0.0 { 1.0 } else { (no / ni).powi(2) };
+        sum += m.brdf(wi, s.value, n, t, (0.0, 0.0), in_to_out).x * cos.abs() / s.pdf.0 * scale;
+      }
+      let mean = sum / count as f32;
+      assert!(
+        mean > 0.9 && mean <= 1.02,
+        "{} {}: {}",
+        roughness,
+        in_to_out,
+        mean
+      );
+    }
+  }
+}
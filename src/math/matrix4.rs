@@ -5,7 +5,7 @@ use super::vector4::Vector4;
 use std::fmt;
 use std::ops::{Add, Mul, Neg, Sub};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Matrix4 {
   v: [f32; 4 * 4],
 }
@@ -85,6 +85,128 @@ impl Matrix4 {
     out.into()
   }
 
+  // 上2行と下2行の2x2小行列式
+  fn minors(&self) -> ([f32; 6], [f32; 6]) {
+    let m = &self.v;
+    let s = [
+      m[0] * m[5] - m[4] * m[1],
+      m[0] * m[6] - m[4] * m[2],
+      m[0] * m[7] - m[4] * m[3],
+      m[1] * m[6] - m[5] * m[2],
+      m[1] * m[7] - m[5] * m[3],
+      m[2] * m[7] - m[6] * m[3],
+    ];
+    let c = [
+      m[8] * m[13] - m[12] * m[9],
+      m[8] * m[14] - m[12] * m[10],
+      m[8] * m[15] - m[12] * m[11],
+      m[9] * m[14] - m[13] * m[10],
+      m[9] * m[15] - m[13] * m[11],
+      m[10] * m[15] - m[14] * m[11],
+    ];
+    (s, c)
+  }
+
+  pub fn determinant(&self) -> f32 {
+    let (s, c) = self.minors();
+    s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+  }
+
+  /**
+   * 逆行列 (ラプラス展開)
+   *
+   * 正則でない場合は成分が無限大またはNaNになる
+   */
+  pub fn inverse(&self) -> Matrix4 {
+    let m = &self.v;
+    let (s, c) = self.minors();
+    let (s0, s1, s2, s3, s4, s5) = (s[0], s[1], s[2], s[3], s[4], s[5]);
+    let (c0, c1, c2, c3, c4, c5) = (c[0], c[1], c[2], c[3], c[4], c[5]);
+    let inv_det = 1.0 / self.determinant();
+    Matrix4::new([
+      (m[5] * c5 - m[6] * c4 + m[7] * c3) * inv_det,
+      (-m[1] * c5 + m[2] * c4 - m[3] * c3) * inv_det,
+      (m[13] * s5 - m[14] * s4 + m[15] * s3) * inv_det,
+      (-m[9] * s5 + m[10] * s4 - m[11] * s3) * inv_det,
+      (-m[4] * c5 + m[6] * c2 - m[7] * c1) * inv_det,
+      (m[0] * c5 - m[2] * c2 + m[3] * c1) * inv_det,
+      (-m[12] * s5 + m[14] * s2 - m[15] * s1) * inv_det,
+      (m[8] * s5 - m[10] * s2 + m[11] * s1) * inv_det,
+      (m[4] * c4 - m[5] * c2 + m[7] * c0) * inv_det,
+      (-m[0] * c4 + m[1] * c2 - m[3] * c0) * inv_det,
+      (m[12] * s4 - m[13] * s2 + m[15] * s0) * inv_det,
+      (-m[8] * s4 + m[9] * s2 - m[11] * s0) * inv_det,
+      (-m[4] * c3 + m[5] * c1 - m[6] * c0) * inv_det,
+      (m[0] * c3 - m[1] * c1 + m[2] * c0) * inv_det,
+      (-m[12] * s3 + m[13] * s1 - m[14] * s0) * inv_det,
+      (m[8] * s3 - m[9] * s1 + m[10] * s0) * inv_det,
+    ])
+  }
+
+  /**
+   * 逆行列 (正則でない場合はNone)
+   */
+  pub fn try_inverse(&self) -> Option<Matrix4> {
+    let det = self.determinant();
+    if det == 0.0 || !det.is_finite() {
+      return None;
+    }
+    let inverse = self.inverse();
+    if inverse.v.iter().all(|v| v.is_finite()) {
+      Some(inverse)
+    } else {
+      None
+    }
+  }
+
+  /**
+   * 法線ベクトルの変換行列 (逆行列の転置)
+   *
+   * 非一様なスケールでも接平面との直交性が保たれる
+   */
+  pub fn normal_matrix(&self) -> Matrix4 {
+    self.inverse().transpose()
+  }
+
+  /**
+   * 平行移動, 回転, 拡大縮小への分解 (M = T R S)
+   *
+   * せん断と射影を含まないアフィン変換を前提とする
+   * 鏡映を含む場合はx軸の拡大率を負にして回転行列の行列式を1に保つ
+   */
+  pub fn decompose(&self) -> (Vector3, Matrix4, Vector3) {
+    let m = &self.v;
+    let translation = Vector3::new(m[3], m[7], m[11]);
+    let x = Vector3::new(m[0], m[4], m[8]);
+    let y = Vector3::new(m[1], m[5], m[9]);
+    let z = Vector3::new(m[2], m[6], m[10]);
+    let mut scale = Vector3::new(x.norm(), y.norm(), z.norm());
+    if self.determinant() < 0.0 {
+      scale.x = -scale.x;
+    }
+    let rotation = [x / scale.x, y / scale.y, z / scale.z].into();
+    (translation, rotation, scale)
+  }
+
+  /**
+   * 拡大縮小, 回転, 平行移動の順に適用する変換 (decomposeの逆)
+   */
+  pub fn trs(translation: Vector3, rotation: &Matrix4, scale: Vector3) -> Matrix4 {
+    &(&Matrix4::translate(translation) * rotation) * &Matrix4::scale(scale)
+  }
+
+  /**
+   * 方向ベクトルの変換 (w = 0 として平行移動を無視する)
+   */
+  pub fn transform_direction(&self, v: Vector3) -> Vector3 {
+    let m = &self.v;
+    Vector3::new(
+      m[0] * v.x + m[1] * v.y + m[2] * v.z,
+      m[4] * v.x + m[5] * v.y + m[6] * v.z,
+      m[8] * v.x + m[9] * v.y + m[10] * v.z,
+    )
+  }
+
   pub fn transpose(&self) -> Matrix4 {
     let mut out = Matrix4::zero();
     for (i, o) in out.v.iter_mut().enumerate() {
@@ -195,3 +317,124 @@ impl fmt::Display for Matrix4 {
     )
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use math::*;
+
+  fn assert_matrix_eq(a: &Matrix4, b: &Matrix4) {
+    for (x, y) in a.v.iter().zip(b.v.iter()) {
+      assert!((x - y).abs() < 1e-4, "\n{}\n!=\n{}", a, b);
+    }
+  }
+
+  fn samples() -> Vec<Matrix4> {
+    let axis = Vector3::new(1.0, 2.0, -0.5).normalize();
+    vec![
+      Matrix4::unit(),
+      Matrix4::translate(Vector3::new(1.0, -2.0, 3.0)),
+      Matrix4::scale(Vector3::new(2.0, 0.5, 4.0)),
+      Matrix4::axis_angle(axis, 0.7),
+      Matrix4::look_at(
+        Vector3::new(278.0, 273.0, -800.0),
+        Vector3::new(278.0, 273.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+      ),
+      Matrix4::trs(
+        Vector3::new(-3.0, 0.5, 10.0),
+        &Matrix4::axis_angle(axis, -2.1),
+        Vector3::new(0.1, 3.0, 1.5),
+      ),
+    ]
+  }
+
+  #[test]
+  fn determinant_test() {
+    let axis = Vector3::new(0.0, 1.0, 1.0).normalize();
+    assert!((Matrix4::unit().determinant() - 1.0).abs() < 1e-6);
+    assert!((Matrix4::translate(Vector3::new(5.0, 6.0, 7.0)).determinant() - 1.0).abs() < 1e-6);
+    assert!((Matrix4::scale(Vector3::new(2.0, 3.0, 4.0)).determinant() - 24.0).abs() < 1e-4);
+    assert!((Matrix4::axis_angle(axis, 1.3).determinant() - 1.0).abs() < 1e-5);
+    assert!(Matrix4::zero().determinant() == 0.0);
+  }
+
+  #[test]
+  fn inverse_round_trip_test() {
+    for m in samples() {
+      let inverse = m.inverse();
+      assert_matrix_eq(&(&m * &inverse), &Matrix4::unit());
+      assert_matrix_eq(&(&inverse * &m), &Matrix4::unit());
+    }
+  }
+
+  #[test]
+  fn inverse_constructor_test() {
+    let v = Vector3::new(1.0, -2.0, 3.0);
+    assert_matrix_eq(&Matrix4::translate(v).inverse(), &Matrix4::translate(-v));
+    let s = Vector3::new(2.0, 0.5, 4.0);
+    assert_matrix_eq(&Matrix4::scale(s).inverse(), &Matrix4::scale(1.0 / s));
+    let r = Matrix4::axis_angle(Vector3::new(0.0, 0.0, 1.0), 0.3);
+    assert_matrix_eq(&r.inverse(), &r.transpose());
+    assert_matrix_eq(
+      &r.inverse(),
+      &Matrix4::axis_angle(Vector3::new(0.0, 0.0, 1.0), -0.3),
+    );
+  }
+
+  #[test]
+  fn try_inverse_test() {
+    assert!(Matrix4::scale(Vector3::new(1.0, 0.0, 1.0))
+      .try_inverse()
+      .is_none());
+    assert!(Matrix4::zero().try_inverse().is_none());
+    assert!(Matrix4::translate(Vector3::fill(1.0))
+      .try_inverse()
+      .is_some());
+  }
+
+  #[test]
+  fn transform_direction_test() {
+    let m = Matrix4::translate(Vector3::new(1.0, 2.0, 3.0));
+    let d = Vector3::new(0.0, 1.0, 0.0);
+    assert!(m.transform_direction(d).approx_eq(d));
+    assert!((&m * d).approx_eq(Vector3::new(1.0, 3.0, 3.0)));
+    let r = Matrix4::axis_angle(Vector3::new(0.0, 0.0, 1.0), PI / 2.0);
+    assert!(r
+      .transform_direction(Vector3::new(1.0, 0.0, 0.0))
+      .approx_eq(Vector3::new(0.0, 1.0, 0.0)));
+  }
+
+  #[test]
+  fn normal_matrix_test() {
+    // 法線は変換後の接ベクトルと直交したまま
+    let n = Vector3::new(1.0, 1.0, 0.0).normalize();
+    let t = Vector3::new(1.0, -1.0, 0.0).normalize();
+    for m in samples() {
+      let n2 = m.normal_matrix().transform_direction(n).normalize();
+      let t2 = m.transform_direction(t).normalize();
+      assert!(n2.dot(t2).abs() < 1e-4, "{} {}", n2, t2);
+    }
+  }
+
+  #[test]
+  fn decompose_test() {
+    let translation = Vector3::new(-3.0, 0.5, 10.0);
+    let rotation = Matrix4::axis_angle(Vector3::new(1.0, 2.0, -0.5).normalize(), -2.1);
+    let scale = Vector3::new(0.1, 3.0, 1.5);
+    let (t, r, s) = Matrix4::trs(translation, &rotation, scale).decompose();
+    assert!(t.approx_eq(translation));
+    assert_matrix_eq(&r, &rotation);
+    assert!(s.approx_eq(scale));
+  }
+
+  #[test]
+  fn decompose_round_trip_test() {
+    let mirror = Matrix4::scale(Vector3::new(1.0, -2.0, 1.0));
+    for m in samples().iter().chain(Some(&mirror)) {
+      let (t, r, s) = m.decompose();
+      assert!((r.determinant() - 1.0).abs() < 1e-4, "{}", r);
+      assert_matrix_eq(&Matrix4::trs(t, &r, s), m);
+    }
+  }
+}
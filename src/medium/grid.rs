@@ -0,0 +1,266 @@
+use super::{HenyeyGreenstein, Medium, MediumSample};
+use math::*;
+use ray::Ray;
+use sampler::Sampling;
+use std::fs;
+use std::path::Path;
+
+/**
+ * 格子状に密度を与えた不均質な媒質
+ *
+ * 軸に平行な箱 [min, max] を nx * ny * nz の格子に分け、格子点の密度を三線形補間する
+ * 消散係数は 密度 * extinction で、散乱する割合 (アルベド) albedo は一定とする
+ * 散乱する点は delta tracking で、透過率は ratio tracking でサンプリングする
+ */
+pub struct Grid {
+  nx: usize,
+  ny: usize,
+  nz: usize,
+  density: Vec<f32>,
+  max_density: f32,
+  min: Vector3,
+  max: Vector3,
+  extinction: f32,
+  albedo: Vector3,
+  phase: HenyeyGreenstein,
+}
+
+impl Grid {
+  /**
+   * densityはx, y, zの順に添字が速く変わる並び
+   */
+  pub fn new(
+    (nx, ny, nz): (usize, usize, usize),
+    density: Vec<f32>,
+    min: Vector3,
+    max: Vector3,
+    extinction: f32,
+    albedo: Vector3,
+    phase: HenyeyGreenstein,
+  ) -> Self {
+    debug_assert_eq!(density.len(), nx * ny * nz);
+    let max_density = density.iter().cloned().fold(0.0, f32::max);
+    Grid {
+      nx: nx,
+      ny: ny,
+      nz: nz,
+      density: density,
+      max_density: max_density,
+      min: min,
+      max: max,
+      extinction: extinction,
+      albedo: albedo,
+      phase: phase,
+    }
+  }
+
+  /**
+   * 空白区切りのテキストファイルから密度を読み込む
+   *
+   * 先頭の3つの整数が格子の大きさ nx ny nz で、続けて nx * ny * nz 個の密度を並べる
+   */
+  pub fn load(
+    path: &Path,
+    min: Vector3,
+    max: Vector3,
+    extinction: f32,
+    albedo: Vector3,
+    phase: HenyeyGreenstein,
+  ) -> Result<Self, String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut tokens = source.split_whitespace();
+    let mut size = || -> Result<usize, String> {
+      tokens
+        .next()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|&v| v > 0)
+        .ok_or_else(|| "expected a positive grid size".to_string())
+    };
+    let (nx, ny, nz) = (size()?, size()?, size()?);
+    let density = tokens
+      .map(|v| {
+        v.parse::<f32>()
+          .ok()
+          .filter(|v| *v >= 0.0 && v.is_finite())
+          .ok_or_else(|| format!("invalid density `{}`", v))
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    if density.len() != nx * ny * nz {
+      return Err(format!(
+        "expected {} densities but found {}",
+        nx * ny * nz,
+        density.len()
+      ));
+    }
+    Ok(Grid::new(
+      (nx, ny, nz),
+      density,
+      min,
+      max,
+      extinction,
+      albedo,
+      phase,
+    ))
+  }
+
+  fn at(&self, x: isize, y: isize, z: isize) -> f32 {
+    if x < 0
+      || y < 0
+      || z < 0
+      || x >= self.nx as isize
+      || y >= self.ny as isize
+      || z >= self.nz as isize
+    {
+      return 0.0;
+    }
+    self.density[(z as usize * self.ny + y as usize) * self.nx + x as usize]
+  }
+
+  /**
+   * 点pの密度
+   */
+  fn density(&self, p: Vector3) -> f32 {
+    // 格子点は各セルの中心にある
+    let extent = self.max - self.min;
+    let gx = (p.x - self.min.x) / extent.x * self.nx as f32 - 0.5;
+    let gy = (p.y - self.min.y) / extent.y * self.ny as f32 - 0.5;
+    let gz = (p.z - self.min.z) / extent.z * self.nz as f32 - 0.5;
+    let (x, y, z) = (gx.floor(), gy.floor(), gz.floor());
+    let (dx, dy, dz) = (gx - x, gy - y, gz - z);
+    let (x, y, z) = (x as isize, y as isize, z as isize);
+    let lerp = |t: f32, a: f32, b: f32| a * (1.0 - t) + b * t;
+    let d00 = lerp(dx, self.at(x, y, z), self.at(x + 1, y, z));
+    let d10 = lerp(dx, self.at(x, y + 1, z), self.at(x + 1, y + 1, z));
+    let d01 = lerp(dx, self.at(x, y, z + 1), self.at(x + 1, y, z + 1));
+    let d11 = lerp(dx, self.at(x, y + 1, z + 1), self.at(x + 1, y + 1, z + 1));
+    lerp(dz, lerp(dy, d00, d10), lerp(dy, d01, d11))
+  }
+
+  /**
+   * レイの区間 [0, t_max] のうち箱の中にある区間
+   */
+  fn overlap(&self, ray: &Ray, t_max: f32) -> Option<(f32, f32)> {
+    let mut t0: f32 = 0.0;
+    let mut t1 = t_max;
+    for axis in 0..3 {
+      let o = ray.origin[axis];
+      let d = ray.direction[axis];
+      let (min, max) = (self.min[axis], self.max[axis]);
+      if d == 0.0 {
+        if o < min || o > max {
+          return None;
+        }
+        continue;
+      }
+      let (near, far) = ((min - o) / d, (max - o) / d);
+      let (near, far) = if near < far { (near, far) } else { (far, near) };
+      t0 = t0.max(near);
+      t1 = t1.min(far);
+      if t0 >= t1 {
+        return None;
+      }
+    }
+    Some((t0, t1))
+  }
+
+  /**
+   * 最大の消散係数 (majorant) で一様に衝突した次の距離
+   */
+  fn step(&self, t: f32) -> f32 {
+    t - (1.0 - Sampling::get_1d()).ln() / (self.max_density * self.extinction)
+  }
+}
+
+impl Medium for Grid {
+  fn sample(&self, ray: &Ray, t_max: f32) -> MediumSample {
+    let pass = MediumSample::Pass {
+      weight: Vector3::fill(1.0),
+    };
+    if self.max_density * self.extinction <= 0.0 {
+      return pass;
+    }
+    let (mut t, t1) = match self.overlap(ray, t_max) {
+      None => return pass,
+      Some(range) => range,
+    };
+    // delta tracking: 実際の密度との比の確率で本当の衝突とみなす
+    loop {
+      t = self.step(t);
+      if t >= t1 {
+        return pass;
+      }
+      let density = self.density(ray.origin + ray.direction * t);
+      if Sampling::get_1d() < density / self.max_density {
+        return MediumSample::Scatter {
+          distance: t,
+          weight: self.albedo,
+        };
+      }
+    }
+  }
+
+  fn transmittance(&self, ray: &Ray, t_max: f32) -> Vector3 {
+    if self.max_density * self.extinction <= 0.0 {
+      return Vector3::fill(1.0);
+    }
+    let (mut t, t1) = match self.overlap(ray, t_max) {
+      None => return Vector3::fill(1.0),
+      Some(range) => range,
+    };
+    // ratio tracking: 衝突ごとに衝突しなかった割合を掛ける
+    let mut tr = 1.0;
+    loop {
+      t = self.step(t);
+      if t >= t1 {
+        break;
+      }
+      let density = self.density(ray.origin + ray.direction * t);
+      tr *= 1.0 - density / self.max_density;
+    }
+    Vector3::fill(tr)
+  }
+
+  fn phase(&self) -> HenyeyGreenstein {
+    self.phase
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn transmittance_test() {
+    // 密度が一定なら透過率の推定の平均は Beer-Lambert則 に一致する
+    let grid = Grid::new(
+      (4, 4, 4),
+      vec![1.0; 64],
+      Vector3::fill(-1.0),
+      Vector3::fill(1.0),
+      0.5,
+      Vector3::fill(1.0),
+      HenyeyGreenstein { g: 0.0 },
+    );
+    let ray = Ray {
+      from: None,
+      origin: Vector3::new(0.0, 0.0, -3.0),
+      direction: Vector3::new(0.0, 0.0, 1.0),
+    };
+    let n = 20000;
+    let ratio = (0..n)
+      .map(|_| grid.transmittance(&ray, 10.0).x)
+      .sum::<f32>()
+      / n as f32;
+    let delta = (0..n)
+      .filter(|_| match grid.sample(&ray, 10.0) {
+        MediumSample::Pass { .. } => true,
+        MediumSample::Scatter { .. } => false,
+      })
+      .count() as f32
+      / n as f32;
+    // 箱の端の半セルでは補間で密度が0.5まで下がるので、密度の積分は 1.5 + 2 * 0.25 * 0.75
+    let expected = (-0.5f32 * 1.875).exp();
+    assert!((ratio - expected).abs() < 0.02, "{} {}", ratio, expected);
+    assert!((delta - expected).abs() < 0.02, "{} {}", delta, expected);
+  }
+}
@@ -0,0 +1,88 @@
+use math::*;
+use sample::{pdf, Sample};
+use sampler::Sampling;
+
+/**
+ * Henyey-Greensteinの位相関数
+ *
+ * gは散乱の非対称性 (正で前方散乱, 負で後方散乱, 0で等方散乱)
+ * 方向wi, woはともに散乱点から外向き
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HenyeyGreenstein {
+  pub g: f32,
+}
+
+impl HenyeyGreenstein {
+  /**
+   * 方向wiから入射した光が方向woへ散乱する割合
+   */
+  pub fn p(&self, wi: Vector3, wo: Vector3) -> f32 {
+    // 光の進む向きどうしのなす角
+    let cos = -wi.dot(wo);
+    let g = self.g;
+    let denom = 1.0 + g * g - 2.0 * g * cos;
+    (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+  }
+
+  /**
+   * 位相関数に比例した方向のサンプリング
+   */
+  pub fn sample(&self, wi: Vector3) -> Sample<Vector3, pdf::SolidAngle> {
+    let (u, v) = Sampling::get_2d();
+    let g = self.g;
+    let cos = if g.abs() < 1e-3 {
+      1.0 - 2.0 * u
+    } else {
+      let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+      (1.0 + g * g - s * s) / (2.0 * g)
+    };
+    let cos = cos.clamp(-1.0, 1.0);
+    let sin = (1.0 - cos * cos).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    let wo = &(-wi).orthonormal_basis() * Vector3::new(sin * phi.cos(), sin * phi.sin(), cos);
+    Sample {
+      value: wo,
+      pdf: pdf::SolidAngle(self.p(wi, wo)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn normalization_test() {
+    // 球面全体で積分すると1になる
+    let wi = Vector3::new(0.0, 0.0, 1.0);
+    for &g in &[-0.5, 0.0, 0.3, 0.8] {
+      let phase = HenyeyGreenstein { g: g };
+      let n = 400;
+      let mut sum = 0.0;
+      for i in 0..n {
+        let cos = -1.0 + (i as f32 + 0.5) * 2.0 / n as f32;
+        let sin = (1.0 - cos * cos).sqrt();
+        sum += phase.p(wi, Vector3::new(sin, 0.0, cos)) * 2.0 * PI * 2.0 / n as f32;
+      }
+      assert!((sum - 1.0).abs() < 1e-2, "g = {}: {}", g, sum);
+    }
+  }
+
+  #[test]
+  fn sample_test() {
+    // サンプリングした方向の平均のcosはgになる
+    let wi = Vector3::new(0.0, 1.0, 0.0);
+    let phase = HenyeyGreenstein { g: 0.6 };
+    let n = 20000;
+    let mean = (0..n)
+      .map(|_| {
+        let sample = phase.sample(wi);
+        debug_assert!(sample.value.norm().approx_eq(1.0));
+        -wi.dot(sample.value)
+      })
+      .sum::<f32>()
+      / n as f32;
+    assert!((mean - 0.6).abs() < 0.02, "{}", mean);
+  }
+}
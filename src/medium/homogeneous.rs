@@ -0,0 +1,70 @@
+use super::{HenyeyGreenstein, Medium, MediumSample};
+use math::*;
+use ray::Ray;
+use sampler::Sampling;
+
+/**
+ * 一様な媒質
+ *
+ * absorptionは吸収係数, scatteringは散乱係数 (どちらも単位長さあたり)
+ */
+pub struct Homogeneous {
+  pub absorption: Vector3,
+  pub scattering: Vector3,
+  pub phase: HenyeyGreenstein,
+}
+
+impl Homogeneous {
+  fn extinction(&self) -> Vector3 {
+    self.absorption + self.scattering
+  }
+}
+
+/**
+ * 消散係数extinctionの媒質を距離tだけ進んだときの透過率 (Beer-Lambert則)
+ */
+fn beer(extinction: Vector3, t: f32) -> Vector3 {
+  // 消散係数が0の色は無限遠まで減衰しない
+  extinction.map(|e| if e > 0.0 { (-e * t).exp() } else { 1.0 })
+}
+
+impl Medium for Homogeneous {
+  fn sample(&self, _ray: &Ray, t_max: f32) -> MediumSample {
+    let extinction = self.extinction();
+    // 色ごとに減衰が異なるので、距離をサンプリングする色を一様に選ぶ
+    // (確率密度は3色の確率密度の平均になる)
+    let channel = ((Sampling::get_1d() * 3.0) as usize).min(2);
+    let distance = if extinction[channel] > 0.0 {
+      -(1.0 - Sampling::get_1d()).ln() / extinction[channel]
+    } else {
+      INF
+    };
+    if distance < t_max {
+      let tr = beer(extinction, distance);
+      let density = extinction * tr;
+      let pdf = (density.x + density.y + density.z) / 3.0;
+      MediumSample::Scatter {
+        distance: distance,
+        weight: tr * self.scattering / pdf,
+      }
+    } else {
+      let tr = beer(extinction, t_max);
+      let probability = (tr.x + tr.y + tr.z) / 3.0;
+      MediumSample::Pass {
+        weight: if probability > 0.0 {
+          tr / probability
+        } else {
+          Vector3::zero()
+        },
+      }
+    }
+  }
+
+  fn transmittance(&self, _ray: &Ray, t_max: f32) -> Vector3 {
+    beer(self.extinction(), t_max)
+  }
+
+  fn phase(&self) -> HenyeyGreenstein {
+    self.phase
+  }
+}
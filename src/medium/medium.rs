@@ -0,0 +1,46 @@
+use super::HenyeyGreenstein;
+use math::*;
+use ray::Ray;
+
+/**
+ * 媒質の中でレイを進めた結果
+ */
+pub enum MediumSample {
+  // 原点から距離distanceの点で散乱した (weightは 散乱係数 * 透過率 / 確率密度)
+  Scatter { distance: f32, weight: Vector3 },
+  // 散乱せずに区間の終わりまで進んだ (weightは 透過率 / 確率)
+  Pass { weight: Vector3 },
+}
+
+/**
+ * 光を吸収・散乱する媒質 (関与媒質)
+ *
+ * レイの方向ベクトルは正規化されているものとする
+ */
+pub trait Medium {
+  /**
+   * レイの原点から距離t_maxまでの区間で散乱する点をサンプリングする
+   */
+  fn sample(&self, ray: &Ray, t_max: f32) -> MediumSample;
+
+  /**
+   * レイの原点から距離t_maxまでの透過率 (不均質な媒質では推定値)
+   */
+  fn transmittance(&self, ray: &Ray, t_max: f32) -> Vector3;
+
+  /**
+   * 散乱の位相関数
+   */
+  fn phase(&self) -> HenyeyGreenstein;
+}
+
+/**
+ * 物体の境界の内側と外側の媒質 (Noneは真空)
+ *
+ * 境界を透過したレイは進んだ側の媒質に入る
+ */
+#[derive(Clone, Copy)]
+pub struct MediumInterface<'a> {
+  pub interior: Option<&'a (dyn Medium + Send + Sync)>,
+  pub exterior: Option<&'a (dyn Medium + Send + Sync)>,
+}
@@ -0,0 +1,9 @@
+mod grid;
+mod henyey_greenstein;
+mod homogeneous;
+mod medium;
+
+pub use self::grid::*;
+pub use self::henyey_greenstein::*;
+pub use self::homogeneous::*;
+pub use self::medium::*;
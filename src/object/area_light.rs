@@ -0,0 +1,266 @@
+use math::*;
+use sample::distribution::Distribution1D;
+use sample::{pdf, Sample};
+use sampler::Sampling;
+use texture::Texture;
+
+// 配光の表から方向をサンプリングするときの天頂角の分割数の目安
+const PROFILE_BINS: usize = 256;
+
+/**
+ * 面の法線からの角度に対する放射輝度の倍率 (配光)
+ */
+pub enum Profile {
+  // 全方向に一様 (完全拡散)
+  Diffuse,
+  // 法線から cos_total の角度までを照らし、cos_falloff の角度から縁に向けて滑らかに暗くなる
+  Spot { cos_total: f32, cos_falloff: f32 },
+  // 法線からの角度 0°..90° を等間隔に区切った点の倍率 (IESの配光のような表で、間は線形補間)
+  Table(Vec<f32>),
+}
+
+impl Profile {
+  /**
+   * 法線と放射方向のなす角のcos -> 放射輝度の倍率
+   */
+  pub fn value(&self, cos: f32) -> f32 {
+    match *self {
+      Profile::Diffuse => 1.0,
+      Profile::Spot {
+        cos_total,
+        cos_falloff,
+      } => {
+        if cos < cos_total {
+          0.0
+        } else if cos >= cos_falloff {
+          1.0
+        } else {
+          let t = (cos - cos_total) / (cos_falloff - cos_total);
+          t * t * (3.0 - 2.0 * t)
+        }
+      }
+      Profile::Table(ref table) => {
+        let theta = cos.clamp(0.0, 1.0).acos();
+        let x = theta / (PI / 2.0) * (table.len() - 1) as f32;
+        let i = (x as usize).min(table.len() - 2);
+        let t = x - i as f32;
+        table[i] * (1.0 - t) + table[i + 1] * t
+      }
+    }
+  }
+}
+
+/**
+ * 物体の表面からの放射 (面光源)
+ *
+ * 放射輝度はテクスチャで、配光に従って方向ごとに倍率が掛かる
+ * 片面の場合は幾何的な法線の表側にのみ放射する
+ */
+pub struct AreaLight {
+  radiance: Box<dyn Texture<Vector3> + Send + Sync>,
+  two_sided: bool,
+  profile: Profile,
+  // 配光が一様でない場合の天頂角の区間の分布 (倍率 * cos * sin に比例)
+  distribution: Option<Distribution1D>,
+  // 片面あたりの ∫ 倍率 * cos dω
+  integral: f32,
+}
+
+impl AreaLight {
+  pub fn new(
+    radiance: Box<dyn Texture<Vector3> + Send + Sync>,
+    two_sided: bool,
+    profile: Profile,
+  ) -> Self {
+    let (distribution, integral) = match profile {
+      Profile::Diffuse => (None, PI),
+      _ => {
+        // 表の点が区間の端に揃うように分割する
+        let bins = match profile {
+          Profile::Table(ref table) => {
+            let segments = table.len() - 1;
+            segments * PROFILE_BINS.div_ceil(segments)
+          }
+          _ => PROFILE_BINS,
+        };
+        let width = PI / 2.0 / bins as f32;
+        let mut integral = 0.0;
+        let weights = (0..bins)
+          .map(|i| {
+            let theta = (i as f32 + 0.5) * width;
+            let (sin, cos) = theta.sin_cos();
+            let f = profile.value(cos);
+            integral += f * cos * sin * width * 2.0 * PI;
+            // 区間の中で倍率が0でない方向が選ばれないと偏るので、端の値も見る
+            let f_max = f
+              .max(profile.value((i as f32 * width).cos()))
+              .max(profile.value(((i + 1) as f32 * width).cos()));
+            f_max * cos * sin
+          })
+          .collect::<Vec<_>>();
+        (Some(Distribution1D::new(&weights)), integral)
+      }
+    };
+    AreaLight {
+      radiance: radiance,
+      two_sided: two_sided,
+      profile: profile,
+      distribution: distribution,
+      integral: integral,
+    }
+  }
+
+  /**
+   * 完全拡散の片面の光源 (マテリアルの `emittance` 相当)
+   */
+  pub fn diffuse(radiance: Box<dyn Texture<Vector3> + Send + Sync>) -> Self {
+    Self::new(radiance, false, Profile::Diffuse)
+  }
+
+  /**
+   * テクスチャ座標uv, 幾何的な法線n (表側), 放射方向wo -> 放射輝度
+   */
+  pub fn radiance(&self, uv: (f32, f32), n: Vector3, wo: Vector3) -> Vector3 {
+    let cos = wo.dot(n);
+    if cos <= 0.0 && !self.two_sided {
+      return Vector3::zero();
+    }
+    let f = self.profile.value(cos.abs());
+    if f <= 0.0 {
+      return Vector3::zero();
+    }
+    self.radiance.value(uv) * f
+  }
+
+  /**
+   * 単位面積あたりの放射束の目安 (光源の選択確率に使う)
+   */
+  pub fn power(&self) -> f32 {
+    let sides = if self.two_sided { 2.0 } else { 1.0 };
+    self.radiance.mean().max() * self.integral * sides
+  }
+
+  /**
+   * 幾何的な法線nの点からの放射方向のサンプリング
+   *
+   * 配光 * cos項に (一様でない配光では区間ごとに近似的に) 比例する
+   */
+  pub fn sample_direction(&self, n: Vector3) -> Sample<Vector3, pdf::SolidAngle> {
+    let (n, side_pdf) = if self.two_sided {
+      if Sampling::get_1d() < 0.5 {
+        (n, 0.5)
+      } else {
+        (-n, 0.5)
+      }
+    } else {
+      (n, 1.0)
+    };
+    let (local, pdf) = match self.distribution {
+      None => {
+        let local = Sampling::hemisphere_cos_importance();
+        (local, pdf::SolidAngle(local.z / PI))
+      }
+      Some(ref distribution) => {
+        let (u, v) = Sampling::get_2d();
+        let i = distribution.sample(u);
+        // 区間内では角度について一様
+        let theta = (i as f32 + Sampling::get_1d()) * self.bin_width();
+        let phi = 2.0 * PI * v;
+        let (sin, cos) = theta.sin_cos();
+        let local = Vector3::new(phi.cos() * sin, phi.sin() * sin, cos);
+        (local, self.pdf_bin(distribution, i, sin))
+      }
+    };
+    Sample {
+      value: &n.orthonormal_basis() * local,
+      pdf: pdf * side_pdf,
+    }
+  }
+
+  /**
+   * 幾何的な法線nの点から方向woがサンプリングされる確率密度
+   */
+  pub fn pdf_direction(&self, n: Vector3, wo: Vector3) -> pdf::SolidAngle {
+    let cos = wo.dot(n);
+    if self.two_sided {
+      self.pdf_local(cos.abs()) * 0.5
+    } else if cos > 0.0 {
+      self.pdf_local(cos)
+    } else {
+      pdf::SolidAngle(0.0)
+    }
+  }
+
+  fn pdf_local(&self, cos: f32) -> pdf::SolidAngle {
+    match self.distribution {
+      None => pdf::SolidAngle(cos.max(0.0) / PI),
+      Some(ref distribution) => {
+        let theta = cos.clamp(0.0, 1.0).acos();
+        let i = ((theta / self.bin_width()) as usize).min(distribution.len() - 1);
+        self.pdf_bin(distribution, i, theta.sin())
+      }
+    }
+  }
+
+  /**
+   * 天頂角の区間iの中の、天頂角のsinがsinの方向の確率密度
+   */
+  fn pdf_bin(&self, distribution: &Distribution1D, i: usize, sin: f32) -> pdf::SolidAngle {
+    if sin <= 0.0 {
+      return pdf::SolidAngle(0.0);
+    }
+    let width = self.bin_width();
+    pdf::SolidAngle(distribution.probability(i) / (width * 2.0 * PI * sin))
+  }
+
+  fn bin_width(&self) -> f32 {
+    let bins = self.distribution.as_ref().map(|d| d.len()).unwrap_or(1);
+    PI / 2.0 / bins as f32
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use texture::Constant;
+
+  #[test]
+  fn profile_sampling_test() {
+    let profiles = vec![
+      (Profile::Diffuse, false),
+      (Profile::Diffuse, true),
+      (
+        Profile::Spot {
+          cos_total: (30.0f32).to_radians().cos(),
+          cos_falloff: (20.0f32).to_radians().cos(),
+        },
+        false,
+      ),
+      (Profile::Table(vec![1.0, 0.8, 0.2, 0.0]), true),
+    ];
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    for (profile, two_sided) in profiles {
+      let light = AreaLight::new(
+        Box::new(Constant::new(Vector3::fill(1.0))),
+        two_sided,
+        profile,
+      );
+      // 放射輝度 * cos / 確率密度 の平均は片面あたりの積分 * 面の数
+      let count = 200000;
+      let mut sum = 0.0;
+      for _ in 0..count {
+        let sample = light.sample_direction(n);
+        let l = light.radiance((0.0, 0.0), n, sample.value).x;
+        sum += l * sample.value.dot(n).abs() / sample.pdf.0;
+      }
+      let estimate = sum / count as f32;
+      let expected = light.power();
+      assert!(
+        (estimate - expected).abs() < 0.02 * expected,
+        "{} {}",
+        estimate,
+        expected
+      );
+    }
+  }
+}
@@ -0,0 +1,261 @@
+use super::Profile;
+use math::*;
+use ray::Ray;
+use sampler::Sampling;
+
+/**
+ * 位置か方向が1点に定まる光源 (点光源, スポットライト, 平行光源)
+ *
+ * 面積を持たないので物体のように衝突せず、光源のサンプリングと光子の放出でのみ寄与する
+ */
+pub enum DeltaLight {
+  // 全方向に放射強度 intensity で放射する点光源
+  Point {
+    position: Vector3,
+    intensity: Vector3,
+  },
+  // direction を中心に cos_total の角度までを照らし、cos_falloff の角度から縁に向けて滑らかに暗くなる点光源
+  Spot {
+    position: Vector3,
+    direction: Vector3,
+    intensity: Vector3,
+    cos_total: f32,
+    cos_falloff: f32,
+  },
+  // 無限遠から direction の向きに進む平行光 (放射照度 irradiance)
+  Directional {
+    direction: Vector3,
+    irradiance: Vector3,
+  },
+}
+
+/**
+ * 光源から点に届く光
+ */
+pub struct Incidence {
+  // 点から光源への向き
+  pub direction: Vector3,
+  // 光源までの距離 (平行光源ではNone)
+  pub distance: Option<f32>,
+  // 光の向きに垂直な面での放射照度
+  pub irradiance: Vector3,
+}
+
+impl DeltaLight {
+  /**
+   * 点xに届く光 (届かない場合はNone)
+   */
+  pub fn incidence(&self, x: Vector3) -> Option<Incidence> {
+    let (direction, distance, irradiance) = match *self {
+      DeltaLight::Point {
+        position,
+        intensity,
+      } => {
+        let path = position - x;
+        let sqr_distance = path.sqr_norm();
+        let distance = sqr_distance.sqrt();
+        (path / distance, Some(distance), intensity / sqr_distance)
+      }
+      DeltaLight::Spot {
+        position,
+        direction,
+        intensity,
+        cos_total,
+        cos_falloff,
+      } => {
+        let path = position - x;
+        let sqr_distance = path.sqr_norm();
+        let distance = sqr_distance.sqrt();
+        let wi = path / distance;
+        let profile = Profile::Spot {
+          cos_total: cos_total,
+          cos_falloff: cos_falloff,
+        };
+        let falloff = profile.value((-wi).dot(direction));
+        (wi, Some(distance), intensity * (falloff / sqr_distance))
+      }
+      DeltaLight::Directional {
+        direction,
+        irradiance,
+      } => (-direction, None, irradiance),
+    };
+    if !distance.map(|d| d > 0.0).unwrap_or(true) || irradiance.max() <= 0.0 {
+      return None;
+    }
+    Some(Incidence {
+      direction: direction,
+      distance: distance,
+      irradiance: irradiance,
+    })
+  }
+
+  /**
+   * 放射束の目安 (光源の選択確率に使う)
+   *
+   * 平行光源はシーンを囲む半径radiusの球の断面に届く分とする
+   */
+  pub fn power(&self, radius: f32) -> f32 {
+    match *self {
+      DeltaLight::Point { intensity, .. } => 4.0 * PI * intensity.max(),
+      DeltaLight::Spot {
+        intensity,
+        cos_total,
+        cos_falloff,
+        ..
+      } => 2.0 * PI * (1.0 - (cos_total + cos_falloff) / 2.0) * intensity.max(),
+      DeltaLight::Directional { irradiance, .. } => PI * radius * radius * irradiance.max(),
+    }
+  }
+
+  /**
+   * 光源から光子を放出する
+   *
+   * 光子のレイと、運ぶ放射束 (放射強度などを方向や始点の確率密度で割ったもの) を返す
+   * 平行光源では中心center, 半径radiusのシーンを囲む球の断面の円板上の点から放出する
+   */
+  pub fn emit(&self, center: Vector3, radius: f32) -> (Ray, Vector3) {
+    let (origin, direction, power) = match *self {
+      DeltaLight::Point {
+        position,
+        intensity,
+      } => (position, Sampling::sphere_uniform(), intensity * (4.0 * PI)),
+      DeltaLight::Spot {
+        position,
+        direction,
+        intensity,
+        cos_total,
+        cos_falloff,
+      } => {
+        // 円錐の中で立体角について一様にサンプリング
+        let (u, v) = Sampling::get_2d();
+        let cos = 1.0 - u * (1.0 - cos_total);
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        let local = Vector3::new(phi.cos() * sin, phi.sin() * sin, cos);
+        let profile = Profile::Spot {
+          cos_total: cos_total,
+          cos_falloff: cos_falloff,
+        };
+        let solid_angle = 2.0 * PI * (1.0 - cos_total);
+        (
+          position,
+          &direction.orthonormal_basis() * local,
+          intensity * (profile.value(cos) * solid_angle),
+        )
+      }
+      DeltaLight::Directional {
+        direction,
+        irradiance,
+      } => {
+        // 円板上で一様に始点をサンプリング
+        let (u, v) = Sampling::get_2d();
+        let r = radius * u.sqrt();
+        let theta = 2.0 * PI * v;
+        let offset =
+          &direction.orthonormal_basis() * Vector3::new(r * theta.cos(), r * theta.sin(), 0.0);
+        (
+          center - direction * radius + offset,
+          direction,
+          irradiance * (PI * radius * radius),
+        )
+      }
+    };
+    let ray = Ray {
+      from: None,
+      origin: origin,
+      direction: direction,
+    };
+    (ray, power)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use sampler::Independent;
+
+  #[test]
+  fn spot_incidence_test() {
+    let light = DeltaLight::Spot {
+      position: Vector3::new(0.0, 2.0, 0.0),
+      direction: Vector3::new(0.0, -1.0, 0.0),
+      intensity: Vector3::fill(8.0),
+      cos_total: (30.0f32).to_radians().cos(),
+      cos_falloff: (20.0f32).to_radians().cos(),
+    };
+    // 中心では逆2乗で減衰する
+    let center = light.incidence(Vector3::zero()).unwrap();
+    assert!((center.irradiance.x - 2.0).abs() < 1e-5);
+    assert_eq!(center.distance, Some(2.0));
+    assert!((center.direction - Vector3::new(0.0, 1.0, 0.0)).norm() < 1e-5);
+    // 円錐の外には届かない
+    assert!(light.incidence(Vector3::new(2.0, 0.0, 0.0)).is_none());
+    // ぼかしの範囲では中心より暗い
+    let x = 2.0 * (25.0f32).to_radians().tan();
+    let edge = light.incidence(Vector3::new(x, 0.0, 0.0)).unwrap();
+    assert!(edge.irradiance.x > 0.0 && edge.irradiance.x < center.irradiance.x);
+  }
+
+  #[test]
+  fn spot_emit_test() {
+    // 放出した光子の放射束の平均は、放射強度を円錐の中で立体角について積分したもの
+    let light = DeltaLight::Spot {
+      position: Vector3::zero(),
+      direction: Vector3::new(0.0, 0.0, 1.0),
+      intensity: Vector3::fill(1.0),
+      cos_total: (30.0f32).to_radians().cos(),
+      cos_falloff: (20.0f32).to_radians().cos(),
+    };
+    let n = 1000;
+    let expected = (0..n)
+      .map(|i| {
+        let cos = 1.0 - (i as f32 + 0.5) / n as f32;
+        light
+          .incidence(Vector3::new(0.0, (1.0 - cos * cos).sqrt(), cos))
+          .map(|v| v.irradiance.x)
+          .unwrap_or(0.0)
+      })
+      .sum::<f32>()
+      * 2.0
+      * PI
+      / n as f32;
+    let sampler = Independent::new(1);
+    let m = 100000;
+    let mut sum = 0.0;
+    for i in 0..m {
+      Sampling::start_pixel(&sampler, i);
+      let (ray, power) = light.emit(Vector3::zero(), 1.0);
+      assert_eq!(ray.origin, Vector3::zero());
+      assert!(power.x == 0.0 || ray.direction.z >= (30.0f32).to_radians().cos() - 1e-5);
+      sum += power.x;
+    }
+    let mean = sum / m as f32;
+    assert!(
+      (mean - expected).abs() < 1e-2 * expected,
+      "{} {}",
+      mean,
+      expected
+    );
+  }
+
+  #[test]
+  fn directional_emit_test() {
+    // シーンを囲む球の断面の円板から、光の向きに放出する
+    let direction = Vector3::new(1.0, -1.0, 0.0).normalize();
+    let light = DeltaLight::Directional {
+      direction: direction,
+      irradiance: Vector3::fill(2.0),
+    };
+    let center = Vector3::new(1.0, 2.0, 3.0);
+    let sampler = Independent::new(1);
+    for i in 0..100 {
+      Sampling::start_pixel(&sampler, i);
+      let (ray, power) = light.emit(center, 3.0);
+      assert_eq!(ray.direction, direction);
+      assert!((power.x - 2.0 * PI * 9.0).abs() < 1e-3);
+      let offset = ray.origin - center;
+      assert!((offset.dot(direction) + 3.0).abs() < 1e-4);
+      assert!(offset.norm() <= 3.0 * 2.0f32.sqrt() + 1e-4);
+    }
+  }
+}
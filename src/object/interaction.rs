@@ -1,24 +1,32 @@
 use super::LightSampler;
+use super::Object;
 use acceleration::Acceleration;
 use geometry::Geometry;
 use geometry::Intersection;
 use material::Material;
 use math::*;
+use medium::Medium;
 use ray::Ray;
 use sample::{pdf, Sample};
 use std::cmp::Ordering;
 use util::*;
 
 pub trait Interact {
-  fn interact<'a>(&'a self, ray: Ray) -> Option<Interaction>;
+  fn interact<'a>(&'a self, ray: Ray) -> Option<Interaction<'a>>;
 }
 
 pub struct Interaction<'a> {
+  object: &'a Object<'a>,
+  geometry: &'a (dyn Geometry + Send + Sync),
   material: &'a Box<dyn Material + Send + Sync>,
-  geometry: &'a Box<dyn Geometry + Send + Sync>,
   pub intersection: Intersection,
   ray: Ray,
+  // レイが来た側に向きを揃えた幾何的な法線
   pub orienting_normal: Vector3,
+  // 頂点法線を補間し、法線マップで揺らしたシェーディング法線 (intersection.normal と同じ側)
+  pub shading_normal: Vector3,
+  // シェーディング法線に直交する接線 (テクスチャ座標の u が増える向き)
+  pub tangent: Vector3,
   is_backface: bool,
 }
 
@@ -48,8 +56,8 @@ impl<'a> Ord for Interaction<'a> {
 impl<'a> Interaction<'a> {
   pub fn new(
     intersection: Intersection,
-    material: &'a Box<dyn Material + Send + Sync>,
-    geometry: &'a Box<dyn Geometry + Send + Sync>,
+    object: &'a Object<'a>,
+    geometry: &'a (dyn Geometry + Send + Sync),
     ray: Ray,
   ) -> Self {
     let dot_sign = intersection.normal.dot(-ray.direction).signum();
@@ -59,71 +67,383 @@ impl<'a> Interaction<'a> {
       "{}",
       intersection.distance
     );
+    let (shading_normal, tangent) = Self::shading_frame(&intersection, object);
+    // 入射方向がシェーディング法線の裏側にある場合はマテリアルをサンプリングできないので幾何法線を使う
+    let (shading_normal, tangent) = if shading_normal.dot(-ray.direction) * dot_sign > 0.0 {
+      (shading_normal, tangent)
+    } else {
+      let n = intersection.normal;
+      (n, Self::orthogonalize(n, tangent))
+    };
     Interaction {
       intersection: intersection,
-      material: material,
+      object: object,
       geometry: geometry,
+      material: object.material,
       ray: ray,
       orienting_normal: orienting_normal,
+      shading_normal: shading_normal,
+      tangent: tangent,
       is_backface: dot_sign == -1.0,
     }
   }
 
-  pub fn emittance(&self) -> Vector3 {
-    if self.is_backface {
-      Vector3::zero()
+  /**
+   * 衝突点の接空間 (シェーディング法線と接線)
+   *
+   * 頂点法線を補間した法線を基準に接線を直交化し、物体に法線マップがあれば法線を揺らす
+   */
+  fn shading_frame(intersection: &Intersection, object: &Object) -> (Vector3, Vector3) {
+    let n = intersection.shading_normal;
+    let t = Self::orthogonalize(n, intersection.tangent);
+    match object.normal_map {
+      None => (n, t),
+      Some(normal_map) => {
+        let shading_normal = normal_map.apply(intersection.uv, n, t);
+        (shading_normal, Self::orthogonalize(shading_normal, t))
+      }
+    }
+  }
+
+  /**
+   * 接線を法線に直交化する (縮退している場合は任意の接線)
+   */
+  fn orthogonalize(n: Vector3, t: Vector3) -> Vector3 {
+    let t = t - n * n.dot(t);
+    if t.sqr_norm() > 0.0 && t.is_finite() {
+      t.normalize()
+    } else {
+      let basis = n.orthonormal_basis();
+      &basis * Vector3::new(1.0, 0.0, 0.0)
+    }
+  }
+
+  /**
+   * 裏面側かどうかに合わせて向きを揃えたシェーディング法線
+   *
+   * マテリアルの評価とサンプリングはこの法線で行う
+   */
+  fn shading(&self, is_backface: bool) -> Vector3 {
+    if is_backface {
+      -self.shading_normal
     } else {
-      self.material.emittance()
+      self.shading_normal
+    }
+  }
+
+  /**
+   * 衝突点からレイの来た方向への放射輝度 (光源でなければ0)
+   */
+  pub fn emittance(&self) -> Vector3 {
+    match self.object.area_light {
+      None => Vector3::zero(),
+      Some(light) => light.radiance(
+        self.intersection.uv,
+        self.intersection.normal,
+        -self.ray.direction,
+      ),
+    }
+  }
+
+  /**
+   * 衝突点が光源として方向woに放射する向きがサンプリングされる確率密度
+   */
+  pub fn emission_pdf(&self, wo: Vector3) -> pdf::SolidAngle {
+    match self.object.area_light {
+      None => pdf::SolidAngle(0.0),
+      Some(light) => light.pdf_direction(self.intersection.normal, wo),
     }
   }
 
   pub fn geometry_id(&self) -> usize {
-    self.geometry.id()
+    self.object.geometry_id(self.geometry)
   }
 
   pub fn sample_material(&self) -> Sample<Vector3, pdf::SolidAngle> {
-    let n = self.orienting_normal;
+    let n = self.shading(self.is_backface);
     let wi = -self.ray.direction;
     // BRDFに応じたサンプリング
     // NOTE: 方向ベクトルがサンプリングされる
-    self.material.sample(wi, n, self.is_backface)
+    self
+      .material
+      .sample(wi, n, self.tangent, self.intersection.uv, self.is_backface)
   }
 
   pub fn is_delta(&self) -> bool {
     self.material.is_delta()
   }
 
-  pub fn connect_direction<S>(&self, structure: &'a S, wo: Vector3) -> Option<Geom>
+  pub fn is_transmissive(&self) -> bool {
+    self.material.is_transmissive()
+  }
+
+  /**
+   * 衝突点での方向woへのBSDF
+   */
+  pub fn bsdf(&self, wo: Vector3) -> Vector3 {
+    self.material.brdf(
+      -self.ray.direction,
+      wo,
+      self.shading(self.is_backface),
+      self.tangent,
+      self.intersection.uv,
+      self.is_backface,
+    )
+  }
+
+  /**
+   * 衝突点から方向woがサンプリングされる確率密度
+   */
+  pub fn bsdf_pdf(&self, wo: Vector3) -> pdf::SolidAngle {
+    self.material.pdf(
+      -self.ray.direction,
+      wo,
+      self.shading(self.is_backface),
+      self.tangent,
+      self.intersection.uv,
+      self.is_backface,
+    )
+  }
+
+  /**
+   * 衝突点での方向wiから見た方向woへのBSDF
+   *
+   * wiはレイの入射方向とは限らない (光源サブパスなど)
+   */
+  pub fn bsdf_between(&self, wi: Vector3, wo: Vector3) -> Vector3 {
+    let (n, is_backface) = self.orient(wi);
+    self
+      .material
+      .brdf(wi, wo, n, self.tangent, self.intersection.uv, is_backface)
+  }
+
+  /**
+   * 方向wiから見て方向woがサンプリングされる確率密度
+   */
+  pub fn bsdf_pdf_between(&self, wi: Vector3, wo: Vector3) -> pdf::SolidAngle {
+    let (n, is_backface) = self.orient(wi);
+    self
+      .material
+      .pdf(wi, wo, n, self.tangent, self.intersection.uv, is_backface)
+  }
+
+  /**
+   * 方向wiの側を向いたシェーディング法線と、wiが裏面側かどうか
+   *
+   * 表裏は幾何的な法線で判定する
+   */
+  fn orient(&self, wi: Vector3) -> (Vector3, bool) {
+    let is_backface = wi.dot(self.intersection.normal) < 0.0;
+    (self.shading(is_backface), is_backface)
+  }
+
+  /**
+   * 衝突点が光源として sample_emitter でサンプリングされる確率密度
+   */
+  pub fn emitter_pdf(&self, light_sampler: &LightSampler) -> Option<pdf::Area> {
+    if self.object.area_light.is_some() {
+      light_sampler.emitter_pdf(self.object, self.geometry, self.intersection.position)
+    } else {
+      None
+    }
+  }
+
+  /**
+   * 衝突点が光源として LightSampler::sample でサンプリングされる確率密度
+   */
+  pub fn light_pdf(&self, light_sampler: &LightSampler) -> Option<pdf::Area> {
+    if self.object.area_light.is_some() {
+      light_sampler.pdf(self.object, self.geometry, self.intersection.position)
+    } else {
+      None
+    }
+  }
+
+  /**
+   * light_pdf をレイの原点から見た立体角測度に変換したもの
+   */
+  pub fn light_pdf_solid_angle(&self, light_sampler: &LightSampler) -> Option<pdf::SolidAngle> {
+    self.light_pdf(light_sampler).map(|pdf| {
+      pdf.solid_angle_measure(
+        self.ray.origin,
+        self.intersection.position,
+        self.orienting_normal,
+      )
+    })
+  }
+
+  /**
+   * 方向woに進むレイが入る媒質
+   *
+   * 媒質の境界でなければ、レイがいた媒質currentのまま
+   */
+  pub fn medium_towards(
+    &self,
+    wo: Vector3,
+    current: Option<&'a (dyn Medium + Send + Sync)>,
+  ) -> Option<&'a (dyn Medium + Send + Sync)> {
+    match self.object.medium {
+      None => current,
+      Some(ref interface) => {
+        if wo.dot(self.intersection.normal) < 0.0 {
+          interface.interior
+        } else {
+          interface.exterior
+        }
+      }
+    }
+  }
+
+  /**
+   * 衝突点から点x2までの間が遮られていないかどうか
+   */
+  pub fn unoccluded<S>(&self, structure: &'a S, x2: Vector3) -> bool
   where
     S: Acceleration,
   {
-    let x = self.intersection.position;
-    // 新しいレイ
-    let ray = Ray {
-      from: Some(self.geometry.id()),
-      origin: x + wo * EPS,
-      direction: wo,
-    };
+    let path = x2 - self.intersection.position;
+    let distance = path.norm();
+    let wo = path / distance;
+    match structure.interact(self.ray(wo)) {
+      None => true,
+      Some(interaction) => interaction.intersection.distance >= distance - EPS,
+    }
+  }
+
+  /**
+   * 方向woのcos項 (レイが来た側を正とする)
+   *
+   * シェーディング法線で測り、幾何的な法線と表裏が食い違う方向は0にする
+   * (揺らした法線では表でも実際には面の裏側に抜ける方向など)
+   */
+  pub fn cos(&self, wo: Vector3) -> f32 {
+    let cos = wo.dot(self.shading(self.is_backface));
+    if cos * wo.dot(self.orienting_normal) <= 0.0 {
+      0.0
+    } else {
+      cos
+    }
+  }
+
+  /**
+   * 光源側から方向wiで到達した光子が方向woに散乱するときのcos項 (光源サブパスで使う)
+   *
+   * シェーディング法線ではBSDFが対称でなくなるので、
+   * 随伴BSDFの補正 |wi・ns| |wo・ng| / (|wi・ng| |wo・ns|) をcos項 |wo・ns| に掛けておく (Veach 1997, 5.3)
+   */
+  pub fn adjoint_cos(&self, wi: Vector3, wo: Vector3) -> f32 {
+    let cos_i = self.cos(wi).abs();
+    let cos_o = self.cos(wo).abs();
+    if cos_i == 0.0 || cos_o == 0.0 {
+      return 0.0;
+    }
+    cos_i * wo.dot(self.orienting_normal).abs() / wi.dot(self.orienting_normal).abs()
+  }
+
+  /**
+   * 方向woの幾何項 (cos項 / 確率密度)
+   */
+  pub fn weight(&self, wo: Vector3, pdf: pdf::SolidAngle) -> f32 {
+    let pdf::SolidAngle(p) = pdf;
+    self.leak_guarded_cos(-self.ray.direction, wo) / p
+  }
+
+  /**
+   * 方向wiから来たレイが方向woに出るときのcos項 (透過する方向でも正の値)
+   *
+   * wiの側でシェーディング法線と幾何的な法線の表裏が食い違う場合も、
+   * 面の裏側に光が漏れる原因になるので0にする
+   */
+  fn leak_guarded_cos(&self, wi: Vector3, wo: Vector3) -> f32 {
+    if self.cos(wi) == 0.0 {
+      0.0
+    } else {
+      self.cos(wo).abs()
+    }
+  }
+
+  /**
+   * 衝突点から方向woに出るレイ
+   */
+  pub fn ray(&self, wo: Vector3) -> Ray {
     debug_assert!(wo.is_finite(), "{}", wo);
-    structure.interact(ray).and_then(|interaction| {
+    Ray {
+      from: Some(self.geometry_id()),
+      origin: self.intersection.position + wo * EPS,
+      direction: wo,
+    }
+  }
+
+  /**
+   * 方向woにパスを接続する
+   *
+   * 何にも衝突しなければ環境光に接続されたものとする
+   */
+  pub fn trace<'b, S>(&'b self, structure: &'a S, wo: Vector3) -> Connection<'a, 'b>
+  where
+    S: Acceleration,
+  {
+    match structure.interact(self.ray(wo)) {
       // 可視チェック(1)
-      Geom::new(self, interaction)
-    })
+      Some(interaction) => match Geom::new(self, interaction) {
+        Some(geom) => Connection::Hit(Box::new(geom)),
+        None => Connection::Blocked,
+      },
+      None => Connection::Escape,
+    }
+  }
+
+  pub fn connect_direction<'b, S>(&'b self, structure: &'a S, wo: Vector3) -> Option<Geom<'a, 'b>>
+  where
+    S: Acceleration,
+  {
+    structure
+      .interact(self.ray(wo))
+      .and_then(|interaction| Geom::new(self, interaction))
+  }
+
+  /**
+   * 方向woの環境光が遮られずに届くかどうか
+   */
+  pub fn connect_environment<S>(&self, structure: &'a S, wo: Vector3) -> bool
+  where
+    S: Acceleration,
+  {
+    self.connect_light(structure, wo, None)
+  }
+
+  /**
+   * 方向woの距離distance (Noneの場合は無限遠) にある光源が遮られずに見えるかどうか
+   */
+  pub fn connect_light<S>(&self, structure: &'a S, wo: Vector3, distance: Option<f32>) -> bool
+  where
+    S: Acceleration,
+  {
+    // 可視チェック(1)
+    if wo.dot(self.orienting_normal) <= 0.0 && !self.is_transmissive() {
+      return false;
+    }
+    // 可視チェック(2)
+    match (structure.interact(self.ray(wo)), distance) {
+      (None, _) => true,
+      (Some(_), None) => false,
+      (Some(interaction), Some(distance)) => interaction.intersection.distance >= distance - EPS,
+    }
   }
 
-  pub fn connect_point<S>(&self, structure: &'a S, x2: Vector3) -> Option<Geom>
+  pub fn connect_point<'b, S>(&'b self, structure: &'a S, x2: Vector3) -> Option<Geom<'a, 'b>>
   where
     S: Acceleration,
   {
     let x = self.intersection.position;
     let path = x2 - x;
     // 可視チェック(1)
-    if path.dot(self.orienting_normal) < 0.0 {
+    if path.dot(self.orienting_normal) < 0.0 && !self.is_transmissive() {
       return None;
     }
     let wo = path.normalize();
     let ray = Ray {
-      from: Some(self.geometry.id()),
+      from: Some(self.geometry_id()),
       origin: x + wo * EPS,
       direction: wo,
     };
@@ -139,7 +459,24 @@ impl<'a> Interaction<'a> {
   }
 }
 
-pub struct Geom<'a> {
+/**
+ * 方向を指定したパスの接続結果
+ */
+pub enum Connection<'a, 'b> {
+  // 物体に接続された
+  Hit(Box<Geom<'a, 'b>>),
+  // 何にも衝突せずにシーンの外に出た
+  Escape,
+  // 物体に衝突したが接続できなかった
+  Blocked,
+}
+
+/**
+ * 2つの衝突点の間の幾何的な関係
+ *
+ * nextは接続元 (current) の借用とは独立して取り出せる
+ */
+pub struct Geom<'a, 'b> {
   pub x: Vector3,
   pub x_offset: Vector3,
   pub x1: Vector3,
@@ -150,12 +487,12 @@ pub struct Geom<'a> {
   pub wo: Vector3,
   pub path_o: Vector3,
   pub path_i: Vector3,
-  pub current: &'a Interaction<'a>,
+  pub current: &'b Interaction<'a>,
   pub next: Interaction<'a>,
 }
 
-impl<'a> Geom<'a> {
-  fn new(current: &'a Interaction, next: Interaction<'a>) -> Option<Self> {
+impl<'a, 'b> Geom<'a, 'b> {
+  fn new(current: &'b Interaction<'a>, next: Interaction<'a>) -> Option<Self> {
     debug_assert!(current.intersection.position.approx_eq(next.ray.origin));
     let x = current.intersection.position;
     let x_offset = next.ray.origin;
@@ -188,26 +525,37 @@ impl<'a> Geom<'a> {
   }
 
   pub fn bsdf(&self) -> Vector3 {
-    self
-      .current
-      .material
-      .brdf(self.wi, self.wo, self.n, self.x, self.current.is_backface)
+    self.current.material.brdf(
+      self.wi,
+      self.wo,
+      self.current.shading(self.current.is_backface),
+      self.current.tangent,
+      self.current.intersection.uv,
+      self.current.is_backface,
+    )
   }
 
   pub fn bsdf_pdf(&self) -> pdf::SolidAngle {
-    self
-      .current
-      .material
-      .pdf(self.wi, self.wo, self.n, self.current.is_backface)
+    self.current.material.pdf(
+      self.wi,
+      self.wo,
+      self.current.shading(self.current.is_backface),
+      self.current.tangent,
+      self.current.intersection.uv,
+      self.current.is_backface,
+    )
   }
 
-  pub fn g(&self) -> f32 {
-    self.wo.dot(self.n) * (-self.wo).dot(self.n2) / (self.x2 - self.x_offset).sqr_norm()
+  /**
+   * 接続元での方向woのcos項 (シェーディング法線で測り、光漏れする場合は0)
+   */
+  pub fn cos(&self) -> f32 {
+    self.current.leak_guarded_cos(self.wi, self.wo)
   }
 
   pub fn light_pdf(&self, light_sampler: &LightSampler) -> Option<pdf::Area> {
-    if self.next.material.emittance().sqr_norm() > 0.0 {
-      light_sampler.pdf(self.next.geometry)
+    if self.next.object.area_light.is_some() {
+      light_sampler.pdf(self.next.object, self.next.geometry, self.x2)
     } else {
       None
     }
@@ -221,31 +569,29 @@ where
   fn weight(&self, pdf: PDF) -> f32;
 }
 
-impl<'a> GeomWeight<pdf::SolidAngle> for Geom<'a> {
+impl<'a, 'b> GeomWeight<pdf::SolidAngle> for Geom<'a, 'b> {
   fn weight(&self, pdf: pdf::SolidAngle) -> f32 {
     let pdf::SolidAngle(p) = pdf;
-    debug_assert!(
-      (self.wo.dot(self.n) / p).is_finite(),
-      "{}",
-      self.wo.dot(self.n) / p
-    );
-    self.wo.dot(self.n) / p
+    let cos = self.cos();
+    debug_assert!((cos / p).is_finite(), "{}", cos / p);
+    cos / p
   }
 }
 
-impl<'a> GeomWeight<pdf::Area> for Geom<'a> {
+impl<'a, 'b> GeomWeight<pdf::Area> for Geom<'a, 'b> {
   fn weight(&self, pdf: pdf::Area) -> f32 {
     let pdf::Area(p) = pdf;
+    // 接続元のcos項はシェーディング法線、面積測度からの変換は接続先の幾何的な法線で測る
+    let cos = self.cos();
     debug_assert!(
-      (self.wo.dot(self.n) * (-self.wo).dot(self.n2) / (self.x2 - self.x_offset).sqr_norm() / p)
-        .is_finite(),
+      (cos * (-self.wo).dot(self.n2) / (self.x2 - self.x_offset).sqr_norm() / p).is_finite(),
       "\nwo . n = {}\n-wo . n2 = {}\n|x2 - x| = {}\np = {}\n",
-      self.wo.dot(self.n),
+      cos,
       (-self.wo).dot(self.n2),
       (self.x2 - self.x_offset).sqr_norm(),
       p
     );
-    self.wo.dot(self.n) * (-self.wo).dot(self.n2) / (self.x2 - self.x_offset).sqr_norm() / p
+    cos * (-self.wo).dot(self.n2) / (self.x2 - self.x_offset).sqr_norm() / p
   }
 }
 
@@ -257,6 +603,8 @@ mod tests {
   use math::*;
   use object::{Interact, Interaction, Object};
   use ray::Ray;
+  use sampler::{Independent, Sampling};
+  use texture;
 
   fn setup() -> (
     Box<dyn material::Material + Send + Sync>,
@@ -285,6 +633,8 @@ mod tests {
     let (m, g) = setup();
     let a = acceleration::Linear::new(vec![Object::new(g, Matrix4::unit(), &m)]);
     let i = a.interact(ray).unwrap();
+    // フレネル反射を選ばないようにシードを固定する
+    Sampling::start_pixel(&Independent::new(1), 0);
     let s = i.sample_material();
     assert!(s.value.dot(Vector3::new(0.0, 0.0, -1.0)).approx_eq(1.0));
   }
@@ -299,6 +649,8 @@ mod tests {
     let (m, g) = setup();
     let a = acceleration::Linear::new(vec![Object::new(g, Matrix4::unit(), &m)]);
     let i = a.interact(ray).unwrap();
+    // フレネル反射を選ばないようにシードを固定する
+    Sampling::start_pixel(&Independent::new(1), 0);
     let s = i.sample_material();
     assert!(s.value.dot(Vector3::new(0.0, 0.0, 1.0)).approx_eq(1.0));
   }
@@ -313,8 +665,12 @@ mod tests {
     let (m, g) = setup();
     let a = acceleration::Linear::new(vec![Object::new(g, Matrix4::unit(), &m)]);
     let i = a.interact(ray).unwrap();
+    // フレネル反射を選ばないようにシードを固定する
+    Sampling::start_pixel(&Independent::new(1), 0);
     let s = i.sample_material();
     let geom = i.connect_direction(&a, s.value).unwrap();
+    // 透過した方向のcos項も正
+    assert!(geom.cos() > 0.0, "{}", geom.cos());
     assert!(
       (geom.x - Vector3::new(0.0, 0.0, 0.0)).norm().approx_eq(0.0),
       "{}",
@@ -328,4 +684,80 @@ mod tests {
       geom.x2
     );
   }
+
+  /**
+   * z=0 の三角形 (幾何的な法線は +z) に上から当たったレイの衝突点を調べる
+   *
+   * 頂点法線を shading_normal にして、シェーディング法線を幾何的な法線からずらす
+   */
+  fn with_shading_normal<F: Fn(&Interaction)>(shading_normal: Vector3, f: F) {
+    let mut uuid = geometry::UUID::new();
+    let m: Box<dyn material::Material + Send + Sync> = Box::new(material::Lambertian {
+      albedo: Box::new(texture::Constant::new(Vector3::fill(0.5))),
+    });
+    let n = shading_normal.normalize();
+    let g: Box<dyn geometry::Geometry + Send + Sync> = Box::new(geometry::Triangle::new(
+      Vector3::new(-1.0, -1.0, 0.0),
+      Vector3::new(1.0, -1.0, 0.0),
+      Vector3::new(1.0, 1.0, 0.0),
+      n,
+      n,
+      n,
+      &mut uuid,
+    ));
+    let a = acceleration::Linear::new(vec![Object::new(g, Matrix4::unit(), &m)]);
+    let ray = Ray {
+      origin: Vector3::new(0.5, -0.5, 1.0),
+      direction: Vector3::new(0.0, 0.0, -1.0),
+      from: None,
+    };
+    f(&a.interact(ray).unwrap());
+  }
+
+  #[test]
+  fn adjoint_cos_flat_test() {
+    // シェーディング法線が幾何的な法線と一致すれば補正は掛からない
+    with_shading_normal(Vector3::new(0.0, 0.0, 1.0), |i| {
+      let wi = Vector3::new(-0.4, 0.2, 0.9).normalize();
+      let wo = Vector3::new(0.5, 0.1, 0.8).normalize();
+      assert!(i.cos(wo).approx_eq(wo.z));
+      assert!(i.adjoint_cos(wi, wo).approx_eq(i.cos(wo)));
+      assert!(i.adjoint_cos(wo, wi).approx_eq(i.cos(wi)));
+    });
+  }
+
+  #[test]
+  fn adjoint_cos_perturbed_test() {
+    // cos項 |wo・ns| に随伴BSDFの補正 |wi・ns| |wo・ng| / (|wi・ng| |wo・ns|) が掛かる
+    let ns = Vector3::new(0.3, 0.0, 1.0).normalize();
+    let ng = Vector3::new(0.0, 0.0, 1.0);
+    with_shading_normal(ns, |i| {
+      let wi = Vector3::new(-0.4, 0.2, 0.9).normalize();
+      let wo = Vector3::new(0.5, 0.1, 0.8).normalize();
+      assert!(i.cos(wo).approx_eq(wo.dot(ns)));
+      let correction = wi.dot(ns).abs() * wo.dot(ng).abs() / (wi.dot(ng).abs() * wo.dot(ns).abs());
+      assert!(
+        i.adjoint_cos(wi, wo).approx_eq(i.cos(wo) * correction),
+        "{} {}",
+        i.adjoint_cos(wi, wo),
+        i.cos(wo) * correction
+      );
+    });
+  }
+
+  #[test]
+  fn leak_guard_test() {
+    // シェーディング法線では表だが幾何的な法線では裏側の方向
+    let ns = Vector3::new(0.3, 0.0, 1.0).normalize();
+    with_shading_normal(ns, |i| {
+      let leak = Vector3::new(1.0, 0.0, -0.1).normalize();
+      assert!(leak.dot(ns) > 0.0);
+      let wo = Vector3::new(0.5, 0.1, 0.8).normalize();
+      assert_eq!(i.cos(leak), 0.0);
+      assert_eq!(i.leak_guarded_cos(leak, wo), 0.0);
+      assert_eq!(i.leak_guarded_cos(wo, leak), 0.0);
+      assert!(i.leak_guarded_cos(wo, wo).approx_eq(i.cos(wo)));
+      assert_eq!(i.adjoint_cos(leak, wo), 0.0);
+    });
+  }
 }
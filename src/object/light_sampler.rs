@@ -1,62 +1,339 @@
-use geometry::Geometry;
+use environment::Environment;
+use geometry::{Geometry, AABB};
 use math::*;
-use object::Object;
-use rand::Rng;
+use object::{AreaLight, DeltaLight, Object};
+use ray::Ray;
 use sample::{pdf, Sample};
-use RNG;
+use sampler::Sampling;
 
 pub struct LightSampler<'a> {
   light: Vec<&'a Object<'a>>,
   pdf: Vec<f32>,
+  // 物体の光源のいずれかが選ばれる確率
+  area_pdf: f32,
+  delta_lights: &'a [DeltaLight],
+  delta_pdf: Vec<f32>,
+  environment: &'a (dyn Environment + Send + Sync),
+  // 環境光が選ばれる確率
+  environment_pdf: f32,
+  // シーンを囲む球
+  center: Vector3,
+  radius: f32,
+}
+
+/**
+ * 光源のサンプリング結果
+ */
+pub enum LightSample<'a> {
+  // 光源上の位置ベクトル
+  Area(Sample<Vector3, pdf::Area>),
+  // 点光源などの位置や方向が1点に定まる光源と、その光源が選ばれる確率
+  Delta {
+    light: &'a DeltaLight,
+    probability: f32,
+  },
+  // 環境光の入射方向
+  Environment(Sample<Vector3, pdf::SolidAngle>),
+}
+
+/**
+ * 物体の光源上でサンプリングされた点
+ */
+pub struct Emitter<'a> {
+  pub object: &'a Object<'a>,
+  pub light: &'a AreaLight,
+  pub position: Vector3,
+  pub normal: Vector3,
+  pub uv: (f32, f32),
+  pub pdf: pdf::Area,
 }
 
 impl<'a> LightSampler<'a> {
-  pub fn new(objects: &'a Vec<Object>) -> Self {
+  pub fn new(
+    objects: &'a Vec<Object>,
+    delta_lights: &'a [DeltaLight],
+    environment: &'a (dyn Environment + Send + Sync),
+  ) -> Self {
     // 光源だけ取り出す
     let light = objects
       .iter()
-      .filter(|v| v.material.emittance().sqr_norm() > 0.0)
+      .filter(|v| v.area_light.map(|l| l.power() > 0.0).unwrap_or(false))
       .collect::<Vec<_>>();
     let intensity = light
       .iter()
-      .map(|v| v.geometry.area() * v.material.emittance().max())
+      .map(|v| v.area() * v.area_light.map(|l| l.power()).unwrap_or(0.0))
       .collect::<Vec<_>>();
-    let normalize_factor: f32 = intensity.iter().sum();
+    // 環境光はシーンを囲む球の表面積を持つ光源とみなす
+    let aabb = objects
+      .iter()
+      .fold(AABB::empty(), |aabb, v| aabb.merge_with(v.aabb()));
+    let center = if objects.is_empty() {
+      Vector3::zero()
+    } else {
+      (aabb.max + aabb.min) / 2.0
+    };
+    let radius = if objects.is_empty() {
+      0.0
+    } else {
+      (aabb.max - aabb.min).norm() / 2.0
+    };
+    let environment_intensity =
+      4.0 * PI * radius * radius * environment.mean_radiance().max().max(0.0);
+    let delta_intensity = delta_lights
+      .iter()
+      .map(|v| v.power(radius).max(0.0))
+      .collect::<Vec<_>>();
+    let normalize_factor: f32 =
+      intensity.iter().sum::<f32>() + delta_intensity.iter().sum::<f32>() + environment_intensity;
     let pdf = intensity
       .iter()
       .map(|v| v / normalize_factor)
       .collect::<Vec<_>>();
-    LightSampler { light, pdf }
+    let delta_pdf = delta_intensity
+      .iter()
+      .map(|v| v / normalize_factor)
+      .collect::<Vec<_>>();
+    let environment_pdf = if environment_intensity > 0.0 {
+      environment_intensity / normalize_factor
+    } else {
+      0.0
+    };
+    LightSampler {
+      light: light,
+      area_pdf: pdf.iter().sum(),
+      pdf: pdf,
+      delta_lights: delta_lights,
+      delta_pdf: delta_pdf,
+      environment: environment,
+      environment_pdf: environment_pdf,
+      center: center,
+      radius: radius,
+    }
   }
 
   /**
    * 光源の重点的サンプリング
    *
-   * NOTE: 位置ベクトルがサンプリングされる
+   * NOTE: 物体の光源は位置ベクトル、環境光は方向ベクトルがサンプリングされ、
+   * 点光源などは光源そのものが選ばれる
    */
-  pub fn sample(&self) -> Option<Sample<Vector3, pdf::Area>> {
-    RNG.with(|rng| {
-      let roulette = rng.borrow_mut().gen::<f32>();
-      let mut accumulator = 0.0;
-      for (i, obj) in self.light.iter().enumerate() {
-        accumulator += self.pdf[i];
-        if roulette <= accumulator {
-          let sample = obj.geometry.sample();
-          return Some(Sample {
-            value: sample.value,
-            pdf: sample.pdf * self.pdf[i],
-          });
+  pub fn sample(&self) -> Option<LightSample<'a>> {
+    let roulette = Sampling::get_1d();
+    let mut accumulator = 0.0;
+    for (i, obj) in self.light.iter().enumerate() {
+      accumulator += self.pdf[i];
+      if roulette <= accumulator {
+        let sample = obj.sample();
+        return Some(LightSample::Area(Sample {
+          value: sample.value,
+          pdf: sample.pdf * self.pdf[i],
+        }));
+      }
+    }
+    for (i, light) in self.delta_lights.iter().enumerate() {
+      accumulator += self.delta_pdf[i];
+      if roulette <= accumulator {
+        return Some(LightSample::Delta {
+          light: light,
+          probability: self.delta_pdf[i],
+        });
+      }
+    }
+    if self.environment_pdf > 0.0 {
+      let sample = self.environment.sample();
+      return Some(LightSample::Environment(Sample {
+        value: sample.value,
+        pdf: sample.pdf * self.environment_pdf,
+      }));
+    }
+    None
+  }
+
+  /**
+   * 物体の光源だけから光源上の点をサンプリングする (環境光や点光源などは選ばない)
+   */
+  pub fn sample_emitter(&self) -> Option<Emitter<'a>> {
+    let area_pdf = self.area_pdf;
+    if self.light.is_empty() || area_pdf <= 0.0 {
+      return None;
+    }
+    let roulette = Sampling::get_1d() * area_pdf;
+    let mut accumulator = 0.0;
+    let i = self
+      .pdf
+      .iter()
+      .position(|&p| {
+        accumulator += p;
+        roulette < accumulator
+      })
+      .unwrap_or(self.light.len() - 1);
+    let (sample, normal, uv) = self.light[i].sample_surface();
+    Some(Emitter {
+      object: self.light[i],
+      light: self.light[i].area_light?,
+      position: sample.value,
+      normal: normal,
+      uv: uv,
+      pdf: sample.pdf * (self.pdf[i] / area_pdf),
+    })
+  }
+
+  /**
+   * 光源から光子を放出する
+   *
+   * 光子のレイと、運ぶ放射束 (放射輝度を始点と方向の確率密度で割ったもの) を返す
+   * 物体の光源では光源上の点から配光とcos項に (おおよそ) 比例した方向に、
+   * 環境光ではシーンを囲む球の外側の方向に垂直な円板上の点から内向きに放出する
+   * 点光源などからの放出は DeltaLight::emit による
+   */
+  pub fn emit(&self) -> Option<(Ray, Vector3)> {
+    let roulette = Sampling::get_1d();
+    let mut accumulator = 0.0;
+    for (i, obj) in self.light.iter().enumerate() {
+      accumulator += self.pdf[i];
+      if roulette <= accumulator {
+        let light = obj.area_light?;
+        let (sample, normal, uv) = obj.sample_surface();
+        let direction = light.sample_direction(normal);
+        if direction.pdf.0 <= 0.0 {
+          return None;
         }
+        let cos = direction.value.dot(normal).abs();
+        let power = light.radiance(uv, normal, direction.value)
+          * (cos / (direction.pdf.0 * sample.pdf.0 * self.pdf[i]));
+        let direction = direction.value;
+        let ray = Ray {
+          from: None,
+          origin: sample.value + direction * EPS,
+          direction: direction,
+        };
+        return Some((ray, power));
+      }
+    }
+    for (i, light) in self.delta_lights.iter().enumerate() {
+      accumulator += self.delta_pdf[i];
+      if roulette <= accumulator {
+        let (ray, power) = light.emit(self.center, self.radius);
+        return Some((ray, power / self.delta_pdf[i]));
       }
+    }
+    if self.environment_pdf > 0.0 {
+      let sample = self.environment.sample();
+      let direction = sample.value;
+      // 円板上で一様に始点をサンプリング
+      let (u, v) = Sampling::get_2d();
+      let r = self.radius * u.sqrt();
+      let theta = 2.0 * PI * v;
+      let offset =
+        &direction.orthonormal_basis() * Vector3::new(r * theta.cos(), r * theta.sin(), 0.0);
+      let area = PI * self.radius * self.radius;
+      let power =
+        self.environment.radiance(direction) * (area / (sample.pdf.0 * self.environment_pdf));
+      let ray = Ray {
+        from: None,
+        origin: self.center + direction * self.radius + offset,
+        direction: -direction,
+      };
+      return Some((ray, power));
+    }
+    None
+  }
+
+  /**
+   * sample_emitter で光源 (object の geometry) 上の点xがサンプリングされる確率密度
+   */
+  pub fn emitter_pdf(
+    &self,
+    object: &Object,
+    geometry: &(dyn Geometry + Send + Sync),
+    x: Vector3,
+  ) -> Option<pdf::Area> {
+    self
+      .pdf(object, geometry, x)
+      .map(|pdf| pdf * (1.0 / self.area_pdf))
+  }
+
+  /**
+   * 環境光の方向directionがサンプリングされる確率密度
+   */
+  pub fn environment_pdf(&self, direction: Vector3) -> Option<pdf::SolidAngle> {
+    if self.environment_pdf > 0.0 {
+      Some(self.environment.pdf(direction) * self.environment_pdf)
+    } else {
       None
-    })
+    }
   }
 
-  pub fn pdf(&self, geometry: &Box<dyn Geometry + Send + Sync>) -> Option<pdf::Area> {
+  /**
+   * 光源 (object の geometry) 上の点xがサンプリングされる確率密度
+   */
+  pub fn pdf(
+    &self,
+    object: &Object,
+    geometry: &(dyn Geometry + Send + Sync),
+    x: Vector3,
+  ) -> Option<pdf::Area> {
     self
       .light
       .iter()
-      .position(|v| v.geometry.id() == geometry.id())
-      .map(|i| geometry.pdf() * self.pdf[i])
+      .position(|&v| std::ptr::eq(v, object))
+      .map(|i| self.light[i].pdf(geometry, x) * self.pdf[i])
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use environment::Constant as ConstantEnvironment;
+  use geometry::{Triangle, UUID};
+  use material::{Lambertian, Material};
+  use sampler::Independent;
+  use texture::Constant;
+
+  #[test]
+  fn emit_power_test() {
+    // 放出した光子の放射束の平均は、面光源と点光源の放射束の合計になる
+    let mut uuid = UUID::new();
+    let black: Box<dyn Material + Send + Sync> = Box::new(Lambertian {
+      albedo: Box::new(Constant::new(Vector3::zero())),
+    });
+    let light = AreaLight::diffuse(Box::new(Constant::new(Vector3::fill(2.0))));
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let triangle = Triangle::new(
+      Vector3::new(0.0, 0.0, 0.0),
+      Vector3::new(1.0, 0.0, 0.0),
+      Vector3::new(0.0, 1.0, 0.0),
+      n,
+      n,
+      n,
+      &mut uuid,
+    );
+    let objects =
+      vec![Object::new(Box::new(triangle), Matrix4::unit(), &black).with_area_light(&light)];
+    let delta_lights = [DeltaLight::Point {
+      position: Vector3::new(0.0, 0.0, 1.0),
+      intensity: Vector3::fill(0.5),
+    }];
+    let environment = ConstantEnvironment {
+      radiance: Vector3::zero(),
+    };
+    let light_sampler = LightSampler::new(&objects, &delta_lights, &environment);
+    let expected = 2.0 * PI * 0.5 + 0.5 * 4.0 * PI;
+    let sampler = Independent::new(1);
+    let m = 100000;
+    let mut sum = 0.0;
+    for i in 0..m {
+      Sampling::start_pixel(&sampler, i);
+      if let Some((_, power)) = light_sampler.emit() {
+        sum += power.x;
+      }
+    }
+    let mean = sum / m as f32;
+    assert!(
+      (mean - expected).abs() < 1e-2 * expected,
+      "{} {}",
+      mean,
+      expected
+    );
   }
 }
@@ -1,8 +1,12 @@
+mod area_light;
+mod delta_light;
 mod interaction;
 mod light_sampler;
 mod object;
 mod transform;
 
+pub use self::area_light::*;
+pub use self::delta_light::*;
 pub use self::interaction::*;
 pub use self::light_sampler::*;
 pub use self::object::*;
@@ -1,15 +1,46 @@
+use super::AreaLight;
 use super::Interact;
 use super::Interaction;
 use super::Transform;
-use geometry::Geometry;
+use geometry::{Geometry, Intersection, Mesh, AABB, UUID};
 use material::Material;
 use math::*;
+use medium::MediumInterface;
 use ray::Ray;
+use sample::{pdf, Sample};
+use texture::NormalMap;
+
+/**
+ * オブジェクトの形状
+ *
+ * メッシュは複数のオブジェクトから共有される
+ */
+enum Shape<'a> {
+  Geometry(Box<dyn Geometry + Send + Sync>),
+  Mesh(&'a Mesh),
+}
 
 pub struct Object<'a> {
-  pub geometry: Box<dyn Geometry + Send + Sync>,
+  shape: Shape<'a>,
+  // メッシュの三角形のid (1から連番) に足してシーン全体で一意にする
+  id_offset: usize,
+  // オブジェクト座標系 -> ワールド座標系
   matrix: Matrix4,
+  // ワールド座標系 -> オブジェクト座標系
+  inverse: Matrix4,
+  // 法線の変換 (逆行列の転置)
+  normal_matrix: Matrix4,
+  is_identity: bool,
+  // ワールド座標系での表面積
+  area: f32,
+  aabb: AABB,
   pub material: &'a Box<dyn Material + Send + Sync>,
+  // 境界の内外の媒質 (Noneの場合は媒質の境界ではない)
+  pub medium: Option<MediumInterface<'a>>,
+  // シェーディング法線を揺らす法線マップ (バンプマップ)
+  pub normal_map: Option<&'a NormalMap>,
+  // 表面からの放射 (Noneの場合は光源ではない)
+  pub area_light: Option<&'a AreaLight>,
 }
 
 impl<'a> Object<'a> {
@@ -18,10 +49,188 @@ impl<'a> Object<'a> {
     matrix: Matrix4,
     material: &'a Box<dyn Material + Send + Sync>,
   ) -> Self {
+    let aabb = geometry.aabb().transform(&matrix);
+    Self::with_shape(Shape::Geometry(geometry), 0, aabb, matrix, material)
+  }
+
+  /**
+   * メッシュのインスタンス
+   *
+   * 三角形の分のidをuuidから確保する
+   */
+  pub fn instance(
+    mesh: &'a Mesh,
+    matrix: Matrix4,
+    material: &'a Box<dyn Material + Send + Sync>,
+    uuid: &mut UUID,
+  ) -> Self {
+    let id_offset = uuid.reserve(mesh.len());
+    let aabb = mesh.aabb().transform(&matrix);
+    Self::with_shape(Shape::Mesh(mesh), id_offset, aabb, matrix, material)
+  }
+
+  fn with_shape(
+    shape: Shape<'a>,
+    id_offset: usize,
+    aabb: AABB,
+    matrix: Matrix4,
+    material: &'a Box<dyn Material + Send + Sync>,
+  ) -> Self {
+    debug_assert!(matrix.determinant() != 0.0, "singular matrix\n{}", matrix);
+    let inverse = matrix.inverse();
+    let normal_matrix = inverse.transpose();
+    let is_identity = matrix == Matrix4::unit();
+    let area = match shape {
+      Shape::Geometry(ref geometry) if is_identity => geometry.area(),
+      Shape::Geometry(ref geometry) => geometry.transformed_area(&matrix),
+      Shape::Mesh(mesh) if is_identity => mesh.area(),
+      Shape::Mesh(mesh) => mesh.transformed_area(&matrix),
+    };
     Object {
-      geometry: geometry,
+      shape: shape,
+      id_offset: id_offset,
       matrix: matrix,
+      inverse: inverse,
+      normal_matrix: normal_matrix,
+      is_identity: is_identity,
+      area: area,
+      aabb: aabb,
       material: material,
+      medium: None,
+      normal_map: None,
+      area_light: None,
+    }
+  }
+
+  /**
+   * 媒質の境界にする
+   */
+  pub fn with_medium(mut self, medium: MediumInterface<'a>) -> Self {
+    self.medium = Some(medium);
+    self
+  }
+
+  /**
+   * 法線マップ (バンプマップ) を使う
+   */
+  pub fn with_normal_map(mut self, normal_map: &'a NormalMap) -> Self {
+    self.normal_map = Some(normal_map);
+    self
+  }
+
+  /**
+   * 表面から放射する光源にする
+   */
+  pub fn with_area_light(mut self, area_light: &'a AreaLight) -> Self {
+    self.area_light = Some(area_light);
+    self
+  }
+
+  /**
+   * ワールド座標系でのAABB
+   */
+  pub fn aabb(&self) -> &AABB {
+    &self.aabb
+  }
+
+  /**
+   * シーン全体で一意なジオメトリのid
+   */
+  pub fn geometry_id(&self, geometry: &(dyn Geometry + Send + Sync)) -> usize {
+    self.id_offset + geometry.id()
+  }
+
+  /**
+   * オブジェクト座標系の法線nの点における面積の拡大率
+   *
+   * dA' = |det(M)| |M^-T n| dA
+   */
+  fn area_scale(&self, n: Vector3) -> f32 {
+    if self.is_identity {
+      return 1.0;
+    }
+    self.matrix.determinant().abs() * self.normal_matrix.transform_direction(n).norm()
+  }
+
+  /**
+   * ワールド座標系での表面積
+   */
+  pub fn area(&self) -> f32 {
+    self.area
+  }
+
+  /**
+   * ワールド座標系での表面上の点のサンプリング
+   */
+  pub fn sample(&self) -> Sample<Vector3, pdf::Area> {
+    self.sample_surface().0
+  }
+
+  /**
+   * ワールド座標系での表面上の点と、その点の法線とテクスチャ座標のサンプリング
+   */
+  pub fn sample_surface(&self) -> (Sample<Vector3, pdf::Area>, Vector3, (f32, f32)) {
+    let (geometry, sample): (&dyn Geometry, _) = match self.shape {
+      Shape::Geometry(ref geometry) => (&**geometry, geometry.sample()),
+      Shape::Mesh(mesh) => {
+        let (triangle, sample) = mesh.sample();
+        (triangle, sample)
+      }
+    };
+    let n = geometry.normal(sample.value);
+    let uv = geometry.uv(sample.value);
+    if self.is_identity {
+      return (sample, n, uv);
+    }
+    (
+      Sample {
+        value: &self.matrix * sample.value,
+        pdf: sample.pdf * (1.0 / self.area_scale(n)),
+      },
+      self.normal_matrix.transform_direction(n).normalize(),
+      uv,
+    )
+  }
+
+  /**
+   * ワールド座標系の点x (geometry上) における面積測度の確率密度
+   */
+  pub fn pdf(&self, geometry: &(dyn Geometry + Send + Sync), x: Vector3) -> pdf::Area {
+    let pdf = match self.shape {
+      Shape::Geometry(ref geometry) => geometry.pdf(),
+      Shape::Mesh(mesh) => mesh.pdf(),
+    };
+    if self.is_identity {
+      return pdf;
+    }
+    let n = geometry.normal(&self.inverse * x);
+    pdf * (1.0 / self.area_scale(n))
+  }
+
+  /**
+   * オブジェクト座標系のレイに対する交差判定
+   */
+  fn intersect(&self, ray: &Ray) -> Option<(&(dyn Geometry + Send + Sync), Intersection)> {
+    match self.shape {
+      Shape::Geometry(ref geometry) => geometry.intersect(ray).map(|v| (&**geometry, v)),
+      Shape::Mesh(mesh) => {
+        // 自己交差判定のidをメッシュ内のidに戻す
+        let from = ray.from.and_then(|id| {
+          if id > self.id_offset && id <= self.id_offset + mesh.len() {
+            Some(id - self.id_offset)
+          } else {
+            None
+          }
+        });
+        let ray = Ray {
+          from: from,
+          origin: ray.origin,
+          direction: ray.direction,
+        };
+        mesh
+          .intersect(&ray)
+          .map(|(triangle, v)| (triangle as &(dyn Geometry + Send + Sync), v))
+      }
     }
   }
 }
@@ -33,10 +242,155 @@ impl<'a> Transform for Object<'a> {
 }
 
 impl<'a> Interact for Object<'a> {
-  fn interact<'b>(&'b self, ray: Ray) -> Option<Interaction> {
-    self
-      .geometry
-      .intersect(&ray)
-      .map(|intersection| Interaction::new(intersection, &self.material, &self.geometry, ray))
+  fn interact<'b>(&'b self, ray: Ray) -> Option<Interaction<'b>> {
+    if self.is_identity {
+      return self
+        .intersect(&ray)
+        .map(|(geometry, intersection)| Interaction::new(intersection, self, geometry, ray));
+    }
+    // レイをオブジェクト座標系に変換
+    let direction = self.inverse.transform_direction(ray.direction);
+    // 方向ベクトルの拡大率 (オブジェクト座標系での距離 / ワールド座標系での距離)
+    let scale = direction.norm();
+    let local_ray = Ray {
+      from: ray.from,
+      origin: &self.inverse * ray.origin,
+      direction: direction / scale,
+    };
+    self.intersect(&local_ray).and_then(|(geometry, local)| {
+      // 交差点をワールド座標系に戻す
+      let distance = local.distance / scale;
+      // 縮小されたオブジェクトでは近すぎる衝突点が残るので棄却
+      if distance < EPS {
+        return None;
+      }
+      let intersection = Intersection {
+        position: &self.matrix * local.position,
+        normal: self
+          .normal_matrix
+          .transform_direction(local.normal)
+          .normalize(),
+        shading_normal: self
+          .normal_matrix
+          .transform_direction(local.shading_normal)
+          .normalize(),
+        distance: distance,
+        uv: local.uv,
+        barycentric: local.barycentric,
+        tangent: self.matrix.transform_direction(local.tangent),
+      };
+      Some(Interaction::new(intersection, self, geometry, ray))
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use geometry::{Sphere, Triangle};
+  use material::Lambertian;
+  use texture::Constant;
+
+  fn material() -> Box<dyn Material + Send + Sync> {
+    Box::new(Lambertian {
+      albedo: Box::new(Constant::new(Vector3::fill(0.5))),
+    })
+  }
+
+  // z=0 の 2x2 の四角形 (法線は +z)
+  fn quad() -> Mesh {
+    let mut uuid = UUID::new();
+    let n = Vector3::new(0.0, 0.0, 1.0);
+    let p = [
+      Vector3::new(-1.0, -1.0, 0.0),
+      Vector3::new(1.0, -1.0, 0.0),
+      Vector3::new(1.0, 1.0, 0.0),
+      Vector3::new(-1.0, 1.0, 0.0),
+    ];
+    Mesh::new(vec![
+      Triangle::new(p[0], p[1], p[2], n, n, n, &mut uuid),
+      Triangle::new(p[0], p[2], p[3], n, n, n, &mut uuid),
+    ])
+  }
+
+  // 非一様なスケールと回転と平行移動
+  fn matrix() -> Matrix4 {
+    let rotation = Matrix4::axis_angle(Vector3::new(1.0, 1.0, 0.0).normalize(), 0.7);
+    Matrix4::trs(
+      Vector3::new(1.0, 2.0, -3.0),
+      &rotation,
+      Vector3::new(3.0, 0.5, 2.0),
+    )
+  }
+
+  #[test]
+  fn transformed_quad_test() {
+    let mesh = quad();
+    let m = material();
+    let matrix = matrix();
+    let object = Object::instance(&mesh, matrix.clone(), &m, &mut UUID::new());
+    // 2x2 の四角形が 6x1 になる
+    assert!((object.area() - 6.0).abs() < 1e-4, "{}", object.area());
+    // 変換後の面上の点に斜めに向かうレイ
+    let normal = matrix
+      .normal_matrix()
+      .transform_direction(Vector3::new(0.0, 0.0, 1.0))
+      .normalize();
+    let center = &matrix * Vector3::new(0.2, -0.3, 0.0);
+    let origin = center + (normal + Vector3::new(0.3, 0.1, 0.0)).normalize() * 5.0;
+    let i = object
+      .interact(Ray {
+        from: None,
+        origin: origin,
+        direction: (center - origin).normalize(),
+      })
+      .unwrap();
+    assert!((i.intersection.position - center).norm() < 1e-4);
+    assert!((i.intersection.distance - 5.0).abs() < 1e-4);
+    assert!(i.intersection.normal.dot(normal).approx_eq(1.0));
+  }
+
+  #[test]
+  fn transformed_sampling_test() {
+    // サンプリングした点は変換後の面の上にあり、確率密度の逆数の平均は表面積になる
+    let mesh = quad();
+    let m = material();
+    let object = Object::instance(&mesh, matrix(), &m, &mut UUID::new());
+    let origin = &matrix() * Vector3::zero();
+    let count = 10000;
+    let mut sum = 0.0;
+    for _ in 0..count {
+      let (sample, n, _) = object.sample_surface();
+      assert!((sample.value - origin).dot(n).abs() < 1e-3);
+      sum += 1.0 / sample.pdf.0;
+    }
+    let mean = sum / count as f32;
+    assert!(
+      (mean - object.area()).abs() < 1e-3 * object.area(),
+      "{}",
+      mean
+    );
+  }
+
+  #[test]
+  fn transformed_sphere_area_test() {
+    let m = material();
+    let scale = |s: Vector3| {
+      let sphere = Sphere::new(Vector3::zero(), 1.0, &mut UUID::new());
+      Object::new(Box::new(sphere), Matrix4::scale(s), &m).area()
+    };
+    // 一様なスケール
+    let uniform = scale(Vector3::fill(2.0));
+    assert!((uniform - 16.0 * PI).abs() < 1e-3 * uniform, "{}", uniform);
+    // 長軸2, 短軸1の回転楕円体: 2πa^2 (1 + c / (a e) asin(e))
+    let e = (1.0f32 - 1.0 / 4.0).sqrt();
+    let expected = 2.0 * PI * (1.0 + 2.0 / e * e.asin());
+    let prolate = scale(Vector3::new(1.0, 1.0, 2.0));
+    assert!(
+      (prolate - expected).abs() < 1e-3 * expected,
+      "{} {}",
+      prolate,
+      expected
+    );
   }
 }
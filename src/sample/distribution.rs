@@ -1 +1,92 @@
 pub const DELTA_FUNCTION: f32 = 1.0;
+
+/**
+ * 離散的な重みに比例した区分的一定の分布
+ *
+ * 重みの合計が0の場合は一様分布として扱う
+ */
+pub struct Distribution1D {
+  cdf: Vec<f32>,
+  sum: f32,
+}
+
+impl Distribution1D {
+  pub fn new(weights: &[f32]) -> Self {
+    debug_assert!(!weights.is_empty());
+    debug_assert!(weights.iter().all(|&w| w >= 0.0 && w.is_finite()));
+    let sum: f32 = weights.iter().sum();
+    let n = weights.len();
+    let mut accumulator = 0.0;
+    let cdf = weights
+      .iter()
+      .map(|&w| {
+        accumulator += if sum > 0.0 { w / sum } else { 1.0 / n as f32 };
+        accumulator
+      })
+      .collect();
+    Distribution1D { cdf: cdf, sum: sum }
+  }
+
+  pub fn len(&self) -> usize {
+    self.cdf.len()
+  }
+
+  pub fn sum(&self) -> f32 {
+    self.sum
+  }
+
+  /**
+   * [0, 1) の一様乱数uからインデックスを選ぶ
+   */
+  pub fn sample(&self, u: f32) -> usize {
+    // u < cdf[i] となる最小のi (二分探索)
+    let mut low = 0;
+    let mut high = self.cdf.len() - 1;
+    while low < high {
+      let mid = (low + high) / 2;
+      if u < self.cdf[mid] {
+        high = mid;
+      } else {
+        low = mid + 1;
+      }
+    }
+    // 丸め誤差でcdfの末尾が1に届かない場合に、末尾の重み0のインデックスを選ばない
+    if u >= self.cdf[low] {
+      while low > 0 && self.probability(low) <= 0.0 {
+        low -= 1;
+      }
+    }
+    low
+  }
+
+  /**
+   * インデックスiが選ばれる確率
+   */
+  pub fn probability(&self, i: usize) -> f32 {
+    if i == 0 {
+      self.cdf[0]
+    } else {
+      self.cdf[i] - self.cdf[i - 1]
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use math::*;
+
+  #[test]
+  fn distribution_1d_test() {
+    let d = Distribution1D::new(&[1.0, 0.0, 3.0]);
+    assert!(d.sum().approx_eq(4.0));
+    assert!(d.probability(0).approx_eq(0.25));
+    assert!(d.probability(1).approx_eq(0.0));
+    assert!(d.probability(2).approx_eq(0.75));
+    assert_eq!(d.sample(0.0), 0);
+    assert_eq!(d.sample(0.2), 0);
+    assert_eq!(d.sample(0.25), 2);
+    assert_eq!(d.sample(0.3), 2);
+    assert_eq!(d.sample(0.999), 2);
+  }
+}
@@ -0,0 +1,122 @@
+use super::hash;
+use super::Sampler;
+
+/**
+ * Owenスクランブルを施したHalton列
+ *
+ * 次元ごとに異なる素数を基数とする radical inverse を使う
+ * 画素ごとに桁の置換を変えて画素間の相関をなくす
+ * 素数を使い切った次元では独立な乱数になる
+ */
+#[derive(Clone)]
+pub struct Halton {
+  seed: u64,
+  pixel_seed: u64,
+  index: u64,
+  dimension: usize,
+}
+
+impl Halton {
+  pub fn new(seed: u64) -> Self {
+    Halton {
+      seed: seed,
+      pixel_seed: seed,
+      index: 0,
+      dimension: 0,
+    }
+  }
+
+  fn sample_dimension(&mut self) -> f32 {
+    let dimension = self.dimension;
+    self.dimension += 1;
+    let h = hash::hash(&[self.pixel_seed, dimension as u64]);
+    if dimension < PRIMES.len() {
+      scrambled_radical_inverse(PRIMES[dimension], self.index, h)
+    } else {
+      hash::to_unit(hash::hash(&[h, self.index]))
+    }
+  }
+}
+
+impl Sampler for Halton {
+  fn start_pixel(&mut self, pixel: usize) {
+    self.pixel_seed = hash::hash(&[self.seed, pixel as u64]);
+    self.index = 0;
+    self.dimension = 0;
+  }
+
+  fn start_sample(&mut self, index: usize) {
+    self.index = index as u64;
+    self.dimension = 0;
+  }
+
+  fn get_1d(&mut self) -> f32 {
+    self.sample_dimension()
+  }
+
+  fn get_2d(&mut self) -> (f32, f32) {
+    let u = self.sample_dimension();
+    let v = self.sample_dimension();
+    (u, v)
+  }
+
+  fn clone_box(&self) -> Box<dyn Sampler> {
+    Box::new(self.clone())
+  }
+}
+
+/**
+ * 基数baseのradical inverseの各桁を、上位の桁に依存するランダムな置換で入れ替える
+ */
+fn scrambled_radical_inverse(base: u64, index: u64, h: u64) -> f32 {
+  let inv_base = 1.0 / base as f64;
+  let mut inv_base_m = 1.0;
+  let mut reversed_digits = 0u64;
+  let mut a = index;
+  // f32の精度で寄与がなくなるまで桁を取る
+  while inv_base_m > f32::EPSILON as f64 / 2.0 {
+    let next = a / base;
+    let digit = a - next * base;
+    let digit_hash = hash::mix_bits(h ^ reversed_digits) as u32;
+    let digit = hash::permutation_element(digit as u32, base as u32, digit_hash) as u64;
+    reversed_digits = reversed_digits * base + digit;
+    inv_base_m *= inv_base;
+    a = next;
+  }
+  ((reversed_digits as f64 * inv_base_m) as f32).min(hash::ONE_MINUS_EPSILON)
+}
+
+// 各次元の基数
+const PRIMES: [u64; 64] = [
+  2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+  101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+  197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+  311,
+];
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn stratified_test() {
+    // 最初の base^2 個の点は各次元で base^2 個の区間に1つずつ入る
+    let mut sampler = Halton::new(7);
+    sampler.start_pixel(11);
+    for &base in &[2, 3, 5] {
+      let n = base * base;
+      let dimension = PRIMES.iter().position(|&p| p == base as u64).unwrap();
+      let mut strata = (0..n)
+        .map(|i| {
+          sampler.start_sample(i);
+          for _ in 0..dimension {
+            sampler.get_1d();
+          }
+          (sampler.get_1d() * n as f32) as usize
+        })
+        .collect::<Vec<_>>();
+      strata.sort();
+      assert_eq!(strata, (0..n).collect::<Vec<_>>());
+    }
+  }
+}
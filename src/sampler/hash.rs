@@ -0,0 +1,73 @@
+/**
+ * [0, 1) に収まる最大の値
+ */
+pub const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/**
+ * 64bit整数の攪拌 (MurmurHash3 の finalizer の変種)
+ */
+pub fn mix_bits(v: u64) -> u64 {
+  let mut v = v;
+  v ^= v >> 31;
+  v = v.wrapping_mul(0x7fb5_d329_728e_a185);
+  v ^= v >> 27;
+  v = v.wrapping_mul(0x81da_def4_bc2d_d44d);
+  v ^= v >> 33;
+  v
+}
+
+/**
+ * 複数の値をまとめたハッシュ
+ */
+pub fn hash(values: &[u64]) -> u64 {
+  values.iter().fold(0x9e37_79b9_7f4a_7c15, |h, &v| {
+    mix_bits(h ^ mix_bits(v.wrapping_add(0x9e37_79b9_7f4a_7c15)))
+  })
+}
+
+/**
+ * ハッシュ値 -> [0, 1) の一様な値
+ */
+pub fn to_unit(h: u64) -> f32 {
+  (h >> 40) as f32 / (1u32 << 24) as f32
+}
+
+/**
+ * [0, l) の順列のi番目の要素 (pはハッシュ値で順列を選ぶ)
+ *
+ * Kensler, "Correlated Multi-Jittered Sampling" (2013)
+ */
+pub fn permutation_element(i: u32, l: u32, p: u32) -> u32 {
+  debug_assert!(l > 0);
+  let mut w = l - 1;
+  w |= w >> 1;
+  w |= w >> 2;
+  w |= w >> 4;
+  w |= w >> 8;
+  w |= w >> 16;
+  let mut i = i;
+  loop {
+    i ^= p;
+    i = i.wrapping_mul(0xe170_893d);
+    i ^= p >> 16;
+    i ^= (i & w) >> 4;
+    i ^= p >> 8;
+    i = i.wrapping_mul(0x0929_eb3f);
+    i ^= p >> 23;
+    i ^= (i & w) >> 1;
+    i = i.wrapping_mul(1 | p >> 27);
+    i = i.wrapping_mul(0x6935_fa69);
+    i ^= (i & w) >> 11;
+    i = i.wrapping_mul(0x74dc_b303);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0x9e50_1cc3);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0xc860_a3df);
+    i &= w;
+    i ^= i >> 5;
+    if i < l {
+      break;
+    }
+  }
+  (i.wrapping_add(p)) % l
+}
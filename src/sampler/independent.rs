@@ -0,0 +1,46 @@
+use super::hash;
+use super::Sampler;
+use rand::{Rng, SeedableRng};
+use RNG;
+
+/**
+ * 互いに独立な一様乱数
+ *
+ * 画素ごとにシードから乱数生成器を初期化する
+ */
+#[derive(Clone)]
+pub struct Independent {
+  seed: u64,
+  rng: RNG,
+}
+
+impl Independent {
+  pub fn new(seed: u64) -> Self {
+    Independent {
+      seed: seed,
+      rng: RNG::seed_from_u64(seed),
+    }
+  }
+}
+
+impl Sampler for Independent {
+  fn start_pixel(&mut self, pixel: usize) {
+    self.rng = RNG::seed_from_u64(hash::hash(&[self.seed, pixel as u64]));
+  }
+
+  fn start_sample(&mut self, _index: usize) {}
+
+  fn get_1d(&mut self) -> f32 {
+    self.rng.gen::<f32>()
+  }
+
+  fn get_2d(&mut self) -> (f32, f32) {
+    let u = self.rng.gen::<f32>();
+    let v = self.rng.gen::<f32>();
+    (u, v)
+  }
+
+  fn clone_box(&self) -> Box<dyn Sampler> {
+    Box::new(self.clone())
+  }
+}
@@ -0,0 +1,16 @@
+mod halton;
+mod hash;
+mod independent;
+mod primary_sample;
+mod sampler;
+mod sampling;
+mod sobol;
+mod stratified;
+
+pub use self::halton::*;
+pub use self::independent::*;
+pub use self::primary_sample::*;
+pub use self::sampler::*;
+pub use self::sampling::*;
+pub use self::sobol::*;
+pub use self::stratified::*;
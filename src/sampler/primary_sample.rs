@@ -0,0 +1,191 @@
+use super::hash::{self, ONE_MINUS_EPSILON};
+use super::Sampler;
+use math::*;
+use rand::{Rng, SeedableRng};
+use RNG;
+
+/**
+ * 主標本空間 (primary sample space) の点を変異させるサンプラー
+ *
+ * Kelemen et al., "A Simple and Robust Mutation Strategy for the Metropolis Light Transport Algorithm" (2002)
+ * 取り出した値を次元ごとに記録しておき、反復ごとにそれを変異させた値を返す
+ * 変異は大きな変異 (一様乱数で置き換える) と小さな変異 (正規分布で少しずらす) のどちらか
+ * 各次元は取り出されたときに、前回から溜まった分の変異をまとめて受ける
+ * 提案を棄却した場合は、その反復で変異させた値を元に戻す
+ *
+ * 同じシードとストリームから始めると同じ値の列を再現できる
+ */
+#[derive(Clone)]
+pub struct PrimarySample {
+  rng: RNG,
+  // 小さな変異の標準偏差
+  sigma: f32,
+  large_step_probability: f32,
+  values: Vec<PrimarySampleValue>,
+  iteration: u64,
+  large_step: bool,
+  last_large_step_iteration: u64,
+  // 次に取り出す次元
+  index: usize,
+}
+
+#[derive(Clone, Default)]
+struct PrimarySampleValue {
+  value: f32,
+  // 最後に変異させた反復
+  modified: u64,
+  value_backup: f32,
+  modified_backup: u64,
+}
+
+impl PrimarySample {
+  pub fn new(seed: u64, stream: usize, sigma: f32, large_step_probability: f32) -> Self {
+    PrimarySample {
+      rng: RNG::seed_from_u64(hash::hash(&[seed, stream as u64])),
+      sigma: sigma,
+      large_step_probability: large_step_probability,
+      values: Vec::new(),
+      iteration: 0,
+      // 最初の値は一様乱数
+      large_step: true,
+      last_large_step_iteration: 0,
+      index: 0,
+    }
+  }
+
+  /**
+   * 記録した値はそのままに、以降の変異に使う乱数の列を別のストリームに切り替える
+   */
+  pub fn reseed(&mut self, seed: u64, stream: usize) {
+    self.rng = RNG::seed_from_u64(hash::hash(&[seed, stream as u64]));
+  }
+
+  /**
+   * 次の反復を始めて、大きな変異か小さな変異かを決める
+   */
+  pub fn start_iteration(&mut self) {
+    self.iteration += 1;
+    self.large_step = self.rng.gen::<f32>() < self.large_step_probability;
+    self.index = 0;
+  }
+
+  pub fn is_large_step(&self) -> bool {
+    self.large_step
+  }
+
+  /**
+   * 提案を受理する
+   */
+  pub fn accept(&mut self) {
+    if self.large_step {
+      self.last_large_step_iteration = self.iteration;
+    }
+  }
+
+  /**
+   * 提案を棄却して、この反復で変異させた値を元に戻す
+   */
+  pub fn reject(&mut self) {
+    let iteration = self.iteration;
+    for x in self.values.iter_mut().filter(|x| x.modified == iteration) {
+      x.value = x.value_backup;
+      x.modified = x.modified_backup;
+    }
+    self.iteration -= 1;
+  }
+
+  /**
+   * 変異とは関係なく使う一様乱数 (受理の判定など)
+   */
+  pub fn uniform(&mut self) -> f32 {
+    self.rng.gen::<f32>()
+  }
+
+  fn ensure_ready(&mut self, index: usize) {
+    if index >= self.values.len() {
+      self.values.resize(index + 1, PrimarySampleValue::default());
+    }
+    let x = &mut self.values[index];
+    // 最後の大きな変異より前の値は、その大きな変異で置き換わっている
+    if x.modified < self.last_large_step_iteration {
+      x.value = self.rng.gen::<f32>();
+      x.modified = self.last_large_step_iteration;
+    }
+    x.value_backup = x.value;
+    x.modified_backup = x.modified;
+    if self.large_step {
+      x.value = self.rng.gen::<f32>();
+    } else {
+      // 溜まっているn回分の小さな変異は、標準偏差をsqrt(n)倍した1回の変異とみなせる
+      let n = (self.iteration - x.modified) as f32;
+      let r1 = self.rng.gen::<f32>();
+      let r2 = self.rng.gen::<f32>();
+      let normal = (-2.0 * (1.0 - r1).ln()).sqrt() * (2.0 * PI * r2).cos();
+      let value = x.value + normal * self.sigma * n.sqrt();
+      x.value = (value - value.floor()).min(ONE_MINUS_EPSILON);
+    }
+    x.modified = self.iteration;
+  }
+}
+
+impl Sampler for PrimarySample {
+  // 画素を選ぶのも主標本空間の値なので、画素の区別はない
+  fn start_pixel(&mut self, _pixel: usize) {}
+
+  fn start_sample(&mut self, _index: usize) {
+    self.index = 0;
+  }
+
+  fn get_1d(&mut self) -> f32 {
+    let index = self.index;
+    self.ensure_ready(index);
+    self.index += 1;
+    self.values[index].value
+  }
+
+  fn get_2d(&mut self) -> (f32, f32) {
+    let u = self.get_1d();
+    let v = self.get_1d();
+    (u, v)
+  }
+
+  fn clone_box(&self) -> Box<dyn Sampler> {
+    Box::new(self.clone())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reject_test() {
+    // 同じシードからは同じ値が再現でき、棄却すると変異前の値に戻る
+    let draw = |sampler: &mut PrimarySample| (0..8).map(|_| sampler.get_1d()).collect::<Vec<_>>();
+    let mut sampler = PrimarySample::new(1, 2, 0.01, 0.3);
+    let initial = draw(&mut sampler);
+    assert_eq!(initial, draw(&mut PrimarySample::new(1, 2, 0.01, 0.3)));
+    for _ in 0..10 {
+      sampler.start_iteration();
+      let proposal = draw(&mut sampler);
+      assert!(proposal.iter().all(|v| (0.0..1.0).contains(v)));
+      assert_ne!(proposal, initial);
+      sampler.reject();
+      let values = sampler.values.iter().map(|x| x.value).collect::<Vec<_>>();
+      assert_eq!(values, initial);
+    }
+  }
+
+  #[test]
+  fn reseed_test() {
+    // ストリームを切り替えても記録した値は変わらず、以降の変異だけが変わる
+    let draw = |sampler: &mut PrimarySample| (0..8).map(|_| sampler.get_1d()).collect::<Vec<_>>();
+    let mut a = PrimarySample::new(1, 2, 0.01, 0.3);
+    let mut b = PrimarySample::new(1, 2, 0.01, 0.3);
+    assert_eq!(draw(&mut a), draw(&mut b));
+    b.reseed(1, 3);
+    a.start_iteration();
+    b.start_iteration();
+    assert_ne!(draw(&mut a), draw(&mut b));
+  }
+}
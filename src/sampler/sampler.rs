@@ -0,0 +1,26 @@
+/**
+ * 画素ごとのサンプル列を生成するサンプラー
+ *
+ * 画素の各サンプルで [0, 1) の値を次元の順に取り出す
+ * 同じ画素, 同じサンプル番号, 同じ次元では同じ値を返す
+ */
+pub trait Sampler {
+  /**
+   * 画素のサンプル列を始める
+   */
+  fn start_pixel(&mut self, pixel: usize);
+
+  /**
+   * 画素内のindex番目のサンプルを始める (次元は0に戻る)
+   */
+  fn start_sample(&mut self, index: usize);
+
+  fn get_1d(&mut self) -> f32;
+
+  fn get_2d(&mut self) -> (f32, f32);
+
+  /**
+   * スレッドごとに持たせるための複製
+   */
+  fn clone_box(&self) -> Box<dyn Sampler>;
+}
@@ -0,0 +1,159 @@
+use super::{Independent, Sampler};
+use math::*;
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+
+thread_local! {
+  static SAMPLER: RefCell<Box<dyn Sampler>> = RefCell::new(Box::new(Independent::new(rand::random())));
+}
+
+/**
+ * スレッドごとの現在のサンプラーからのサンプリング
+ *
+ * レンダリング中の乱数はすべてここを通して取る
+ * 積分器が画素とサンプルの開始を知らせるので、
+ * シードを固定するとスレッドの割り当てによらず同じ画像になる
+ */
+pub struct Sampling;
+
+impl Sampling {
+  /**
+   * サンプラーを複製して画素のサンプル列を始める
+   */
+  pub fn start_pixel(sampler: &dyn Sampler, pixel: usize) {
+    SAMPLER.with(|current| {
+      let mut sampler = sampler.clone_box();
+      sampler.start_pixel(pixel);
+      *current.borrow_mut() = sampler;
+    });
+  }
+
+  /**
+   * samplerを現在のサンプラーにしてfを実行し、取り出した値を記録したサンプラーを返す
+   *
+   * サンプラーの状態を呼び出し側で持ち続けたい場合 (MLTの変異など) に使う
+   * 実行後は元のサンプラーに戻す
+   */
+  pub fn scoped<S, F, R>(sampler: S, f: F) -> (S, R)
+  where
+    S: Sampler + Clone + 'static,
+    F: FnOnce() -> R,
+  {
+    let shared = Rc::new(RefCell::new(sampler));
+    let previous = SAMPLER
+      .with(|current| mem::replace(&mut *current.borrow_mut(), Box::new(Shared(shared.clone()))));
+    let result = f();
+    SAMPLER.with(|current| *current.borrow_mut() = previous);
+    let sampler = match Rc::try_unwrap(shared) {
+      Ok(sampler) => sampler.into_inner(),
+      Err(_) => unreachable!(),
+    };
+    (sampler, result)
+  }
+
+  pub fn start_sample(index: usize) {
+    SAMPLER.with(|current| current.borrow_mut().start_sample(index));
+  }
+
+  /**
+   * [0, 1) の値
+   */
+  pub fn get_1d() -> f32 {
+    SAMPLER.with(|current| current.borrow_mut().get_1d())
+  }
+
+  /**
+   * [0, 1)^2 の値
+   */
+  pub fn get_2d() -> (f32, f32) {
+    SAMPLER.with(|current| current.borrow_mut().get_2d())
+  }
+
+  pub fn hemisphere_cos_importance() -> Vector3 {
+    // 乱数を生成
+    // (cosにしたがって重点的にサンプル)
+    let (r1, r2) = Self::get_2d();
+    let r1 = 2.0 * PI * r1;
+    let r2s = r2.sqrt();
+    // 球面極座標を用いて反射点から単位半球面上のある一点へのベクトルを生成
+    // (cosにしたがって重点的にサンプル)
+    Vector3::new(r1.cos() * r2s, r1.sin() * r2s, (1.0 - r2).sqrt())
+  }
+
+  pub fn hemisphere_uniform() -> Vector3 {
+    // 乱数を生成
+    let (r1, r2) = Self::get_2d();
+    let r1 = 2.0 * PI * r1;
+    let r2s = (1.0 - r2 * r2).sqrt();
+    // 球面極座標を用いて反射点から単位半球面上のある一点へのベクトルを生成
+    // (一様サンプル)
+    Vector3::new(r1.cos() * r2s, r1.sin() * r2s, r2.sqrt())
+  }
+
+  pub fn sphere_uniform() -> Vector3 {
+    // 乱数を生成
+    let (r1, r2) = Self::get_2d();
+    let r1 = 2.0 * PI * r1;
+    let r2 = r2 * 2.0 - 1.0;
+    let r2s = (1.0 - r2 * r2).sqrt();
+    // 球面極座標を用いて反射点から単位半球面上のある一点へのベクトルを生成
+    // (一様サンプル)
+    Vector3::new(r1.cos() * r2s, r1.sin() * r2s, r2)
+  }
+}
+
+/**
+ * 呼び出し側と状態を共有するサンプラー
+ */
+struct Shared<S>(Rc<RefCell<S>>);
+
+impl<S> Sampler for Shared<S>
+where
+  S: Sampler + Clone + 'static,
+{
+  fn start_pixel(&mut self, pixel: usize) {
+    self.0.borrow_mut().start_pixel(pixel)
+  }
+
+  fn start_sample(&mut self, index: usize) {
+    self.0.borrow_mut().start_sample(index)
+  }
+
+  fn get_1d(&mut self) -> f32 {
+    self.0.borrow_mut().get_1d()
+  }
+
+  fn get_2d(&mut self) -> (f32, f32) {
+    self.0.borrow_mut().get_2d()
+  }
+
+  fn clone_box(&self) -> Box<dyn Sampler> {
+    Box::new(self.0.borrow().clone())
+  }
+}
+
+pub struct Roulette;
+
+impl Roulette {
+  pub fn within(threshold: f32) -> bool {
+    Sampling::get_1d() < threshold
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn seed_test() {
+    let draw = |seed| {
+      Sampling::start_pixel(&Independent::new(seed), 3);
+      (0..16).map(|_| Sampling::get_1d()).collect::<Vec<_>>()
+    };
+    let a = draw(42);
+    assert_eq!(a, draw(42));
+    assert_ne!(a, draw(43));
+    assert!(a.iter().all(|v| (0.0..1.0).contains(v)));
+  }
+}
@@ -0,0 +1,145 @@
+use super::hash;
+use super::Sampler;
+
+/**
+ * Owenスクランブルを施したSobol列
+ *
+ * Burley, "Practical Hash-based Owen Scrambling" (2020)
+ * 2次元のSobol列を次元ごとに異なるスクランブルとサンプル番号のシャッフルで使い回す
+ * (次元数の上限がない)
+ */
+#[derive(Clone)]
+pub struct Sobol {
+  seed: u64,
+  pixel_seed: u64,
+  index: u32,
+  dimension: u64,
+}
+
+impl Sobol {
+  pub fn new(seed: u64) -> Self {
+    Sobol {
+      seed: seed,
+      pixel_seed: seed,
+      index: 0,
+      dimension: 0,
+    }
+  }
+
+  /**
+   * 現在の次元で使う (シャッフルしたサンプル番号, スクランブルのハッシュ)
+   */
+  fn next_dimension(&mut self) -> (u32, u64) {
+    let h = hash::hash(&[self.pixel_seed, self.dimension]);
+    self.dimension += 1;
+    (nested_uniform_scramble(self.index, h as u32), h)
+  }
+}
+
+impl Sampler for Sobol {
+  fn start_pixel(&mut self, pixel: usize) {
+    self.pixel_seed = hash::hash(&[self.seed, pixel as u64]);
+    self.index = 0;
+    self.dimension = 0;
+  }
+
+  fn start_sample(&mut self, index: usize) {
+    self.index = index as u32;
+    self.dimension = 0;
+  }
+
+  fn get_1d(&mut self) -> f32 {
+    let (index, h) = self.next_dimension();
+    let x = nested_uniform_scramble(sobol_0(index), hash::mix_bits(h) as u32);
+    to_unit(x)
+  }
+
+  fn get_2d(&mut self) -> (f32, f32) {
+    let (index, h) = self.next_dimension();
+    let x = nested_uniform_scramble(sobol_0(index), hash::mix_bits(h) as u32);
+    let y = nested_uniform_scramble(sobol_1(index), hash::mix_bits(h ^ 1) as u32);
+    (to_unit(x), to_unit(y))
+  }
+
+  fn clone_box(&self) -> Box<dyn Sampler> {
+    Box::new(self.clone())
+  }
+}
+
+/**
+ * Sobol列の1次元目 (van der Corput列)
+ */
+fn sobol_0(index: u32) -> u32 {
+  index.reverse_bits()
+}
+
+/**
+ * Sobol列の2次元目 (原始多項式 x + 1)
+ */
+fn sobol_1(index: u32) -> u32 {
+  let mut x = 0;
+  // 方向数 v_k = m_k << (32 - k), m_k = m_{k-1} ^ (m_{k-1} << 1)
+  let mut m = 1u32;
+  let mut index = index;
+  let mut k = 1;
+  while index != 0 {
+    if index & 1 == 1 {
+      x ^= m << (32 - k);
+    }
+    index >>= 1;
+    m ^= m << 1;
+    k += 1;
+  }
+  x
+}
+
+/**
+ * ビットの上位から順に、より上位のビットに依存するランダムな反転を行う
+ */
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+  laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/**
+ * 下位のビットが上位のビットに影響しない置換
+ */
+fn laine_karras_permutation(x: u32, seed: u32) -> u32 {
+  let mut x = x.wrapping_add(seed);
+  x ^= x.wrapping_mul(0x6c50_b47c);
+  x ^= x.wrapping_mul(0xb82f_1e52);
+  x ^= x.wrapping_mul(0xc7af_e638);
+  x ^= x.wrapping_mul(0x8d22_f6e6);
+  x
+}
+
+fn to_unit(x: u32) -> f32 {
+  (x >> 8) as f32 / (1u32 << 24) as f32
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn stratified_test() {
+    // 最初の 2^k 個の点は2次元の基本区間 (2^a x 2^b, a + b = k) に1つずつ入る
+    let mut sampler = Sobol::new(5);
+    sampler.start_pixel(3);
+    let n = 16;
+    let points = (0..n)
+      .map(|i| {
+        sampler.start_sample(i);
+        sampler.get_1d();
+        sampler.get_2d()
+      })
+      .collect::<Vec<_>>();
+    for &(a, b) in &[(1, 16), (2, 8), (4, 4), (8, 2), (16, 1)] {
+      let mut cells = points
+        .iter()
+        .map(|&(u, v)| (u * a as f32) as usize * b + (v * b as f32) as usize)
+        .collect::<Vec<_>>();
+      cells.sort();
+      assert_eq!(cells, (0..n).collect::<Vec<_>>(), "{}x{}", a, b);
+    }
+  }
+}
@@ -0,0 +1,114 @@
+use super::hash;
+use super::Sampler;
+
+/**
+ * 層化 (jittered) サンプリング
+ *
+ * 次元ごとに画素のspp個のサンプルを層に割り当て、層内でランダムにずらす
+ * 層の割り当ては次元ごとのランダムな順列で決める
+ */
+#[derive(Clone)]
+pub struct Stratified {
+  seed: u64,
+  spp: usize,
+  // 2次元の層の分割数
+  x_strata: usize,
+  y_strata: usize,
+  pixel_seed: u64,
+  index: usize,
+  dimension: u64,
+}
+
+impl Stratified {
+  pub fn new(seed: u64, spp: usize) -> Self {
+    debug_assert!(spp > 0);
+    let x_strata = (spp as f32).sqrt().ceil() as usize;
+    let y_strata = spp.div_ceil(x_strata);
+    Stratified {
+      seed: seed,
+      spp: spp,
+      x_strata: x_strata,
+      y_strata: y_strata,
+      pixel_seed: seed,
+      index: 0,
+      dimension: 0,
+    }
+  }
+
+  /**
+   * 現在の次元でのサンプルの層
+   */
+  fn stratum(&self, count: usize) -> usize {
+    let p = hash::hash(&[self.pixel_seed, self.dimension]) as u32;
+    hash::permutation_element((self.index % count) as u32, count as u32, p) as usize
+  }
+
+  fn jitter(&self, axis: u64) -> f32 {
+    hash::to_unit(hash::hash(&[
+      self.pixel_seed,
+      self.dimension,
+      self.index as u64,
+      axis,
+    ]))
+  }
+}
+
+impl Sampler for Stratified {
+  fn start_pixel(&mut self, pixel: usize) {
+    self.pixel_seed = hash::hash(&[self.seed, pixel as u64]);
+    self.index = 0;
+    self.dimension = 0;
+  }
+
+  fn start_sample(&mut self, index: usize) {
+    self.index = index;
+    self.dimension = 0;
+  }
+
+  fn get_1d(&mut self) -> f32 {
+    let stratum = self.stratum(self.spp);
+    let u = (stratum as f32 + self.jitter(0)) / self.spp as f32;
+    self.dimension += 1;
+    u.min(hash::ONE_MINUS_EPSILON)
+  }
+
+  fn get_2d(&mut self) -> (f32, f32) {
+    // spp が平方数でない場合は余った層を使わない
+    let stratum = self.stratum(self.x_strata * self.y_strata);
+    let x = stratum % self.x_strata;
+    let y = stratum / self.x_strata;
+    let u = (x as f32 + self.jitter(0)) / self.x_strata as f32;
+    let v = (y as f32 + self.jitter(1)) / self.y_strata as f32;
+    self.dimension += 1;
+    (
+      u.min(hash::ONE_MINUS_EPSILON),
+      v.min(hash::ONE_MINUS_EPSILON),
+    )
+  }
+
+  fn clone_box(&self) -> Box<dyn Sampler> {
+    Box::new(self.clone())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn stratified_1d_test() {
+    // spp個のサンプルはそれぞれ異なる層に入る
+    let spp = 16;
+    let mut sampler = Stratified::new(3, spp);
+    sampler.start_pixel(5);
+    let mut strata = (0..spp)
+      .map(|i| {
+        sampler.start_sample(i);
+        sampler.get_2d();
+        (sampler.get_1d() * spp as f32) as usize
+      })
+      .collect::<Vec<_>>();
+    strata.sort();
+    assert_eq!(strata, (0..spp).collect::<Vec<_>>());
+  }
+}
@@ -0,0 +1,33 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub struct Error {
+  // 1始まりの行番号 (ファイル全体に関するエラーの場合はNone)
+  pub line: Option<usize>,
+  pub message: String,
+}
+
+impl Error {
+  pub fn new<S: Into<String>>(message: S) -> Self {
+    Error {
+      line: None,
+      message: message.into(),
+    }
+  }
+
+  pub fn at<S: Into<String>>(line: usize, message: S) -> Self {
+    Error {
+      line: Some(line),
+      message: message.into(),
+    }
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.line {
+      Some(line) => write!(f, "line {}: {}", line, self.message),
+      None => write!(f, "{}", self.message),
+    }
+  }
+}
@@ -0,0 +1,6 @@
+mod error;
+mod parser;
+mod scene;
+
+pub use self::error::*;
+pub use self::scene::*;
@@ -0,0 +1,246 @@
+use super::Error;
+use math::*;
+
+/**
+ * シーン記述の1行分
+ *
+ * `keyword arg1 arg2 ...` の形式で、`#` 以降は行末までコメント
+ */
+#[derive(Clone)]
+pub struct Directive {
+  pub line: usize,
+  pub keyword: String,
+  tokens: Vec<String>,
+  cursor: usize,
+}
+
+impl Directive {
+  pub fn parse(source: &str) -> Result<Vec<Directive>, Error> {
+    let mut directives = Vec::new();
+    for (i, raw) in source.lines().enumerate() {
+      let line = i + 1;
+      let mut tokens = Self::tokenize(raw, line)?;
+      if tokens.is_empty() {
+        continue;
+      }
+      let keyword = tokens.remove(0);
+      directives.push(Directive {
+        line: line,
+        keyword: keyword,
+        tokens: tokens,
+        cursor: 0,
+      });
+    }
+    Ok(directives)
+  }
+
+  fn tokenize(raw: &str, line: usize) -> Result<Vec<String>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = raw.chars().peekable();
+    while let Some(&c) = chars.peek() {
+      if c.is_whitespace() {
+        chars.next();
+      } else if c == '#' {
+        break;
+      } else if c == '"' {
+        // 空白を含むパスのために "..." で囲まれた文字列を1トークンとして扱う
+        chars.next();
+        let mut token = String::new();
+        loop {
+          match chars.next() {
+            Some('"') => break,
+            Some(c) => token.push(c),
+            None => return Err(Error::at(line, "unterminated string")),
+          }
+        }
+        tokens.push(token);
+      } else {
+        let mut token = String::new();
+        while let Some(&c) = chars.peek() {
+          if c.is_whitespace() || c == '#' {
+            break;
+          }
+          token.push(c);
+          chars.next();
+        }
+        tokens.push(token);
+      }
+    }
+    Ok(tokens)
+  }
+
+  pub fn error<S: Into<String>>(&self, message: S) -> Error {
+    Error::at(self.line, message)
+  }
+
+  pub fn is_end(&self) -> bool {
+    self.cursor >= self.tokens.len()
+  }
+
+  pub fn next_word(&mut self) -> Option<String> {
+    let token = self.tokens.get(self.cursor).cloned();
+    if token.is_some() {
+      self.cursor += 1;
+    }
+    token
+  }
+
+  pub fn word(&mut self, what: &str) -> Result<String, Error> {
+    match self.next_word() {
+      Some(token) => Ok(token),
+      None => Err(self.error(format!("`{}` expects {}", self.keyword, what))),
+    }
+  }
+
+  pub fn float(&mut self, what: &str) -> Result<f32, Error> {
+    let token = self.word(what)?;
+    token.parse::<f32>().map_err(|_| {
+      self.error(format!(
+        "`{}` expects {} but found `{}`",
+        self.keyword, what, token
+      ))
+    })
+  }
+
+  pub fn positive_float(&mut self, what: &str) -> Result<f32, Error> {
+    let value = self.float(what)?;
+    if value > 0.0 && value.is_finite() {
+      Ok(value)
+    } else {
+      Err(self.error(format!("{} must be positive but found {}", what, value)))
+    }
+  }
+
+  pub fn unit_float(&mut self, what: &str) -> Result<f32, Error> {
+    let value = self.float(what)?;
+    if (0.0..=1.0).contains(&value) {
+      Ok(value)
+    } else {
+      Err(self.error(format!("{} must be in [0, 1] but found {}", what, value)))
+    }
+  }
+
+  pub fn usize(&mut self, what: &str) -> Result<usize, Error> {
+    let token = self.word(what)?;
+    match token.parse::<usize>() {
+      Ok(value) if value > 0 => Ok(value),
+      _ => Err(self.error(format!(
+        "`{}` expects {} (positive integer) but found `{}`",
+        self.keyword, what, token
+      ))),
+    }
+  }
+
+  pub fn unsigned(&mut self, what: &str) -> Result<usize, Error> {
+    let token = self.word(what)?;
+    token.parse::<usize>().map_err(|_| {
+      self.error(format!(
+        "`{}` expects {} (non-negative integer) but found `{}`",
+        self.keyword, what, token
+      ))
+    })
+  }
+
+  pub fn vector(&mut self, what: &str) -> Result<Vector3, Error> {
+    let x = self.float(what)?;
+    let y = self.float(what)?;
+    let z = self.float(what)?;
+    Ok(Vector3::new(x, y, z))
+  }
+
+  /**
+   * 省略可能な数値の引数
+   *
+   * 次のトークンが数値として解釈できる場合のみ消費する
+   */
+  pub fn maybe_float(&mut self) -> Option<f32> {
+    let value = self
+      .tokens
+      .get(self.cursor)
+      .and_then(|token| token.parse::<f32>().ok());
+    if value.is_some() {
+      self.cursor += 1;
+    }
+    value
+  }
+
+  /**
+   * 省略可能なキーワードの引数
+   *
+   * 次のトークンが候補のいずれかの場合のみ消費する
+   */
+  pub fn maybe_word(&mut self, candidates: &[&str]) -> Option<String> {
+    let token = self
+      .tokens
+      .get(self.cursor)
+      .filter(|token| candidates.contains(&token.as_str()))
+      .cloned();
+    if token.is_some() {
+      self.cursor += 1;
+    }
+    token
+  }
+
+  pub fn unknown(&self, kind: &str, token: &str) -> Error {
+    self.error(format!(
+      "unknown {} `{}` in `{}`",
+      kind, token, self.keyword
+    ))
+  }
+
+  pub fn end(&self) -> Result<(), Error> {
+    if self.is_end() {
+      Ok(())
+    } else {
+      Err(self.error(format!(
+        "unexpected `{}` at the end of `{}`",
+        self.tokens[self.cursor], self.keyword
+      )))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn tokenize_test() {
+    let directives =
+      Directive::parse("# comment\n\nobj \"models/a b.obj\" material white # tail\n").unwrap();
+    assert_eq!(directives.len(), 1);
+    let mut d = directives.into_iter().next().unwrap();
+    assert_eq!(d.line, 3);
+    assert_eq!(d.keyword, "obj");
+    assert_eq!(d.word("path").unwrap(), "models/a b.obj");
+    assert_eq!(d.word("key").unwrap(), "material");
+    assert_eq!(d.word("name").unwrap(), "white");
+    assert!(d.end().is_ok());
+  }
+
+  #[test]
+  fn error_line_test() {
+    let mut d = Directive::parse("spp 10\nfilm 800 abc")
+      .unwrap()
+      .into_iter()
+      .nth(1)
+      .unwrap();
+    assert!(d.usize("width").is_ok());
+    let error = d.usize("height").unwrap_err();
+    assert_eq!(error.line, Some(2));
+  }
+
+  #[test]
+  fn unsigned_test() {
+    let mut d = Directive::parse("depth 0 0 -1").unwrap().remove(0);
+    assert_eq!(d.unsigned("minimum depth").unwrap(), 0);
+    assert!(d.usize("minimum depth").is_err());
+    assert!(d.unsigned("minimum depth").is_err());
+  }
+
+  #[test]
+  fn unterminated_string_test() {
+    let error = Directive::parse("obj \"models/a.obj").err().unwrap();
+    assert_eq!(error.line, Some(1));
+  }
+}
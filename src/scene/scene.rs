@@ -0,0 +1,1473 @@
+use super::parser::Directive;
+use super::Error;
+use acceleration::BVH;
+use camera::IdealPinhole;
+use environment;
+use environment::Environment;
+use film::Film;
+use geometry::{Sphere, UUID};
+use light_transport::{DensityEstimation, RussianRoulette};
+use loader;
+use material;
+use material::Material;
+use math::*;
+use medium;
+use medium::{HenyeyGreenstein, Medium, MediumInterface};
+use object::{AreaLight, DeltaLight, Object, Profile};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use texture;
+use texture::{NormalMap, Texture};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransportKind {
+  Naive,
+  ExplicitLight,
+  OnlyLight,
+  VolumetricPath,
+  Bidirectional,
+  LightTracing,
+  PhotonMapping,
+  ProgressivePhotonMapping,
+  Normal,
+  Id,
+  IntersectionTest,
+}
+
+impl TransportKind {
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "naive" => Some(TransportKind::Naive),
+      "explicit_light" => Some(TransportKind::ExplicitLight),
+      "only_light" => Some(TransportKind::OnlyLight),
+      "volumetric_path" => Some(TransportKind::VolumetricPath),
+      "bidirectional" => Some(TransportKind::Bidirectional),
+      "light_tracing" => Some(TransportKind::LightTracing),
+      "photon_mapping" => Some(TransportKind::PhotonMapping),
+      "progressive_photon_mapping" => Some(TransportKind::ProgressivePhotonMapping),
+      "normal" => Some(TransportKind::Normal),
+      "id" => Some(TransportKind::Id),
+      "intersection_test" => Some(TransportKind::IntersectionTest),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplerKind {
+  Independent,
+  Stratified,
+  Halton,
+  Sobol,
+}
+
+impl SamplerKind {
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "independent" => Some(SamplerKind::Independent),
+      "stratified" => Some(SamplerKind::Stratified),
+      "halton" => Some(SamplerKind::Halton),
+      "sobol" => Some(SamplerKind::Sobol),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TonemapKind {
+  Linear,
+  Gamma(f32),
+  Debug,
+}
+
+impl TonemapKind {
+  pub const DEFAULT_GAMMA: f32 = 2.2;
+
+  /**
+   * `gamma` のパラメータは省略した場合 DEFAULT_GAMMA になる
+   */
+  pub fn from_name(name: &str, gamma: Option<f32>) -> Option<Self> {
+    match (name, gamma) {
+      ("linear", None) => Some(TonemapKind::Linear),
+      ("gamma", None) => Some(TonemapKind::Gamma(Self::DEFAULT_GAMMA)),
+      ("gamma", Some(gamma)) if gamma > 0.0 && gamma.is_finite() => Some(TonemapKind::Gamma(gamma)),
+      ("debug", None) => Some(TonemapKind::Debug),
+      _ => None,
+    }
+  }
+}
+
+struct CameraDescription {
+  // 水平方向の視野角 [deg]
+  fov: f32,
+  origin: Vector3,
+  target: Vector3,
+  up: Vector3,
+  // カメラがある媒質 (Scene::media のインデックス)
+  medium: Option<usize>,
+}
+
+/**
+ * マテリアルのパラメータ
+ *
+ * テクスチャを扱えないマテリアルには一様な値だけを渡せる
+ */
+enum TextureDescription<T> {
+  Constant(T),
+  Texture(Box<dyn Texture<T> + Send + Sync>),
+}
+
+impl<T> TextureDescription<T>
+where
+  T: Copy + Send + Sync + 'static,
+{
+  fn texture(self) -> Box<dyn Texture<T> + Send + Sync> {
+    match self {
+      TextureDescription::Constant(value) => Box::new(texture::Constant::new(value)),
+      TextureDescription::Texture(texture) => texture,
+    }
+  }
+
+  fn constant(self, d: &Directive, kind: &str) -> Result<T, Error> {
+    match self {
+      TextureDescription::Constant(value) => Ok(value),
+      TextureDescription::Texture(_) => Err(d.error(format!("{} does not support textures", kind))),
+    }
+  }
+}
+
+/**
+ * マテリアルの `emittance` (暗黙の完全拡散の面光源になる)
+ */
+type Emittance = TextureDescription<Vector3>;
+
+/**
+ * 物体の境界の内外の媒質 (Scene::media のインデックス)
+ */
+#[derive(Clone, Copy)]
+struct MediumDescription {
+  interior: Option<usize>,
+  exterior: Option<usize>,
+}
+
+enum Shape {
+  Obj {
+    // Scene::objs のインデックス
+    obj: usize,
+    material: Option<usize>,
+    override_material: Option<usize>,
+    medium: Option<MediumDescription>,
+    // Scene::normal_maps のインデックス
+    normal_map: Option<usize>,
+    // Scene::emitters のインデックス
+    emitter: Option<usize>,
+    matrix: Matrix4,
+  },
+  Sphere {
+    center: Vector3,
+    radius: f32,
+    material: usize,
+    medium: Option<MediumDescription>,
+    normal_map: Option<usize>,
+    emitter: Option<usize>,
+    matrix: Matrix4,
+  },
+}
+
+/**
+ * シーン記述ファイルから読み込んだシーン
+ *
+ * ```text
+ * film 800 800
+ * spp 100
+ * sampler sobol
+ * camera pinhole fov 39.3077 origin 278 273 -800 target 278 273 0 up 0 1 0
+ * material white lambertian albedo 0.75 0.75 0.75
+ * material glass ideal_refraction reflectance 1 1 1 ior 1.5
+ * material frosted rough_refraction reflectance 1 1 1 roughness 0.3 ior 1.5
+ * material brushed ggx reflectance 0.9 0.9 0.9 roughness 0.4 anisotropy 0.8
+ * material gold rough_conductor metal Au roughness 0.2
+ * material paint principled base_color 0.8 0.1 0.1 roughness 0.4 clearcoat 1
+ * obj models/simple/cbox.obj material white
+ * sphere center 178 100 178 radius 100 material glass
+ * environment sky zenith 0.3 0.5 1 horizon 1 1 1 ground 0.3 0.3 0.3
+ * transport explicit_light
+ * depth min 3 max 64
+ * photon count 100000 nearest 50 alpha 0.7
+ * tonemap gamma 2.2
+ * ```
+ *
+ * `environment` は `constant r g b`, `sky [zenith|horizon|ground r g b]`,
+ * `image <path> [scale s] [rotate deg]` のいずれか (省略した場合は黒)
+ * `image` は正距円筒図法の Radiance HDR (.hdr) または OpenEXR (.exr) 形式の画像
+ *
+ * `sampler` は `independent`, `stratified`, `halton`, `sobol` のいずれか (省略した場合は `independent`)
+ *
+ * `depth` はロシアンルーレットを始める反射回数 `min` と打ち切る反射回数 `max`
+ * (`max` を省略した場合は上限なし)
+ *
+ * `photon` は光子マッピングで1回 (SPPMでは1反復) に放出する光子の数 `count`、
+ * 半径を決める近傍の光子の数 `nearest`、SPPMの半径の縮小の割合 `alpha`
+ *
+ * `material` にその種類が使わないパラメータを指定するとエラーになる (`emittance` はどの種類にも指定できる)
+ *
+ * `material` の `albedo` (`reflectance`), `roughness` には一様な値の代わりに
+ * `image <path> [repeat|clamp|mirror]` (画像) や `checker <scale> <a> <b>` (市松模様) の
+ * テクスチャを指定できる (`lambertian`, `ggx`, `rough_refraction`, `principled` のみ、`principled` は `metallic` も)
+ *
+ * ```text
+ * material floor lambertian albedo checker 8 0.8 0.8 0.8 0.2 0.2 0.2
+ * material wood ggx reflectance image wood.png roughness image wood_roughness.png clamp
+ * ```
+ *
+ * `principled` は `base_color` (`albedo`), `metallic`, `roughness`, `specular`, `sheen`,
+ * `clearcoat`, `clearcoat_roughness`, `transmission`, `transmission_color`, `ior`, `anisotropy`
+ * を組み合わせたマテリアルで、省略したパラメータは
+ * `metallic 0 roughness 0.5 specular 0.5 sheen 0 clearcoat 0 clearcoat_roughness 0.3 transmission 0 ior 1.5`
+ *
+ * `mix` は `a` と `b` のマテリアルを `weight` (bの割合、テクスチャでマスクにできる) で混ぜ、
+ * `add` は `a` と `b` のBSDFを足し、`coated` は `base` のマテリアルを `roughness` (省略した場合は0.1) と
+ * `ior` (省略した場合は1.5) の誘電体で覆う
+ * 材料には定義済みのマテリアルの名前を使い、完全鏡面の `ideal_refraction` は組み合わせられない
+ * (材料の `emittance` は使われない)
+ *
+ * ```text
+ * material red lambertian albedo 0.8 0.1 0.1
+ * material gold ggx reflectance 1 0.8 0.4 roughness 0.3
+ * material rusty mix a gold b red weight image rust_mask.png
+ * material lacquer coated base red roughness 0.05 ior 1.5
+ * ```
+ *
+ * `ideal_conductor` (鏡面) と `rough_conductor` は複素屈折率のフレネル反射率による金属で、
+ * `metal` に `Au`, `Ag`, `Cu`, `Al`, `Cr` のいずれかを指定するか、RGBの `eta` と `k` を直接指定する
+ *
+ * `ggx`, `rough_conductor`, `principled` の `anisotropy` は [0, 1) の異方性で、接線方向に伸びたハイライトになる (省略した場合は0で等方的)
+ * 接線はOBJのテクスチャ座標 (球では経度の方向) から決まる
+ *
+ * `obj` の `material` はOBJにマテリアルがない面に使われ、`override` は全ての面に使われる
+ * 同じOBJファイルを複数回置いた場合はメッシュが共有される
+ *
+ * `obj` と `sphere` の `normal_map <path>` は接空間の法線マップ、`bump_map <path> <scale>` は
+ * 高さの画像と倍率によるバンプマップで、シェーディング法線を揺らす (OBJのマテリアルの法線マップより優先)
+ *
+ * `emitter` は名前を付けた面光源で、`obj` と `sphere` の `emitter <name>` で物体の表面から放射する
+ * `radiance` は放射輝度 (テクスチャも可)、`two_sided` は両面からの放射 (省略した場合は幾何的な法線の表側のみ)、
+ * `spot <angle> [blend b]` は法線から `angle` 度までを照らし縁の `b` の割合をぼかす配光、
+ * `profile <v0> <v1> ...` は法線から90度までを等間隔に区切った角度の放射輝度の倍率の表 (IESの配光のような表で、間は線形補間)
+ * マテリアルの `emittance <color>` は、そのマテリアルを使う物体を片面の完全拡散の光源にする
+ * (どのマテリアルにも指定でき、物体の `emitter` があればそちらを使う)
+ *
+ * ```text
+ * emitter panel radiance 10 10 10 two_sided
+ * emitter lamp radiance 50 45 40 spot 30 blend 0.2
+ * emitter sign radiance image sign.png profile 1 0.9 0.6 0.2 0
+ * sphere center 0 5 0 radius 0.5 material white emitter lamp
+ * ```
+ *
+ * `light` は面積を持たない光源で、`point` (点光源) は `position` と放射強度 `intensity`、
+ * `spot` (スポットライト) はさらに照らす先 `target` と中心からの角度 `angle` [deg]、縁をぼかす割合 `blend` (省略した場合は0)、
+ * `directional` (太陽のような平行光源) は光の進む向き `direction` と放射照度 `irradiance` で指定する
+ * 光源を明示的にサンプリングする `explicit_light`, `only_light`, `volumetric_path` でのみ描画される
+ *
+ * ```text
+ * light point position 0 5 0 intensity 100 100 100
+ * light spot position 0 5 5 target 0 0 0 intensity 500 450 400 angle 20 blend 0.3
+ * light directional direction -1 -2 -1 irradiance 3 3 3
+ * ```
+ *
+ * `obj` と `sphere` は末尾に `translate x y z`, `scale s` (`scale x y z`),
+ * `rotate x y z deg` を書いた順に適用する変換として指定できる
+ *
+ * `medium` は名前を付けた関与媒質で、`volumetric_path` でのみ描画される
+ *
+ * ```text
+ * medium fog homogeneous absorption 0.01 0.01 0.01 scattering 0.1 0.1 0.1 g 0.3
+ * medium smoke grid density smoke.txt min -1 0 -1 max 1 2 1 extinction 5 albedo 0.9 0.9 0.9 g 0
+ * sphere center 0 1 0 radius 1 material glass interior fog
+ * ```
+ *
+ * `homogeneous` は吸収係数 `absorption` と散乱係数 `scattering` が一様な媒質で、
+ * `grid` は箱 `min`-`max` に格子状の密度を与えた媒質 (消散係数は 密度 * `extinction`)
+ * 密度のファイルは格子の大きさ `nx ny nz` に続けて x, y, z の順に添字が速く変わる密度を並べたもの
+ * `g` はHenyey-Greensteinの位相関数の非対称性 (省略した場合は0で等方散乱)
+ *
+ * `obj` と `sphere` の `interior`, `exterior` は境界の内側と外側の媒質で、
+ * 省略した側は真空になる (どちらも省略した場合は媒質の境界にならない)
+ * 媒質だけを区切る見えない境界には `ideal_refraction` の `ior 1` を使う
+ * カメラがある媒質は `camera` の `medium` で指定する
+ */
+pub struct Scene {
+  pub width: usize,
+  pub height: usize,
+  pub spp: usize,
+  pub sampler: SamplerKind,
+  pub transport: TransportKind,
+  pub russian_roulette: RussianRoulette,
+  pub density_estimation: DensityEstimation,
+  pub tonemap: TonemapKind,
+  camera: CameraDescription,
+  materials: Vec<Box<dyn Material + Send + Sync>>,
+  // マテリアルの `emittance` による放射 (Scene::emitters のインデックス)
+  material_emitters: Vec<Option<usize>>,
+  fallback_material: Box<dyn Material + Send + Sync>,
+  emitters: Vec<AreaLight>,
+  delta_lights: Vec<DeltaLight>,
+  environment: Box<dyn Environment + Send + Sync>,
+  media: Vec<Box<dyn Medium + Send + Sync>>,
+  normal_maps: Vec<NormalMap>,
+  // 同じOBJファイルは一度だけ読み込み、メッシュを共有する
+  objs: Vec<loader::Obj>,
+  shapes: Vec<Shape>,
+}
+
+impl Scene {
+  pub fn load(path: &Path) -> Result<Scene, Error> {
+    let source = fs::read_to_string(path)
+      .map_err(|e| Error::new(format!("could not read `{}`: {}", path.display(), e)))?;
+    // OBJファイルのパスはシーン記述ファイルからの相対パス
+    let base = path.parent().unwrap_or(Path::new(""));
+    Scene::parse(&source, base)
+  }
+
+  pub fn parse(source: &str, base: &Path) -> Result<Scene, Error> {
+    let mut builder = Builder::new(base);
+    for mut directive in Directive::parse(source)? {
+      builder.directive(&mut directive)?;
+      directive.end()?;
+    }
+    builder.build()
+  }
+
+  pub fn film(&self) -> Film<Vector3> {
+    Film::new(Vector3::zero(), self.width, self.height)
+  }
+
+  pub fn aspect(&self) -> f32 {
+    self.width as f32 / self.height as f32
+  }
+
+  pub fn camera(&self) -> IdealPinhole {
+    let camera_matrix = Matrix4::look_at(self.camera.origin, self.camera.target, self.camera.up);
+    IdealPinhole::new(self.camera.fov * PI / 180.0, self.aspect(), camera_matrix)
+  }
+
+  pub fn objects(&self) -> Vec<Object<'_>> {
+    let mut uuid = UUID::new();
+    let mut objects = Vec::new();
+    for shape in &self.shapes {
+      match shape {
+        Shape::Obj {
+          obj,
+          material,
+          override_material,
+          medium,
+          normal_map,
+          emitter,
+          matrix,
+        } => {
+          let fallback = match *material {
+            Some(i) => self.material(i),
+            None => (&self.fallback_material, None),
+          };
+          let override_material = override_material.map(|i| self.material(i));
+          let normal_map = normal_map.map(|i| &self.normal_maps[i]);
+          let emitter = emitter.map(|i| &self.emitters[i]);
+          let instances = self.objs[*obj].instances(
+            fallback,
+            override_material,
+            normal_map,
+            emitter,
+            matrix,
+            &mut uuid,
+          );
+          objects.extend(
+            instances
+              .into_iter()
+              .map(|object| self.with_medium(object, medium)),
+          );
+        }
+        Shape::Sphere {
+          center,
+          radius,
+          material,
+          medium,
+          normal_map,
+          emitter,
+          matrix,
+        } => {
+          let sphere = Box::new(Sphere::new(*center, *radius, &mut uuid));
+          let (material, material_emitter) = self.material(*material);
+          let object = Object::new(sphere, matrix.clone(), material);
+          let object = match normal_map {
+            Some(i) => object.with_normal_map(&self.normal_maps[*i]),
+            None => object,
+          };
+          let object = match emitter.map(|i| &self.emitters[i]).or(material_emitter) {
+            Some(emitter) => object.with_area_light(emitter),
+            None => object,
+          };
+          objects.push(self.with_medium(object, medium));
+        }
+      }
+    }
+    objects
+  }
+
+  /**
+   * マテリアルと、その `emittance` による放射
+   */
+  fn material(&self, i: usize) -> (&Box<dyn Material + Send + Sync>, Option<&AreaLight>) {
+    (
+      &self.materials[i],
+      self.material_emitters[i].map(|j| &self.emitters[j]),
+    )
+  }
+
+  fn with_medium<'a>(
+    &'a self,
+    object: Object<'a>,
+    medium: &Option<MediumDescription>,
+  ) -> Object<'a> {
+    match *medium {
+      None => object,
+      Some(medium) => object.with_medium(MediumInterface {
+        interior: medium.interior.map(|i| &*self.media[i]),
+        exterior: medium.exterior.map(|i| &*self.media[i]),
+      }),
+    }
+  }
+
+  /**
+   * カメラがある媒質
+   */
+  pub fn camera_medium(&self) -> Option<&(dyn Medium + Send + Sync)> {
+    self.camera.medium.map(|i| &*self.media[i])
+  }
+
+  pub fn environment(&self) -> &(dyn Environment + Send + Sync) {
+    &*self.environment
+  }
+
+  /**
+   * 点光源, スポットライト, 平行光源
+   */
+  pub fn delta_lights(&self) -> &[DeltaLight] {
+    &self.delta_lights
+  }
+
+  pub fn structure(&self) -> BVH<'_> {
+    BVH::new(self.objects())
+  }
+}
+
+struct Builder {
+  base: PathBuf,
+  film: Option<(usize, usize)>,
+  spp: usize,
+  sampler: SamplerKind,
+  camera: Option<CameraDescription>,
+  materials: Vec<Box<dyn Material + Send + Sync>>,
+  material_names: HashMap<String, usize>,
+  material_sources: HashMap<String, Directive>,
+  material_emitters: Vec<Option<usize>>,
+  emitters: Vec<AreaLight>,
+  emitter_names: HashMap<String, usize>,
+  delta_lights: Vec<DeltaLight>,
+  objs: Vec<loader::Obj>,
+  obj_paths: HashMap<PathBuf, usize>,
+  shapes: Vec<Shape>,
+  environment: Option<Box<dyn Environment + Send + Sync>>,
+  media: Vec<Box<dyn Medium + Send + Sync>>,
+  medium_names: HashMap<String, usize>,
+  normal_maps: Vec<NormalMap>,
+  transport: TransportKind,
+  russian_roulette: RussianRoulette,
+  density_estimation: DensityEstimation,
+  tonemap: TonemapKind,
+}
+
+impl Builder {
+  fn new(base: &Path) -> Self {
+    Builder {
+      base: base.to_path_buf(),
+      film: None,
+      spp: 1,
+      sampler: SamplerKind::Independent,
+      camera: None,
+      materials: Vec::new(),
+      material_names: HashMap::new(),
+      material_sources: HashMap::new(),
+      material_emitters: Vec::new(),
+      emitters: Vec::new(),
+      emitter_names: HashMap::new(),
+      delta_lights: Vec::new(),
+      objs: Vec::new(),
+      obj_paths: HashMap::new(),
+      shapes: Vec::new(),
+      environment: None,
+      media: Vec::new(),
+      medium_names: HashMap::new(),
+      normal_maps: Vec::new(),
+      transport: TransportKind::ExplicitLight,
+      russian_roulette: RussianRoulette::default(),
+      density_estimation: DensityEstimation::default(),
+      tonemap: TonemapKind::Gamma(TonemapKind::DEFAULT_GAMMA),
+    }
+  }
+
+  fn build(self) -> Result<Scene, Error> {
+    let (width, height) = self
+      .film
+      .ok_or_else(|| Error::new("missing `film <width> <height>`"))?;
+    let camera = self.camera.ok_or_else(|| Error::new("missing `camera`"))?;
+    Ok(Scene {
+      width: width,
+      height: height,
+      spp: self.spp,
+      sampler: self.sampler,
+      transport: self.transport,
+      russian_roulette: self.russian_roulette,
+      density_estimation: self.density_estimation,
+      tonemap: self.tonemap,
+      camera: camera,
+      materials: self.materials,
+      material_emitters: self.material_emitters,
+      fallback_material: Box::new(material::Lambertian {
+        albedo: Box::new(texture::Constant::new(Vector3::fill(0.75))),
+      }),
+      emitters: self.emitters,
+      delta_lights: self.delta_lights,
+      environment: self
+        .environment
+        .unwrap_or_else(|| Box::new(environment::Constant::black())),
+      media: self.media,
+      normal_maps: self.normal_maps,
+      objs: self.objs,
+      shapes: self.shapes,
+    })
+  }
+
+  fn directive(&mut self, d: &mut Directive) -> Result<(), Error> {
+    match d.keyword.as_str() {
+      "film" => {
+        let width = d.usize("width")?;
+        let height = d.usize("height")?;
+        self.film = Some((width, height));
+      }
+      "spp" => self.spp = d.usize("sample count")?,
+      "sampler" => self.sampler = Self::sampler(d)?,
+      "camera" => self.camera = Some(self.camera(d)?),
+      "material" => self.material(d)?,
+      "emitter" => self.emitter(d)?,
+      "light" => self.delta_lights.push(Self::delta_light(d)?),
+      "medium" => self.medium(d)?,
+      "obj" => self.obj(d)?,
+      "sphere" => self.sphere(d)?,
+      "environment" => {
+        if self.environment.is_some() {
+          return Err(d.error("environment is already defined"));
+        }
+        self.environment = Some(self.environment(d)?)
+      }
+      "transport" => self.transport = Self::transport(d)?,
+      "depth" => self.russian_roulette = Self::depth(d)?,
+      "photon" => self.density_estimation = Self::photon(d)?,
+      "tonemap" => self.tonemap = Self::tonemap(d)?,
+      _ => return Err(d.error(format!("unknown directive `{}`", d.keyword))),
+    }
+    Ok(())
+  }
+
+  fn camera(&self, d: &mut Directive) -> Result<CameraDescription, Error> {
+    let kind = d.word("camera type")?;
+    if kind != "pinhole" {
+      return Err(d.unknown("camera type", &kind));
+    }
+    let mut fov = None;
+    let mut origin = None;
+    let mut target = None;
+    let mut up = Vector3::new(0.0, 1.0, 0.0);
+    let mut medium = None;
+    while let Some(key) = d.next_word() {
+      match key.as_str() {
+        "fov" => fov = Some(d.positive_float("field of view [deg]")?),
+        "medium" => medium = Some(self.medium_ref(d)?),
+        "origin" => origin = Some(d.vector("origin")?),
+        "target" => target = Some(d.vector("target")?),
+        "up" => up = d.vector("up vector")?,
+        _ => return Err(d.unknown("parameter", &key)),
+      }
+    }
+    let origin = origin.ok_or_else(|| d.error("camera requires `origin <x y z>`"))?;
+    let target = target.ok_or_else(|| d.error("camera requires `target <x y z>`"))?;
+    if (target - origin).sqr_norm() == 0.0 {
+      return Err(d.error("camera origin and target must differ"));
+    }
+    Ok(CameraDescription {
+      fov: fov.ok_or_else(|| d.error("camera requires `fov <deg>`"))?,
+      origin: origin,
+      target: target,
+      up: up,
+      medium: medium,
+    })
+  }
+
+  fn material(&mut self, d: &mut Directive) -> Result<(), Error> {
+    let name = d.word("material name")?;
+    if self.material_names.contains_key(&name) {
+      return Err(d.error(format!("material `{}` is already defined", name)));
+    }
+    // 組み合わせのマテリアルから参照されたときに作り直すために名前より後ろを覚えておく
+    let source = d.clone();
+    let (material, emittance) = self.build_material(d)?;
+    self
+      .material_names
+      .insert(name.clone(), self.materials.len());
+    self.material_sources.insert(name, source);
+    self.materials.push(material);
+    // `emittance` はマテリアルを使う物体の片面の完全拡散の光源にする
+    let emitter = emittance.map(|emittance| {
+      self.emitters.push(AreaLight::diffuse(emittance.texture()));
+      self.emitters.len() - 1
+    });
+    self.material_emitters.push(emitter);
+    Ok(())
+  }
+
+  /**
+   * マテリアルの種類とパラメータを読み込んでマテリアルと `emittance` の放射輝度を作る
+   */
+  fn build_material(
+    &self,
+    d: &mut Directive,
+  ) -> Result<(Box<dyn Material + Send + Sync>, Option<Emittance>), Error> {
+    let kind = d.word("material type")?;
+    let mut color = None;
+    let mut emittance = None;
+    let mut roughness = None;
+    let mut ior = None;
+    let mut anisotropy = None;
+    let mut metallic = None;
+    let mut specular = 0.5;
+    let mut sheen = 0.0;
+    let mut clearcoat = 0.0;
+    let mut clearcoat_roughness = 0.3;
+    let mut transmission = 0.0;
+    let mut transmission_color = Vector3::fill(1.0);
+    let mut a = None;
+    let mut b = None;
+    let mut weight = None;
+    let mut base = None;
+    let mut metal = None;
+    let mut eta = None;
+    let mut k = None;
+    while let Some(key) = d.next_word() {
+      if let Some(kinds) = Self::material_kinds(&key) {
+        if !kinds.contains(&kind.as_str()) {
+          return Err(d.error(format!("{} does not support `{}`", kind, key)));
+        }
+      }
+      match key.as_str() {
+        "albedo" | "reflectance" | "base_color" => color = Some(self.color_texture(d, &key)?),
+        "emittance" => emittance = Some(self.color_texture(d, "emittance")?),
+        "roughness" => roughness = Some(self.float_texture(d, "roughness")?),
+        "ior" => ior = Some(d.positive_float("index of refraction")?),
+        "anisotropy" => anisotropy = Some(d.float("anisotropy")?),
+        "metallic" => metallic = Some(self.unit_texture(d, "metallic")?),
+        "specular" => specular = d.unit_float("specular")?,
+        "sheen" => sheen = d.unit_float("sheen")?,
+        "clearcoat" => clearcoat = d.unit_float("clearcoat")?,
+        "clearcoat_roughness" => clearcoat_roughness = d.unit_float("clearcoat roughness")?,
+        "transmission" => transmission = d.unit_float("transmission")?,
+        "transmission_color" => transmission_color = d.vector("transmission color")?,
+        "a" => a = Some(self.material_component(d)?),
+        "b" => b = Some(self.material_component(d)?),
+        "weight" => weight = Some(self.unit_texture(d, "weight")?),
+        "base" => base = Some(self.material_component(d)?),
+        "metal" => {
+          let name = d.word("metal name")?;
+          metal =
+            Some(material::ComplexIor::preset(&name).ok_or_else(|| d.unknown("metal", &name))?)
+        }
+        "eta" => eta = Some(d.vector("index of refraction")?),
+        "k" => k = Some(d.vector("extinction coefficient")?),
+        _ => return Err(d.unknown("parameter", &key)),
+      }
+    }
+    let anisotropy = anisotropy.unwrap_or(0.0);
+    if !(0.0..1.0).contains(&anisotropy) {
+      return Err(d.error(format!(
+        "anisotropy must be in [0, 1) but found {}",
+        anisotropy
+      )));
+    }
+    let conductor = match (metal, eta, k) {
+      (Some(metal), None, None) => Some(metal),
+      (None, Some(eta), Some(k)) => Some(material::ComplexIor { eta: eta, k: k }),
+      (None, None, None) => None,
+      _ => {
+        return Err(
+          d.error("conductor requires either `metal <name>` or both `eta <r g b>` and `k <r g b>`"),
+        )
+      }
+    };
+    let conductor = || {
+      conductor.ok_or_else(|| {
+        d.error(format!(
+          "{} requires `metal <name>` or `eta <r g b> k <r g b>`",
+          kind
+        ))
+      })
+    };
+    let color = color.unwrap_or_else(|| TextureDescription::Constant(Vector3::fill(0.75)));
+    let material: Box<dyn Material + Send + Sync> = match kind.as_str() {
+      "lambertian" => Box::new(material::Lambertian {
+        albedo: color.texture(),
+      }),
+      "ggx" => Box::new(material::GGX {
+        reflectance: color.texture(),
+        roughness: roughness
+          .ok_or_else(|| d.error("ggx requires `roughness <value>`"))?
+          .texture(),
+        anisotropy: anisotropy,
+      }),
+      "blinn" => Box::new(material::Blinn {
+        reflectance: color.constant(d, "blinn")?,
+        roughness: roughness
+          .ok_or_else(|| d.error("blinn requires `roughness <value>`"))?
+          .constant(d, "blinn")?,
+      }),
+      "ideal_refraction" => Box::new(material::IdealRefraction {
+        reflectance: color.constant(d, "ideal_refraction")?,
+        ior: ior.ok_or_else(|| d.error("ideal_refraction requires `ior <value>`"))?,
+      }),
+      "rough_refraction" => Box::new(material::RoughRefraction {
+        reflectance: color.texture(),
+        roughness: roughness
+          .ok_or_else(|| d.error("rough_refraction requires `roughness <value>`"))?
+          .texture(),
+        ior: ior.ok_or_else(|| d.error("rough_refraction requires `ior <value>`"))?,
+      }),
+      "ideal_conductor" => Box::new(material::IdealConductor { ior: conductor()? }),
+      "rough_conductor" => Box::new(material::RoughConductor {
+        ior: conductor()?,
+        roughness: roughness
+          .ok_or_else(|| d.error("rough_conductor requires `roughness <value>`"))?
+          .texture(),
+        anisotropy: anisotropy,
+      }),
+      "principled" => Box::new(material::Principled {
+        base_color: color.texture(),
+        metallic: metallic
+          .unwrap_or(TextureDescription::Constant(0.0))
+          .texture(),
+        roughness: roughness
+          .unwrap_or(TextureDescription::Constant(0.5))
+          .texture(),
+        anisotropy: anisotropy,
+        specular: specular,
+        sheen: sheen,
+        clearcoat: clearcoat,
+        clearcoat_roughness: clearcoat_roughness,
+        transmission: transmission,
+        transmission_color: transmission_color,
+        ior: ior.unwrap_or(1.5),
+      }),
+      "mix" => Box::new(material::Mix {
+        a: a.ok_or_else(|| d.error("mix requires `a <material>`"))?,
+        b: b.ok_or_else(|| d.error("mix requires `b <material>`"))?,
+        weight: weight
+          .ok_or_else(|| d.error("mix requires `weight <value>`"))?
+          .texture(),
+      }),
+      "add" => Box::new(material::Add {
+        a: a.ok_or_else(|| d.error("add requires `a <material>`"))?,
+        b: b.ok_or_else(|| d.error("add requires `b <material>`"))?,
+      }),
+      "coated" => Box::new(material::Coated {
+        base: base.ok_or_else(|| d.error("coated requires `base <material>`"))?,
+        roughness: roughness
+          .unwrap_or(TextureDescription::Constant(0.1))
+          .texture(),
+        ior: ior.unwrap_or(1.5),
+      }),
+      _ => return Err(d.unknown("material type", &kind)),
+    };
+    Ok((material, emittance))
+  }
+
+  /**
+   * 一部のマテリアルだけが受け付けるパラメータ -> 受け付けるマテリアルの種類
+   *
+   * `emittance` はどのマテリアルにも指定できる
+   */
+  fn material_kinds(key: &str) -> Option<&'static [&'static str]> {
+    match key {
+      "albedo" | "reflectance" | "base_color" => Some(&[
+        "lambertian",
+        "ggx",
+        "blinn",
+        "ideal_refraction",
+        "rough_refraction",
+        "principled",
+      ]),
+      "roughness" => Some(&[
+        "ggx",
+        "blinn",
+        "rough_refraction",
+        "rough_conductor",
+        "principled",
+        "coated",
+      ]),
+      "ior" => Some(&[
+        "ideal_refraction",
+        "rough_refraction",
+        "principled",
+        "coated",
+      ]),
+      "anisotropy" => Some(&["ggx", "rough_conductor", "principled"]),
+      "metal" | "eta" | "k" => Some(&["ideal_conductor", "rough_conductor"]),
+      "metallic"
+      | "specular"
+      | "sheen"
+      | "clearcoat"
+      | "clearcoat_roughness"
+      | "transmission"
+      | "transmission_color" => Some(&["principled"]),
+      "a" | "b" => Some(&["mix", "add"]),
+      "weight" => Some(&["mix"]),
+      "base" => Some(&["coated"]),
+      _ => None,
+    }
+  }
+
+  /**
+   * 組み合わせのマテリアルの材料になる定義済みのマテリアル
+   *
+   * 物体と共有できないので、定義を読み直して新しく作る (材料の `emittance` は使われない)
+   */
+  fn material_component(
+    &self,
+    d: &mut Directive,
+  ) -> Result<Box<dyn Material + Send + Sync>, Error> {
+    let name = d.word("material name")?;
+    let mut source = self
+      .material_sources
+      .get(&name)
+      .cloned()
+      .ok_or_else(|| d.error(format!("undefined material `{}`", name)))?;
+    let (material, _) = self.build_material(&mut source)?;
+    if material.is_delta() {
+      return Err(d.error(format!(
+        "material `{}` is perfectly specular and cannot be combined",
+        name
+      )));
+    }
+    Ok(material)
+  }
+
+  /**
+   * 色のテクスチャ (`r g b`, `image <path> [wrap]`, `checker <scale> <r g b> <r g b>`)
+   */
+  fn color_texture(
+    &self,
+    d: &mut Directive,
+    what: &str,
+  ) -> Result<TextureDescription<Vector3>, Error> {
+    if let Some(x) = d.maybe_float() {
+      let y = d.float(what)?;
+      let z = d.float(what)?;
+      return Ok(TextureDescription::Constant(Vector3::new(x, y, z)));
+    }
+    let kind = d.word(what)?;
+    match kind.as_str() {
+      "image" => Ok(TextureDescription::Texture(Box::new(
+        self.image_texture(d, true)?,
+      ))),
+      "checker" => Ok(TextureDescription::Texture(Box::new(
+        texture::Checkerboard {
+          scale: d.positive_float("checker scale")?,
+          a: d.vector(what)?,
+          b: d.vector(what)?,
+        },
+      ))),
+      _ => Err(d.unknown("texture type", &kind)),
+    }
+  }
+
+  /**
+   * 数値のテクスチャ (`value`, `image <path> [wrap]`, `checker <scale> <a> <b>`)
+   */
+  fn float_texture(&self, d: &mut Directive, what: &str) -> Result<TextureDescription<f32>, Error> {
+    if let Some(value) = d.maybe_float() {
+      if value > 0.0 && value.is_finite() {
+        return Ok(TextureDescription::Constant(value));
+      }
+      return Err(d.error(format!("{} must be positive but found {}", what, value)));
+    }
+    let kind = d.word(what)?;
+    match kind.as_str() {
+      "image" => Ok(TextureDescription::Texture(Box::new(
+        self.image_texture(d, false)?,
+      ))),
+      "checker" => Ok(TextureDescription::Texture(Box::new(
+        texture::Checkerboard {
+          scale: d.positive_float("checker scale")?,
+          a: d.positive_float(what)?,
+          b: d.positive_float(what)?,
+        },
+      ))),
+      _ => Err(d.unknown("texture type", &kind)),
+    }
+  }
+
+  /**
+   * [0, 1] の数値のテクスチャ (`value` かテクスチャ)
+   */
+  fn unit_texture(&self, d: &mut Directive, what: &str) -> Result<TextureDescription<f32>, Error> {
+    match d.maybe_float() {
+      Some(value) if (0.0..=1.0).contains(&value) => Ok(TextureDescription::Constant(value)),
+      Some(value) => Err(d.error(format!("{} must be in [0, 1] but found {}", what, value))),
+      None => self.float_texture(d, what),
+    }
+  }
+
+  fn image_texture(&self, d: &mut Directive, srgb: bool) -> Result<texture::ImageTexture, Error> {
+    let file = d.word("texture path")?;
+    let wrap = d
+      .maybe_word(&["repeat", "clamp", "mirror"])
+      .and_then(|name| texture::Wrap::from_name(&name))
+      .unwrap_or(texture::Wrap::Repeat);
+    let path = self.base.join(&file);
+    texture::ImageTexture::load(&path, wrap, srgb)
+      .map_err(|e| d.error(format!("failed to load `{}`: {}", path.display(), e)))
+  }
+
+  fn material_ref(&self, d: &mut Directive) -> Result<usize, Error> {
+    let name = d.word("material name")?;
+    self
+      .material_names
+      .get(&name)
+      .cloned()
+      .ok_or_else(|| d.error(format!("undefined material `{}`", name)))
+  }
+
+  fn emitter(&mut self, d: &mut Directive) -> Result<(), Error> {
+    let name = d.word("emitter name")?;
+    if self.emitter_names.contains_key(&name) {
+      return Err(d.error(format!("emitter `{}` is already defined", name)));
+    }
+    let mut radiance = None;
+    let mut two_sided = false;
+    let mut profile = Profile::Diffuse;
+    while let Some(key) = d.next_word() {
+      match key.as_str() {
+        "radiance" => radiance = Some(self.color_texture(d, "radiance")?),
+        "two_sided" => two_sided = true,
+        "spot" => {
+          let angle = d.positive_float("spot angle [deg]")?;
+          if angle > 90.0 {
+            return Err(d.error(format!("spot angle must be at most 90 but found {}", angle)));
+          }
+          let blend = match d.maybe_word(&["blend"]) {
+            Some(_) => d.unit_float("spot blend")?,
+            None => 0.0,
+          };
+          profile = Profile::Spot {
+            cos_total: (angle * PI / 180.0).cos(),
+            cos_falloff: (angle * (1.0 - blend) * PI / 180.0).cos(),
+          };
+        }
+        "profile" => {
+          let mut table = Vec::new();
+          while let Some(value) = d.maybe_float() {
+            table.push(value);
+          }
+          if table.len() < 2 {
+            return Err(d.error("profile requires at least 2 values from 0 to 90 degrees"));
+          }
+          if table.iter().any(|&v| v < 0.0 || !v.is_finite()) || table.iter().all(|&v| v == 0.0) {
+            return Err(d.error("profile values must not be negative or all zero"));
+          }
+          profile = Profile::Table(table);
+        }
+        _ => return Err(d.unknown("parameter", &key)),
+      }
+    }
+    let radiance = radiance.ok_or_else(|| d.error("emitter requires `radiance <color>`"))?;
+    self.emitter_names.insert(name, self.emitters.len());
+    self
+      .emitters
+      .push(AreaLight::new(radiance.texture(), two_sided, profile));
+    Ok(())
+  }
+
+  fn emitter_ref(&self, d: &mut Directive) -> Result<usize, Error> {
+    let name = d.word("emitter name")?;
+    self
+      .emitter_names
+      .get(&name)
+      .cloned()
+      .ok_or_else(|| d.error(format!("undefined emitter `{}`", name)))
+  }
+
+  fn delta_light(d: &mut Directive) -> Result<DeltaLight, Error> {
+    let kind = d.word("light type")?;
+    let keys: &[&str] = match kind.as_str() {
+      "point" => &["position", "intensity"],
+      "spot" => &["position", "target", "intensity", "angle", "blend"],
+      "directional" => &["direction", "irradiance"],
+      _ => return Err(d.unknown("light type", &kind)),
+    };
+    let mut position = None;
+    let mut target = None;
+    let mut direction = None;
+    let mut power = None;
+    let mut angle = None;
+    let mut blend = 0.0;
+    while let Some(key) = d.next_word() {
+      if !keys.contains(&key.as_str()) {
+        return Err(d.unknown("parameter", &key));
+      }
+      match key.as_str() {
+        "position" => position = Some(d.vector("position")?),
+        "target" => target = Some(d.vector("target")?),
+        "direction" => direction = Some(d.vector("direction")?),
+        "intensity" => power = Some(d.vector("intensity")?),
+        "irradiance" => power = Some(d.vector("irradiance")?),
+        "angle" => angle = Some(d.positive_float("spot angle [deg]")?),
+        "blend" => blend = d.unit_float("spot blend")?,
+        _ => unreachable!(),
+      }
+    }
+    let power_key = if kind == "directional" {
+      "irradiance"
+    } else {
+      "intensity"
+    };
+    let power = power.ok_or_else(|| d.error(format!("{} light requires `{}`", kind, power_key)))?;
+    let position =
+      || position.ok_or_else(|| d.error(format!("{} light requires `position`", kind)));
+    let light = match kind.as_str() {
+      "point" => DeltaLight::Point {
+        position: position()?,
+        intensity: power,
+      },
+      "spot" => {
+        let position = position()?;
+        let target = target.ok_or_else(|| d.error("spot light requires `target <x y z>`"))?;
+        if (target - position).sqr_norm() == 0.0 {
+          return Err(d.error("spot light position and target must differ"));
+        }
+        let angle = angle.ok_or_else(|| d.error("spot light requires `angle <deg>`"))?;
+        if angle > 180.0 {
+          return Err(d.error(format!(
+            "spot angle must be at most 180 but found {}",
+            angle
+          )));
+        }
+        DeltaLight::Spot {
+          position: position,
+          direction: (target - position).normalize(),
+          intensity: power,
+          cos_total: (angle * PI / 180.0).cos(),
+          cos_falloff: (angle * (1.0 - blend) * PI / 180.0).cos(),
+        }
+      }
+      _ => {
+        let direction =
+          direction.ok_or_else(|| d.error("directional light requires `direction <x y z>`"))?;
+        if direction.sqr_norm() == 0.0 {
+          return Err(d.error("light direction must not be zero"));
+        }
+        DeltaLight::Directional {
+          direction: direction.normalize(),
+          irradiance: power,
+        }
+      }
+    };
+    Ok(light)
+  }
+
+  fn medium(&mut self, d: &mut Directive) -> Result<(), Error> {
+    let name = d.word("medium name")?;
+    if self.medium_names.contains_key(&name) {
+      return Err(d.error(format!("medium `{}` is already defined", name)));
+    }
+    let kind = d.word("medium type")?;
+    let mut absorption = Vector3::zero();
+    let mut scattering = Vector3::zero();
+    let mut density = None;
+    let mut min = None;
+    let mut max = None;
+    let mut extinction = None;
+    let mut albedo = Vector3::fill(1.0);
+    let mut g = 0.0;
+    while let Some(key) = d.next_word() {
+      match key.as_str() {
+        "absorption" => absorption = d.vector("absorption coefficient")?,
+        "scattering" => scattering = d.vector("scattering coefficient")?,
+        "density" => density = Some(d.word("density file path")?),
+        "min" => min = Some(d.vector("minimum corner")?),
+        "max" => max = Some(d.vector("maximum corner")?),
+        "extinction" => extinction = Some(d.positive_float("extinction coefficient")?),
+        "albedo" => albedo = d.vector("albedo")?,
+        "g" => g = d.float("asymmetry")?,
+        _ => return Err(d.unknown("parameter", &key)),
+      }
+    }
+    if g <= -1.0 || g >= 1.0 {
+      return Err(d.error(format!("`g` must be in (-1, 1) but found {}", g)));
+    }
+    let phase = HenyeyGreenstein { g: g };
+    let medium: Box<dyn Medium + Send + Sync> = match kind.as_str() {
+      "homogeneous" => {
+        let negative = |v: Vector3| v.x < 0.0 || v.y < 0.0 || v.z < 0.0;
+        if negative(absorption) || negative(scattering) {
+          return Err(d.error("`absorption` and `scattering` must not be negative"));
+        }
+        Box::new(medium::Homogeneous {
+          absorption: absorption,
+          scattering: scattering,
+          phase: phase,
+        })
+      }
+      "grid" => {
+        let file = density.ok_or_else(|| d.error("grid requires `density <path>`"))?;
+        let min = min.ok_or_else(|| d.error("grid requires `min <x y z>`"))?;
+        let max = max.ok_or_else(|| d.error("grid requires `max <x y z>`"))?;
+        if max.x <= min.x || max.y <= min.y || max.z <= min.z {
+          return Err(d.error("grid `max` must be greater than `min`"));
+        }
+        let extinction = extinction.ok_or_else(|| d.error("grid requires `extinction <value>`"))?;
+        let path = self.base.join(&file);
+        let grid = medium::Grid::load(&path, min, max, extinction, albedo, phase)
+          .map_err(|e| d.error(format!("failed to load `{}`: {}", path.display(), e)))?;
+        Box::new(grid)
+      }
+      _ => return Err(d.unknown("medium type", &kind)),
+    };
+    self.medium_names.insert(name, self.media.len());
+    self.media.push(medium);
+    Ok(())
+  }
+
+  fn medium_ref(&self, d: &mut Directive) -> Result<usize, Error> {
+    let name = d.word("medium name")?;
+    self
+      .medium_names
+      .get(&name)
+      .cloned()
+      .ok_or_else(|| d.error(format!("undefined medium `{}`", name)))
+  }
+
+  /**
+   * 物体の `interior` と `exterior` の指定を読み込む
+   *
+   * 媒質のキーワードでなければfalseを返す
+   */
+  fn medium_interface(
+    &self,
+    d: &mut Directive,
+    key: &str,
+    medium: &mut Option<MediumDescription>,
+  ) -> Result<bool, Error> {
+    let mut description = medium.unwrap_or(MediumDescription {
+      interior: None,
+      exterior: None,
+    });
+    match key {
+      "interior" => description.interior = Some(self.medium_ref(d)?),
+      "exterior" => description.exterior = Some(self.medium_ref(d)?),
+      _ => return Ok(false),
+    }
+    *medium = Some(description);
+    Ok(true)
+  }
+
+  /**
+   * 法線マップ (`normal_map <path>`) とバンプマップ (`bump_map <path> <scale>`) を読み込む
+   *
+   * 法線マップのキーワードでなければfalseを返す
+   */
+  fn normal_map(
+    &mut self,
+    d: &mut Directive,
+    key: &str,
+    normal_map: &mut Option<usize>,
+  ) -> Result<bool, Error> {
+    if key != "normal_map" && key != "bump_map" {
+      return Ok(false);
+    }
+    let file = d.word("texture path")?;
+    let path = self.base.join(&file);
+    let texture = texture::ImageTexture::load(&path, texture::Wrap::Repeat, false)
+      .map_err(|e| d.error(format!("failed to load `{}`: {}", path.display(), e)))?;
+    let map = if key == "normal_map" {
+      NormalMap::Normal(texture)
+    } else {
+      NormalMap::Bump {
+        height: texture,
+        scale: d.float("bump scale")?,
+      }
+    };
+    *normal_map = Some(self.normal_maps.len());
+    self.normal_maps.push(map);
+    Ok(true)
+  }
+
+  /**
+   * 変換の指定を読み込んで行列に適用する
+   *
+   * 変換のキーワードでなければfalseを返す
+   */
+  fn transform(d: &mut Directive, key: &str, matrix: &mut Matrix4) -> Result<bool, Error> {
+    let m = match key {
+      "translate" => Matrix4::translate(d.vector("translation")?),
+      "scale" => {
+        let x = d.positive_float("scale")?;
+        match d.maybe_float() {
+          None => Matrix4::scale(Vector3::fill(x)),
+          Some(y) => Matrix4::scale(Vector3::new(x, y, d.positive_float("scale")?)),
+        }
+      }
+      "rotate" => {
+        let axis = d.vector("rotation axis")?;
+        if axis.sqr_norm() == 0.0 {
+          return Err(d.error("rotation axis must not be zero"));
+        }
+        let angle = d.float("rotation angle [deg]")?;
+        Matrix4::axis_angle(axis.normalize(), angle * PI / 180.0)
+      }
+      _ => return Ok(false),
+    };
+    *matrix = &m * &*matrix;
+    Ok(true)
+  }
+
+  fn obj(&mut self, d: &mut Directive) -> Result<(), Error> {
+    let file = d.word("file path")?;
+    let path = self.base.join(&file);
+    let obj = match self.obj_paths.get(&path) {
+      Some(&i) => i,
+      None => {
+        let obj = loader::Obj::load(&path)
+          .map_err(|e| d.error(format!("failed to load `{}`: {}", path.display(), e)))?;
+        self.objs.push(obj);
+        self.obj_paths.insert(path, self.objs.len() - 1);
+        self.objs.len() - 1
+      }
+    };
+    let mut material = None;
+    let mut override_material = None;
+    let mut medium = None;
+    let mut normal_map = None;
+    let mut emitter = None;
+    let mut matrix = Matrix4::unit();
+    while let Some(key) = d.next_word() {
+      if Self::transform(d, &key, &mut matrix)?
+        || self.medium_interface(d, &key, &mut medium)?
+        || self.normal_map(d, &key, &mut normal_map)?
+      {
+        continue;
+      }
+      match key.as_str() {
+        "material" => material = Some(self.material_ref(d)?),
+        "override" => override_material = Some(self.material_ref(d)?),
+        "emitter" => emitter = Some(self.emitter_ref(d)?),
+        _ => return Err(d.unknown("parameter", &key)),
+      }
+    }
+    self.shapes.push(Shape::Obj {
+      obj: obj,
+      material: material,
+      override_material: override_material,
+      medium: medium,
+      normal_map: normal_map,
+      emitter: emitter,
+      matrix: matrix,
+    });
+    Ok(())
+  }
+
+  fn sphere(&mut self, d: &mut Directive) -> Result<(), Error> {
+    let mut center = Vector3::zero();
+    let mut radius = None;
+    let mut material = None;
+    let mut medium = None;
+    let mut normal_map = None;
+    let mut emitter = None;
+    let mut matrix = Matrix4::unit();
+    while let Some(key) = d.next_word() {
+      if Self::transform(d, &key, &mut matrix)?
+        || self.medium_interface(d, &key, &mut medium)?
+        || self.normal_map(d, &key, &mut normal_map)?
+      {
+        continue;
+      }
+      match key.as_str() {
+        "center" => center = d.vector("center")?,
+        "radius" => radius = Some(d.positive_float("radius")?),
+        "material" => material = Some(self.material_ref(d)?),
+        "emitter" => emitter = Some(self.emitter_ref(d)?),
+        _ => return Err(d.unknown("parameter", &key)),
+      }
+    }
+    self.shapes.push(Shape::Sphere {
+      center: center,
+      radius: radius.ok_or_else(|| d.error("sphere requires `radius <value>`"))?,
+      material: material.ok_or_else(|| d.error("sphere requires `material <name>`"))?,
+      medium: medium,
+      normal_map: normal_map,
+      emitter: emitter,
+      matrix: matrix,
+    });
+    Ok(())
+  }
+
+  fn environment(&self, d: &mut Directive) -> Result<Box<dyn Environment + Send + Sync>, Error> {
+    let kind = d.word("environment type")?;
+    match kind.as_str() {
+      "constant" => Ok(Box::new(environment::Constant {
+        radiance: d.vector("radiance")?,
+      })),
+      "sky" => {
+        let mut sky = environment::Sky::default();
+        while let Some(key) = d.next_word() {
+          match key.as_str() {
+            "zenith" => sky.zenith = d.vector("zenith color")?,
+            "horizon" => sky.horizon = d.vector("horizon color")?,
+            "ground" => sky.ground = d.vector("ground color")?,
+            _ => return Err(d.unknown("parameter", &key)),
+          }
+        }
+        Ok(Box::new(sky))
+      }
+      "image" => {
+        let file = d.word("image path")?;
+        let path = self.base.join(&file);
+        let mut scale = 1.0;
+        let mut rotation = 0.0;
+        while let Some(key) = d.next_word() {
+          match key.as_str() {
+            "scale" => scale = d.positive_float("scale")?,
+            "rotate" => rotation = d.float("rotation angle [deg]")? * PI / 180.0,
+            _ => return Err(d.unknown("parameter", &key)),
+          }
+        }
+        let image = environment::Equirectangular::load(&path, scale, rotation)
+          .map_err(|e| d.error(format!("failed to load `{}`: {}", path.display(), e)))?;
+        Ok(Box::new(image))
+      }
+      _ => Err(d.unknown("environment type", &kind)),
+    }
+  }
+
+  fn sampler(d: &mut Directive) -> Result<SamplerKind, Error> {
+    let kind = d.word("sampler")?;
+    SamplerKind::from_name(&kind).ok_or_else(|| d.unknown("sampler", &kind))
+  }
+
+  fn transport(d: &mut Directive) -> Result<TransportKind, Error> {
+    let kind = d.word("light transport")?;
+    TransportKind::from_name(&kind).ok_or_else(|| d.unknown("light transport", &kind))
+  }
+
+  fn depth(d: &mut Directive) -> Result<RussianRoulette, Error> {
+    let mut russian_roulette = RussianRoulette::default();
+    while let Some(key) = d.next_word() {
+      match key.as_str() {
+        "min" => russian_roulette.min_depth = d.unsigned("minimum depth")?,
+        "max" => russian_roulette.max_depth = Some(d.usize("maximum depth")?),
+        _ => return Err(d.unknown("parameter", &key)),
+      }
+    }
+    if let Some(max) = russian_roulette.max_depth {
+      if max < russian_roulette.min_depth {
+        return Err(d.error("`max` depth must not be less than `min` depth"));
+      }
+    }
+    Ok(russian_roulette)
+  }
+
+  fn photon(d: &mut Directive) -> Result<DensityEstimation, Error> {
+    let mut density_estimation = DensityEstimation::default();
+    while let Some(key) = d.next_word() {
+      match key.as_str() {
+        "count" => density_estimation.photons = d.usize("photon count")?,
+        "nearest" => density_estimation.nearest = d.usize("nearest photon count")?,
+        "alpha" => density_estimation.alpha = d.positive_float("alpha")?,
+        _ => return Err(d.unknown("parameter", &key)),
+      }
+    }
+    if density_estimation.alpha > 1.0 {
+      return Err(d.error("photon `alpha` must not be greater than 1"));
+    }
+    Ok(density_estimation)
+  }
+
+  fn tonemap(d: &mut Directive) -> Result<TonemapKind, Error> {
+    let kind = d.word("tonemap")?;
+    let gamma = if kind == "gamma" {
+      d.maybe_float()
+    } else {
+      None
+    };
+    TonemapKind::from_name(&kind, gamma).ok_or_else(|| match gamma {
+      Some(gamma) => d.error(format!("gamma must be positive but found {}", gamma)),
+      None => d.unknown("tonemap", &kind),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn unsupported_material_parameter_test() {
+    // マテリアルの種類が使わないパラメータは未知の指定と同じくエラーになる
+    for &(line, key) in &[
+      ("lambertian albedo 0.8 0.8 0.8 ior 1.5", "`ior`"),
+      (
+        "ideal_conductor metal Au reflectance 1 1 1",
+        "`reflectance`",
+      ),
+      (
+        "mix a white b white weight 0.5 roughness 0.1",
+        "`roughness`",
+      ),
+      ("coated base white albedo 0.5 0.5 0.5", "`albedo`"),
+    ] {
+      let source = format!(
+        "material white lambertian albedo 0.8 0.8 0.8\nmaterial m {}\n",
+        line
+      );
+      let error = Scene::parse(&source, Path::new(".")).err().unwrap();
+      assert_eq!(error.line, Some(2));
+      assert!(error.message.contains(key), "{}", error.message);
+    }
+  }
+
+  #[test]
+  fn principled_range_test() {
+    // 他のパラメータと同じく [0, 1] の範囲外はエラーになる
+    for &value in &["-0.1", "1.5"] {
+      let source = format!("material m principled clearcoat_roughness {}\n", value);
+      let error = Scene::parse(&source, Path::new(".")).err().unwrap();
+      assert!(error.message.contains("clearcoat roughness"), "{}", error.message);
+    }
+  }
+
+  #[test]
+  fn depth_test() {
+    // `--min-depth 0` と同じく最初の反射からロシアンルーレットを行う指定を受け付ける
+    let mut d = Directive::parse("depth min 0 max 8").unwrap().remove(0);
+    let russian_roulette = Builder::depth(&mut d).unwrap();
+    assert_eq!(russian_roulette.min_depth, 0);
+    assert_eq!(russian_roulette.max_depth, Some(8));
+    let mut d = Directive::parse("depth min -1").unwrap().remove(0);
+    assert!(Builder::depth(&mut d).is_err());
+  }
+}
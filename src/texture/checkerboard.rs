@@ -0,0 +1,52 @@
+use super::Texture;
+use std::ops::{Add, Mul};
+
+/**
+ * 市松模様のテクスチャ
+ *
+ * テクスチャ座標の1辺を scale 個のマスに分けて a と b を交互に並べる
+ */
+pub struct Checkerboard<T> {
+  pub a: T,
+  pub b: T,
+  pub scale: f32,
+}
+
+impl<T> Texture<T> for Checkerboard<T>
+where
+  T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+{
+  fn value(&self, uv: (f32, f32)) -> T {
+    let i = (uv.0 * self.scale).floor() as i64;
+    let j = (uv.1 * self.scale).floor() as i64;
+    if (i + j) % 2 == 0 {
+      self.a
+    } else {
+      self.b
+    }
+  }
+
+  fn mean(&self) -> T {
+    (self.a + self.b) * 0.5
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn checkerboard_test() {
+    let t = Checkerboard {
+      a: 1.0,
+      b: 0.0,
+      scale: 4.0,
+    };
+    assert_eq!(t.value((0.1, 0.1)), 1.0);
+    assert_eq!(t.value((0.3, 0.1)), 0.0);
+    assert_eq!(t.value((0.3, 0.3)), 1.0);
+    // 負の座標でも交互になる
+    assert_eq!(t.value((-0.1, 0.1)), 0.0);
+    assert_eq!(t.mean(), 0.5);
+  }
+}
@@ -0,0 +1,27 @@
+use super::Texture;
+
+/**
+ * 一様な値のテクスチャ
+ */
+pub struct Constant<T> {
+  pub value: T,
+}
+
+impl<T> Constant<T> {
+  pub fn new(value: T) -> Self {
+    Constant { value: value }
+  }
+}
+
+impl<T> Texture<T> for Constant<T>
+where
+  T: Copy,
+{
+  fn value(&self, _uv: (f32, f32)) -> T {
+    self.value
+  }
+
+  fn mean(&self) -> T {
+    self.value
+  }
+}
@@ -0,0 +1,205 @@
+use super::Texture;
+use math::*;
+use std::path::Path;
+
+/**
+ * テクスチャ座標が [0, 1) の外に出たときの扱い
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wrap {
+  // 繰り返す
+  Repeat,
+  // 端の画素を延ばす
+  Clamp,
+  // 折り返して繰り返す
+  Mirror,
+}
+
+impl Wrap {
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "repeat" => Some(Wrap::Repeat),
+      "clamp" => Some(Wrap::Clamp),
+      "mirror" => Some(Wrap::Mirror),
+      _ => None,
+    }
+  }
+
+  /**
+   * 範囲外の画素の添字 -> [0, size) の添字
+   */
+  fn index(&self, i: i64, size: usize) -> usize {
+    let n = size as i64;
+    let i = match *self {
+      Wrap::Repeat => i.rem_euclid(n),
+      Wrap::Clamp => i.clamp(0, n - 1),
+      Wrap::Mirror => {
+        let i = i.rem_euclid(2 * n);
+        if i < n {
+          i
+        } else {
+          2 * n - 1 - i
+        }
+      }
+    };
+    i as usize
+  }
+}
+
+/**
+ * 画像のテクスチャ
+ *
+ * テクスチャ座標の原点は画像の左下 (OBJの vt と同じ) で、画素の値をバイリニア補間する
+ */
+pub struct ImageTexture {
+  width: usize,
+  height: usize,
+  // Row-major order (上の行から)
+  data: Vec<Vector3>,
+  wrap: Wrap,
+  mean: Vector3,
+}
+
+impl ImageTexture {
+  pub fn new(width: usize, height: usize, data: Vec<Vector3>, wrap: Wrap) -> Self {
+    debug_assert!(width > 0 && height > 0);
+    debug_assert_eq!(data.len(), width * height);
+    let mean = data.iter().fold(Vector3::zero(), |sum, &v| sum + v) / data.len() as f32;
+    ImageTexture {
+      width: width,
+      height: height,
+      data: data,
+      wrap: wrap,
+      mean: mean,
+    }
+  }
+
+  /**
+   * PNGなどの8bitの画像を読み込む
+   *
+   * 色のテクスチャ (srgb) はガンマ2.2で線形な値に戻し、
+   * ラフネスなどの数値のテクスチャは [0, 1] にそのまま対応させる
+   */
+  pub fn load(path: &Path, wrap: Wrap, srgb: bool) -> Result<Self, String> {
+    let image = image::open(path).map_err(|e| e.to_string())?.to_rgb8();
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+      return Err("empty image".to_string());
+    }
+    let data = image
+      .pixels()
+      .map(|p| {
+        let v = Vector3::new(p[0] as f32, p[1] as f32, p[2] as f32) / 255.0;
+        if srgb {
+          v.map(|c| c.powf(2.2))
+        } else {
+          v
+        }
+      })
+      .collect::<Vec<_>>();
+    Ok(ImageTexture::new(
+      width as usize,
+      height as usize,
+      data,
+      wrap,
+    ))
+  }
+
+  /**
+   * 画像の大きさ (幅, 高さ)
+   */
+  pub fn size(&self) -> (usize, usize) {
+    (self.width, self.height)
+  }
+
+  /**
+   * 全ての画素に係数を掛ける
+   */
+  pub fn scale(mut self, scale: Vector3) -> Self {
+    for v in self.data.iter_mut() {
+      *v = *v * scale;
+    }
+    self.mean = self.mean * scale;
+    self
+  }
+
+  fn texel(&self, i: i64, j: i64) -> Vector3 {
+    let i = self.wrap.index(i, self.width);
+    let j = self.wrap.index(j, self.height);
+    self.data[j * self.width + i]
+  }
+
+  fn bilinear(&self, uv: (f32, f32)) -> Vector3 {
+    // 画素の中心が整数になる座標 (画像の上端が v = 1)
+    let x = uv.0 * self.width as f32 - 0.5;
+    let y = (1.0 - uv.1) * self.height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (dx, dy) = (x - x0, y - y0);
+    let (i, j) = (x0 as i64, y0 as i64);
+    self.texel(i, j) * ((1.0 - dx) * (1.0 - dy))
+      + self.texel(i + 1, j) * (dx * (1.0 - dy))
+      + self.texel(i, j + 1) * ((1.0 - dx) * dy)
+      + self.texel(i + 1, j + 1) * (dx * dy)
+  }
+}
+
+impl Texture<Vector3> for ImageTexture {
+  fn value(&self, uv: (f32, f32)) -> Vector3 {
+    self.bilinear(uv)
+  }
+
+  fn mean(&self) -> Vector3 {
+    self.mean
+  }
+}
+
+/**
+ * グレースケールの値として使う場合はRGBの平均
+ */
+impl Texture<f32> for ImageTexture {
+  fn value(&self, uv: (f32, f32)) -> f32 {
+    let v = self.bilinear(uv);
+    (v.x + v.y + v.z) / 3.0
+  }
+
+  fn mean(&self) -> f32 {
+    (self.mean.x + self.mean.y + self.mean.z) / 3.0
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn texture(wrap: Wrap) -> ImageTexture {
+    // 上の行が 0, 1 で下の行が 2, 3
+    let data = (0..4).map(|k| Vector3::fill(k as f32)).collect();
+    ImageTexture::new(2, 2, data, wrap)
+  }
+
+  #[test]
+  fn bilinear_test() {
+    let t = texture(Wrap::Clamp);
+    // 画素の中心では画素の値
+    assert!(t.bilinear((0.25, 0.75)).x.approx_eq(0.0));
+    assert!(t.bilinear((0.75, 0.25)).x.approx_eq(3.0));
+    // 中央は4画素の平均
+    assert!(t.bilinear((0.5, 0.5)).x.approx_eq(1.5));
+    // 端の外側は端の画素のまま
+    assert!(t.bilinear((0.0, 0.75)).x.approx_eq(0.0));
+  }
+
+  #[test]
+  fn wrap_test() {
+    assert_eq!(Wrap::Repeat.index(-1, 4), 3);
+    assert_eq!(Wrap::Repeat.index(5, 4), 1);
+    assert_eq!(Wrap::Clamp.index(-3, 4), 0);
+    assert_eq!(Wrap::Clamp.index(7, 4), 3);
+    assert_eq!(Wrap::Mirror.index(-1, 4), 0);
+    assert_eq!(Wrap::Mirror.index(4, 4), 3);
+    assert_eq!(Wrap::Mirror.index(9, 4), 1);
+    // 繰り返しでは左端の外側が右端の画素と補間される
+    let t = texture(Wrap::Repeat);
+    assert!(t.bilinear((0.0, 0.75)).x.approx_eq(0.5));
+  }
+}
@@ -0,0 +1,11 @@
+mod checkerboard;
+mod constant;
+mod image_texture;
+mod normal_map;
+mod texture;
+
+pub use self::checkerboard::*;
+pub use self::constant::*;
+pub use self::image_texture::*;
+pub use self::normal_map::*;
+pub use self::texture::*;
@@ -0,0 +1,85 @@
+use super::{ImageTexture, Texture};
+use math::*;
+use util::Finite;
+
+/**
+ * 表面の法線を揺らすテクスチャ
+ *
+ * 法線 n と、n に直交する接線 t (テクスチャ座標の u が増える向き) の接空間で法線を求める
+ * 従法線は b = n × t (v が増える向き)
+ */
+pub enum NormalMap {
+  // 接空間の法線をRGBで表した画像 ([0, 1] -> [-1, 1])
+  Normal(ImageTexture),
+  // 高さの画像 (グレースケール) と、テクスチャ座標の1あたりの高さの倍率
+  Bump { height: ImageTexture, scale: f32 },
+}
+
+impl NormalMap {
+  /**
+   * テクスチャ座標uvでの揺らした法線 (正規化済み)
+   */
+  pub fn apply(&self, uv: (f32, f32), n: Vector3, t: Vector3) -> Vector3 {
+    let b = n.cross(t);
+    let perturbed = match *self {
+      NormalMap::Normal(ref texture) => {
+        let c: Vector3 = texture.value(uv);
+        let local = c * 2.0 - Vector3::fill(1.0);
+        t * local.x + b * local.y + n * local.z
+      }
+      NormalMap::Bump { ref height, scale } => {
+        // 1画素分の中心差分で高さの勾配を求める
+        let (width, h) = height.size();
+        let du = 1.0 / width as f32;
+        let dv = 1.0 / h as f32;
+        let value = |u: f32, v: f32| -> f32 { height.value((u, v)) };
+        let dhdu = (value(uv.0 + du, uv.1) - value(uv.0 - du, uv.1)) / (2.0 * du);
+        let dhdv = (value(uv.0, uv.1 + dv) - value(uv.0, uv.1 - dv)) / (2.0 * dv);
+        n - (t * dhdu + b * dhdv) * scale
+      }
+    };
+    // 裏返った法線は使わない
+    if perturbed.dot(n) <= 0.0 || !perturbed.is_finite() {
+      n
+    } else {
+      perturbed.normalize()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use texture::Wrap;
+
+  #[test]
+  fn apply_test() {
+    let n = Vector3::new(0.0, 1.0, 0.0);
+    let t = Vector3::new(1.0, 0.0, 0.0);
+    // 平らな法線マップと一様な高さでは法線は変わらない
+    let flat = NormalMap::Normal(ImageTexture::new(
+      1,
+      1,
+      vec![Vector3::new(0.5, 0.5, 1.0)],
+      Wrap::Repeat,
+    ));
+    assert!(flat.apply((0.3, 0.7), n, t).approx_eq(n));
+    let bump = NormalMap::Bump {
+      height: ImageTexture::new(2, 2, vec![Vector3::fill(0.5); 4], Wrap::Repeat),
+      scale: 1.0,
+    };
+    assert!(bump.apply((0.3, 0.7), n, t).approx_eq(n));
+    // u の向きに高くなる斜面では法線が -u の向きに傾く
+    let slope = NormalMap::Bump {
+      height: ImageTexture::new(
+        4,
+        1,
+        (0..4).map(|i| Vector3::fill(i as f32 / 4.0)).collect(),
+        Wrap::Clamp,
+      ),
+      scale: 0.1,
+    };
+    let perturbed = slope.apply((0.5, 0.5), n, t);
+    assert!(perturbed.x < 0.0 && perturbed.y > 0.0, "{}", perturbed);
+  }
+}
@@ -0,0 +1,11 @@
+/**
+ * テクスチャ座標 uv から値を引くテクスチャ
+ *
+ * マテリアルのアルベド (Vector3), ラフネス (f32), 放射輝度 (Vector3) に使う
+ */
+pub trait Texture<T> {
+  // テクスチャ座標 -> 値
+  fn value(&self, uv: (f32, f32)) -> T;
+  // テクスチャ全体の平均 (光源の選択など位置によらない代表値)
+  fn mean(&self) -> T;
+}